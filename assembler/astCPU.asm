@@ -29,6 +29,9 @@
     SUB {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x1 @ 0`12
     MUL {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x2 @ 0`12
     NIG {DR: reg} - {SR1: reg}             => 0x1 @ SR1`4 @  0x0  @ DR`4 @ 0x3 @ 0`12
+    MULIS{DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x0 @ 1`2 @ 0`10
+    DIVIU{DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x1 @ 1`2 @ 0`10
+    DIVIS{DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x2 @ 1`2 @ 0`10
     ; Logial Operations
     AND {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x4 @ 0`12
     OR  {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x5 @ 0`12
@@ -42,25 +45,66 @@
     ARS {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0xC @ 0`12
     RRS {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0xD @ 0`12
     RLS {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0xE @ 0`12
+    ; Floating Point
+    ADF  {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x0 @ 2`2 @ 0`10
+    SBF  {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x1 @ 2`2 @ 0`10
+    MULF {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x2 @ 2`2 @ 0`10
+    DIVF {DR: reg} - {SR1: reg}, {SR2: reg} => 0x1 @ SR1`4 @ SR2`4 @ DR`4 @ 0x3 @ 2`2 @ 0`10
+    NF   {DR: reg} - {SR1: reg}             => 0x1 @ SR1`4 @  0x0  @ DR`4 @ 0x4 @ 2`2 @ 0`10
+    UITF {DR: reg} - {SR1: reg}             => 0x1 @ SR1`4 @  0x0  @ DR`4 @ 0x5 @ 2`2 @ 0`10
+    SITF {DR: reg} - {SR1: reg}             => 0x1 @ SR1`4 @  0x0  @ DR`4 @ 0x6 @ 2`2 @ 0`10
+    FTI  {DR: reg} - {SR1: reg}             => 0x1 @ SR1`4 @  0x0  @ DR`4 @ 0x7 @ 2`2 @ 0`10
+    FTU  {DR: reg} - {SR1: reg}             => 0x1 @ SR1`4 @  0x0  @ DR`4 @ 0x8 @ 2`2 @ 0`10
     ; Compare
     CMP-GT {SR1: reg}, {SR2: reg}          => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x0 @ 0`12
     CMP-EQ {SR1: reg}, {SR2: reg}          => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x1 @ 0`12
     CMP-LT {SR1: reg}, {SR2: reg}          => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x2 @ 0`12
     CMP-GE {SR1: reg}, {SR2: reg}          => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x3 @ 0`12
     CMP-LE {SR1: reg}, {SR2: reg}          => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x4 @ 0`12
+    ; Signed Compare
+    CGTSI {SR1: reg}, {SR2: reg}           => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x0 @ 1`2 @ 0`10
+    CEQSI {SR1: reg}, {SR2: reg}           => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x1 @ 1`2 @ 0`10
+    CLTSI {SR1: reg}, {SR2: reg}           => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x2 @ 1`2 @ 0`10
+    CGESI {SR1: reg}, {SR2: reg}           => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x3 @ 1`2 @ 0`10
+    CLESI {SR1: reg}, {SR2: reg}           => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x4 @ 1`2 @ 0`10
+    ; Floating Point Compare
+    CGTF {SR1: reg}, {SR2: reg}            => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x0 @ 2`2 @ 0`10
+    CEQF {SR1: reg}, {SR2: reg}            => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x1 @ 2`2 @ 0`10
+    CLTF {SR1: reg}, {SR2: reg}            => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x2 @ 2`2 @ 0`10
+    CGEF {SR1: reg}, {SR2: reg}            => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x3 @ 2`2 @ 0`10
+    CLEF {SR1: reg}, {SR2: reg}            => 0x2 @ SR1`4 @ SR2`4 @  0x0  @ 0x4 @ 2`2 @ 0`10
     ; JUMP
-    JMP       {SR: reg}                    => 0x3 @  0x0 @ SR`4 @ 0`20
-    JMP       {value}                      => 0x3 @  1`1 @ 0`3 @ value`24
-    JP-Cr     {SR: reg}                    => 0x3 @  0x1 @ SR`4 @ 0`20
-    JP-Cr     {value}                      => 0x3 @  1`1 @ 1`3 @ value`24
-    JP-NCr    {SR: reg}                    => 0x3 @  0x2 @ SR`4 @ 0`20
-    JP-NCr    {value}                      => 0x3 @  1`1 @ 2`3 @ value`24
-    JP-CMP    {SR: reg}                    => 0x3 @  0x3 @ SR`4 @ 0`20
-    JP-CMP    {value}                      => 0x3 @  1`1 @ 3`3 @ value`24
-    JP-NCMP   {SR: reg}                    => 0x3 @  0x4 @ SR`4 @ 0`20
-    JP-NCMP   {value}                      => 0x3 @  1`1 @ 4`3 @ value`24
-    JP-Zr     {SR: reg}                    => 0x3 @  0x5 @ SR`4 @ 0`20
-    JP-Zr     {value}                      => 0x3 @  1`1 @ 5`3 @ value`24
+    ; The condition is 4 bits, its top bit sits in bit 23 (immediate) or bit 19 (register)
+    JMP       {SR: reg}                    => 0x3 @ 0`1 @ 0`3 @ SR`4 @ 0`1 @ 0`19
+    JMP       {value}                      => 0x3 @ 1`1 @ 0`3 @ 0`1 @ value`23
+    JP-Cr     {SR: reg}                    => 0x3 @ 0`1 @ 1`3 @ SR`4 @ 0`1 @ 0`19
+    JP-Cr     {value}                      => 0x3 @ 1`1 @ 1`3 @ 0`1 @ value`23
+    JP-NCr    {SR: reg}                    => 0x3 @ 0`1 @ 2`3 @ SR`4 @ 0`1 @ 0`19
+    JP-NCr    {value}                      => 0x3 @ 1`1 @ 2`3 @ 0`1 @ value`23
+    JP-CMP    {SR: reg}                    => 0x3 @ 0`1 @ 3`3 @ SR`4 @ 0`1 @ 0`19
+    JP-CMP    {value}                      => 0x3 @ 1`1 @ 3`3 @ 0`1 @ value`23
+    JP-NCMP   {SR: reg}                    => 0x3 @ 0`1 @ 4`3 @ SR`4 @ 0`1 @ 0`19
+    JP-NCMP   {value}                      => 0x3 @ 1`1 @ 4`3 @ 0`1 @ value`23
+    JP-Zr     {SR: reg}                    => 0x3 @ 0`1 @ 5`3 @ SR`4 @ 0`1 @ 0`19
+    JP-Zr     {value}                      => 0x3 @ 1`1 @ 5`3 @ 0`1 @ value`23
+    JP-NZr    {SR: reg}                    => 0x3 @ 0`1 @ 6`3 @ SR`4 @ 0`1 @ 0`19
+    JP-NZr    {value}                      => 0x3 @ 1`1 @ 6`3 @ 0`1 @ value`23
+    JP-Or     {SR: reg}                    => 0x3 @ 0`1 @ 0`3 @ SR`4 @ 1`1 @ 0`19
+    JP-Or     {value}                      => 0x3 @ 1`1 @ 0`3 @ 1`1 @ value`23
+    JP-NOr    {SR: reg}                    => 0x3 @ 0`1 @ 1`3 @ SR`4 @ 1`1 @ 0`19
+    JP-NOr    {value}                      => 0x3 @ 1`1 @ 1`3 @ 1`1 @ value`23
+    JP-Nr     {SR: reg}                    => 0x3 @ 0`1 @ 2`3 @ SR`4 @ 1`1 @ 0`19
+    JP-Nr     {value}                      => 0x3 @ 1`1 @ 2`3 @ 1`1 @ value`23
+    JP-NNr    {SR: reg}                    => 0x3 @ 0`1 @ 3`3 @ SR`4 @ 1`1 @ 0`19
+    JP-NNr    {value}                      => 0x3 @ 1`1 @ 3`3 @ 1`1 @ value`23
+    JP-GTS    {SR: reg}                    => 0x3 @ 0`1 @ 4`3 @ SR`4 @ 1`1 @ 0`19
+    JP-GTS    {value}                      => 0x3 @ 1`1 @ 4`3 @ 1`1 @ value`23
+    JP-LES    {SR: reg}                    => 0x3 @ 0`1 @ 5`3 @ SR`4 @ 1`1 @ 0`19
+    JP-LES    {value}                      => 0x3 @ 1`1 @ 5`3 @ 1`1 @ value`23
+    JP-GES    {SR: reg}                    => 0x3 @ 0`1 @ 6`3 @ SR`4 @ 1`1 @ 0`19
+    JP-GES    {value}                      => 0x3 @ 1`1 @ 6`3 @ 1`1 @ value`23
+    JP-LTS    {SR: reg}                    => 0x3 @ 0`1 @ 7`3 @ SR`4 @ 1`1 @ 0`19
+    JP-LTS    {value}                      => 0x3 @ 1`1 @ 7`3 @ 1`1 @ value`23
     ; Memory
     LD {DR: reg} - {adress}                => 0x4 @ DR`4 @ adress`24
     LD {DR: reg} - {SR: reg}               => 0x5 @ 0x0 @ SR`4 @ DR`4 @ 0`16
@@ -78,6 +122,18 @@
     OPD2W - {SR: reg}                      => 0xA @ SR`4 @ 0x000 @ 0`3 @ 1`1 @ 0`1 @ 0`7
     ; Stack
     PUSH {SR: reg}                         => 0xB @ SR`4 @ 0x000 @ 0`3 @ 0`1 @ 0`1 @ 0`7
+    POP {DR: reg}                          => 0xC @ 0x0 @ 1`2 @ 0`2 @ DR`4 @ 0`16
+    TOP {DR: reg}                          => 0xC @ 0x0 @ 2`2 @ 0`2 @ DR`4 @ 0`16
+    CLR {DR: reg}                          => 0xC @ 0x0 @ 3`2 @ 0`2 @ DR`4 @ 0`16
+    ; Function
+    CALL {adress}                          => 0xD @ 0x0 @ adress`24
+    RET                                    => 0xD @ 0x2 @ 0`24
+    ; System
+    SYS {value}                            => 0xE @ 0x0 @ value`24
+    RETI                                   => 0xE @ 0x8 @ 0`24
+    STRETI {SR: reg}                       => 0xE @ 0xC @ SR`4 @ 0`20
+    EI                                     => 0xE @ 0x6 @ 0`24
+    DI                                     => 0xE @ 0x4 @ 0`24
     
     HLT                                    => 0xffffffff
 }
@@ -0,0 +1,1326 @@
+use crate::assembler::expression::{Expr, ExprKind, Value};
+use crate::assembler::lexer::{Span, Token, TokenKind};
+use crate::assembler::rules::{Argument, Match, Output, RuleSet};
+use crate::object::{self, Object, Target};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+mod expression;
+mod lexer;
+mod rules;
+
+/// The instruction set, as used with customasm.
+pub const CPU_DEFINITION: &str = include_str!("../../../assembler/astCPU.asm");
+/// Name [`CPU_DEFINITION`] has in error messages.
+pub const CPU_DEFINITION_NAME: &str = "astCPU.asm";
+/// The only `#bits` supported, as the emulator loads 32-bit words.
+const WORD_BITS: usize = 32;
+/// How deep `asm` rules may expand into other `asm` rules.
+const MAX_EXPANSION_DEPTH: usize = 64;
+/// Layout passes before giving up on constants that refer to each other.
+const MAX_PASSES: usize = 16;
+
+/// An assembly error at `line` and `column` (both 1-based) of `file`. Both
+/// are 0 when the file could not be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssembleError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            return write!(f, "{}: {}", self.file, self.message);
+        }
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// An error before the source file is known, see [`AssembleError`].
+#[derive(Debug)]
+struct Error {
+    span: Span,
+    message: String,
+}
+
+impl Error {
+    fn new(span: Span, message: impl Into<String>) -> Error {
+        Error {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+/// A `#bankdef`: where labels start and where the words go in the image.
+/// Linker scripts use the same blocks to place sections.
+pub(crate) struct Bank {
+    pub(crate) name: String,
+    /// Address of the first word, for labels.
+    pub(crate) addr: i128,
+    /// Capacity in words.
+    pub(crate) size: Option<usize>,
+    /// Bit offset of the first word in the image; banks without one can
+    /// only hold labels.
+    pub(crate) outp: Option<usize>,
+    /// Pad the bank to `size` in the image.
+    pub(crate) fill: bool,
+    /// `#sections`, in linker scripts: the object sections placed in the
+    /// bank, in order.
+    pub(crate) sections: Vec<String>,
+    span: Span,
+}
+
+enum Statement {
+    Label {
+        name: String,
+        span: Span,
+    },
+    Instruction {
+        output: Expr,
+        arguments: Vec<(String, Argument)>,
+        span: Span,
+    },
+    /// `#d` or `#dN`, which gives each value `N` bits.
+    Data {
+        width: Option<u32>,
+        values: Vec<Expr>,
+    },
+    Bank {
+        index: usize,
+    },
+    Const {
+        name: String,
+        value: Expr,
+        span: Span,
+    },
+    Addr {
+        address: Expr,
+        span: Span,
+    },
+}
+
+/// Contents of a bank after a pass.
+struct Placed {
+    /// Next bit to write.
+    position: usize,
+    words: Vec<u32>,
+    /// Addresses left for the linker, in objects.
+    relocations: Vec<object::Relocation>,
+}
+
+/// A program ready for [`layout`].
+struct Program {
+    banks: Vec<Bank>,
+    statements: Vec<Statement>,
+    /// Every label and constant it defines.
+    names: HashSet<String>,
+    /// Assembling an object: labels are relative to their bank, which
+    /// becomes a section, and unknown names are other objects' symbols.
+    object: bool,
+}
+
+/// What a pass of [`layout`] found.
+struct Layout {
+    symbols: HashMap<String, Value>,
+    placed: Vec<Placed>,
+    /// Labels with their bank and word within it.
+    labels: Vec<(String, usize, usize)>,
+}
+
+/// A source file and where it is, for the relative paths it includes.
+#[derive(Clone)]
+struct Source {
+    name: String,
+    text: String,
+    path: Option<PathBuf>,
+}
+
+/// Assembler for the customasm subset `assembler/astCPU.asm` and the
+/// example programs use: `#ruledef` and `#subruledef` rules, including
+/// rules that expand to `asm { ... }` lines, labels and `.local` labels,
+/// `#d` data, `#const`, `#include`, `#bankdef` / `#bank` and `#addr`.
+///
+/// Sources are assembled as one program in the order they were added,
+/// like the files given to customasm. [`Assembler::new`] starts with
+/// [`CPU_DEFINITION`]. Labels and constants are resolved over several
+/// passes, so they may be used before they are defined.
+///
+/// [`Assembler::assemble_object`] makes a relocatable [`Object`] for
+/// [`crate::linker::link`] instead of an image.
+pub struct Assembler {
+    sources: Vec<Source>,
+}
+
+impl Assembler {
+    /// An assembler for the SS32 instruction set.
+    pub fn new() -> Assembler {
+        let mut assembler = Assembler::empty();
+        assembler.add_source(CPU_DEFINITION_NAME, CPU_DEFINITION);
+        return assembler;
+    }
+    /// An assembler without rules, for sources that bring their own.
+    pub fn empty() -> Assembler {
+        Assembler {
+            sources: Vec::new(),
+        }
+    }
+    /// Adds source text; its `#include`s are relative to the working
+    /// directory.
+    pub fn add_source(&mut self, name: impl Into<String>, text: impl Into<String>) {
+        self.sources.push(Source {
+            name: name.into(),
+            text: text.into(),
+            path: None,
+        });
+    }
+    /// Adds a source file; its `#include`s are relative to its directory.
+    pub fn add_file(&mut self, path: &Path) -> Result<(), AssembleError> {
+        let text = fs::read_to_string(path).map_err(|error| AssembleError {
+            file: path.display().to_string(),
+            line: 0,
+            column: 0,
+            message: error.to_string(),
+        })?;
+        self.sources.push(Source {
+            name: path.display().to_string(),
+            text,
+            path: Some(path.to_path_buf()),
+        });
+        return Ok(());
+    }
+    /// Assembles the sources into an image of words starting at address 0.
+    pub fn assemble(&self) -> Result<Vec<u32>, AssembleError> {
+        let mut sources = self.sources.clone();
+        return run(&mut sources, false)
+            .and_then(|(program, layout)| build_image(&program.banks, &layout.placed))
+            .map_err(|error| locate(&sources, error));
+    }
+    /// Assembles the sources into an object with a section per bank.
+    /// Their `#addr` and `#outp` are left to the linker script, so
+    /// `#addr` directives are not allowed. Names no source defines are
+    /// taken to be global labels of other objects.
+    pub fn assemble_object(&self) -> Result<Object, AssembleError> {
+        let mut sources = self.sources.clone();
+        return run(&mut sources, true)
+            .map(|(program, layout)| build_object(&program.banks, layout))
+            .map_err(|error| locate(&sources, error));
+    }
+}
+
+/// Names the file of an error.
+fn locate(sources: &[Source], error: Error) -> AssembleError {
+    return AssembleError {
+        file: sources[error.span.source].name.clone(),
+        line: error.span.line,
+        column: error.span.column,
+        message: error.message,
+    };
+}
+
+/// Assembles `sources`, adding the files they include.
+fn run(sources: &mut Vec<Source>, object: bool) -> Result<(Program, Layout), Error> {
+    let mut tokens = Vec::new();
+    for index in 0..sources.len() {
+        let mut including = Vec::new();
+        if let Some(path) = sources[index]
+            .path
+            .as_ref()
+            .and_then(|path| fs::canonicalize(path).ok())
+        {
+            including.push(path);
+        }
+        tokens.extend(load(sources, index, &mut including)?);
+    }
+    let (rules, tokens) = collect_rules(tokens)?;
+    let mut parser = StatementParser::new(&rules, span_at(&tokens, 0));
+    parser.parse(&tokens)?;
+    let StatementParser {
+        banks, statements, ..
+    } = parser;
+    let names = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Label { name, .. } | Statement::Const { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let program = Program {
+        banks,
+        statements,
+        names,
+        object,
+    };
+    // Sizes never depend on values, so every pass places the labels at
+    // the same addresses; constants built on later ones take more passes
+    let mut symbols = HashMap::new();
+    for _ in 0..MAX_PASSES {
+        let next = layout(&program, &symbols, false)?.symbols;
+        if next == symbols {
+            break;
+        }
+        symbols = next;
+    }
+    let layout = layout(&program, &symbols, true)?;
+    return Ok((program, layout));
+}
+
+/// Lexes source `index`, splicing in the files it `#include`s. `including`
+/// holds the files being included, to catch cycles.
+fn load(
+    sources: &mut Vec<Source>,
+    index: usize,
+    including: &mut Vec<PathBuf>,
+) -> Result<Vec<Token>, Error> {
+    let tokens = lexer::lex(index, &sources[index].text)?;
+    let mut spliced = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        if !matches!(&token.kind, TokenKind::Directive(name) if name == "include") {
+            spliced.push(token.clone());
+            i += 1;
+            continue;
+        }
+        let Some(TokenKind::String(name)) = tokens.get(i + 1).map(|token| &token.kind) else {
+            return Err(Error::new(
+                token.span,
+                "#include needs a file name in quotes",
+            ));
+        };
+        if tokens
+            .get(i + 2)
+            .is_some_and(|token| token.kind != TokenKind::Newline)
+        {
+            return Err(Error::new(
+                tokens[i + 2].span,
+                "expected the end of the line",
+            ));
+        }
+        let path = match sources[index].path.as_ref().and_then(|path| path.parent()) {
+            Some(directory) => directory.join(name),
+            None => PathBuf::from(name),
+        };
+        let unreadable = |error: std::io::Error| {
+            Error::new(
+                token.span,
+                format!("cannot include `{}`: {}", path.display(), error),
+            )
+        };
+        let canonical = fs::canonicalize(&path).map_err(unreadable)?;
+        if including.contains(&canonical) {
+            return Err(Error::new(
+                token.span,
+                format!("`{}` includes itself", path.display()),
+            ));
+        }
+        let text = fs::read_to_string(&path).map_err(unreadable)?;
+        sources.push(Source {
+            name: path.display().to_string(),
+            text,
+            path: Some(path),
+        });
+        including.push(canonical);
+        spliced.extend(load(sources, sources.len() - 1, including)?);
+        including.pop();
+        i += 2;
+    }
+    return Ok(spliced);
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Assembler::new()
+    }
+}
+
+/// Assembles one program file with the SS32 instruction set.
+pub fn assemble_file(path: &Path) -> Result<Vec<u32>, AssembleError> {
+    let mut assembler = Assembler::new();
+    assembler.add_file(path)?;
+    return assembler.assemble();
+}
+
+/// Parses a linker script, which holds only `#bankdef` blocks. Each may
+/// list the object sections it holds with `#sections a, b`.
+pub(crate) fn parse_linker_script(name: &str, text: &str) -> Result<Vec<Bank>, AssembleError> {
+    return parse_banks(text).map_err(|error| AssembleError {
+        file: name.to_string(),
+        line: error.span.line,
+        column: error.span.column,
+        message: error.message,
+    });
+}
+
+fn parse_banks(text: &str) -> Result<Vec<Bank>, Error> {
+    let tokens = lexer::lex(0, text)?;
+    let mut banks: Vec<Bank> = Vec::new();
+    let mut i = 0;
+    while let Some(token) = tokens.get(i) {
+        match &token.kind {
+            TokenKind::Newline => i += 1,
+            TokenKind::Directive(directive) if directive == "bankdef" => {
+                let (bank, next) = parse_bankdef(&tokens, i + 1, token.span, true)?;
+                if banks.iter().any(|other| other.name == bank.name) {
+                    return Err(Error::new(
+                        token.span,
+                        format!("bank `{}` is already defined", bank.name),
+                    ));
+                }
+                banks.push(bank);
+                i = next;
+            }
+            _ => {
+                return Err(Error::new(
+                    token.span,
+                    "a linker script only holds #bankdef blocks",
+                ))
+            }
+        }
+    }
+    if banks.is_empty() {
+        return Err(Error::new(
+            Span {
+                source: 0,
+                line: 1,
+                column: 1,
+            },
+            "a linker script needs at least one #bankdef",
+        ));
+    }
+    return Ok(banks);
+}
+
+/// One word per line as 8 hex digits, the format the emulator loads.
+pub fn to_hex(words: &[u32]) -> String {
+    let mut hex = String::with_capacity(words.len() * 9);
+    for word in words {
+        hex.push_str(&format!("{:08x}\n", word));
+    }
+    return hex;
+}
+
+/// Takes the `#ruledef` and `#subruledef` blocks out of `tokens`, as rules
+/// apply to the whole program wherever they are defined.
+fn collect_rules(tokens: Vec<Token>) -> Result<(RuleSet, Vec<Token>), Error> {
+    let mut rules = RuleSet::new();
+    let mut rest = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        let subrule = match &token.kind {
+            TokenKind::Directive(name) if name == "ruledef" => false,
+            TokenKind::Directive(name) if name == "subruledef" => true,
+            _ => {
+                rest.push(token.clone());
+                i += 1;
+                continue;
+            }
+        };
+        i += 1;
+        let name = match tokens.get(i).map(|token| &token.kind) {
+            Some(TokenKind::Ident(name)) => {
+                i += 1;
+                Some(name.clone())
+            }
+            _ => None,
+        };
+        let Some(open) = tokens.get(i).filter(|token| token.is_punct('{')) else {
+            return Err(Error::new(
+                token.span,
+                "expected `{` after the rule set name",
+            ));
+        };
+        let (block, used) = rules::parse_block(&tokens[i + 1..], open.span)?;
+        i += 1 + used;
+        if subrule {
+            let Some(name) = name else {
+                return Err(Error::new(token.span, "#subruledef needs a name"));
+            };
+            rules.subrules.entry(name).or_default().extend(block);
+        } else {
+            rules.rules.extend(block);
+        }
+    }
+    return Ok((rules, rest));
+}
+
+/// Index of the token ending the line `tokens[start]` is on.
+fn line_end(tokens: &[Token], start: usize) -> usize {
+    let mut i = start;
+    while tokens
+        .get(i)
+        .is_some_and(|token| token.kind != TokenKind::Newline)
+    {
+        i += 1;
+    }
+    return i;
+}
+
+/// Span of the newline at `index`, or of the last token at the end of the
+/// program.
+fn span_at(tokens: &[Token], index: usize) -> Span {
+    return tokens
+        .get(index)
+        .or(tokens.last())
+        .map(|token| token.span)
+        .expect("there is at least one token");
+}
+
+/// Splits `tokens[start..end]` at top-level commas and parses each part.
+fn parse_list(tokens: &[Token], start: usize, end: usize) -> Result<Vec<Expr>, Error> {
+    let mut values = Vec::new();
+    let mut part = start;
+    let mut depth = 0;
+    for i in start..end {
+        match tokens[i].kind {
+            TokenKind::Punct('(') => depth += 1,
+            TokenKind::Punct(')') => depth -= 1,
+            TokenKind::Punct(',') if depth == 0 => {
+                values.push(expression::parse(&tokens[part..i], tokens[i].span)?);
+                part = i + 1;
+            }
+            _ => {}
+        }
+    }
+    values.push(expression::parse(&tokens[part..end], span_at(tokens, end))?);
+    return Ok(values);
+}
+
+/// Evaluates a field of a `#bankdef`, which cannot use labels.
+fn constant(expr: &Expr) -> Result<i128, Error> {
+    let value = expr.eval(&|name, span| {
+        Err(Error::new(
+            span,
+            format!(
+                "`{}` is not a constant; bank fields cannot use labels",
+                name
+            ),
+        ))
+    })?;
+    return Ok(value.value);
+}
+
+/// Parses a `#bankdef` block from `tokens[start]`, the name after the
+/// directive. Returns the bank and the index after its `}`. `#sections`
+/// is only allowed in a linker `script`.
+fn parse_bankdef(
+    tokens: &[Token],
+    start: usize,
+    directive: Span,
+    script: bool,
+) -> Result<(Bank, usize), Error> {
+    let Some(TokenKind::Ident(name)) = tokens.get(start).map(|token| &token.kind) else {
+        return Err(Error::new(directive, "#bankdef needs a name"));
+    };
+    if !tokens
+        .get(start + 1)
+        .is_some_and(|token| token.is_punct('{'))
+    {
+        return Err(Error::new(directive, "expected `{` after the bank name"));
+    }
+    let mut bank = Bank {
+        name: name.clone(),
+        addr: 0,
+        size: None,
+        outp: None,
+        fill: false,
+        sections: Vec::new(),
+        span: directive,
+    };
+    let mut i = start + 2;
+    loop {
+        let Some(token) = tokens.get(i) else {
+            return Err(Error::new(directive, "unclosed `{`"));
+        };
+        let field = match &token.kind {
+            TokenKind::Newline => {
+                i += 1;
+                continue;
+            }
+            TokenKind::Punct('}') => return Ok((bank, i + 1)),
+            TokenKind::Directive(field) => field.clone(),
+            _ => {
+                return Err(Error::new(
+                    token.span,
+                    "expected a bank field such as #bits",
+                ))
+            }
+        };
+        let end = line_end(tokens, i + 1);
+        if field == "fill" {
+            bank.fill = true;
+            i = end;
+            continue;
+        }
+        if field == "sections" && script {
+            for (index, token) in tokens[i + 1..end].iter().enumerate() {
+                match &token.kind {
+                    TokenKind::Ident(name) if index % 2 == 0 => bank.sections.push(name.clone()),
+                    TokenKind::Punct(',') if index % 2 == 1 => {}
+                    _ => {
+                        return Err(Error::new(
+                            token.span,
+                            "expected section names separated by commas",
+                        ))
+                    }
+                }
+            }
+            i = end;
+            continue;
+        }
+        let value = constant(&expression::parse(
+            &tokens[i + 1..end],
+            span_at(tokens, end),
+        )?)?;
+        let span = token.span;
+        match field.as_str() {
+            "bits" if value == WORD_BITS as i128 => {}
+            "bits" => {
+                return Err(Error::new(
+                    span,
+                    "only #bits 32 is supported, the emulator loads 32-bit words",
+                ))
+            }
+            "addr" => bank.addr = value,
+            "size" => {
+                bank.size = Some(
+                    usize::try_from(value)
+                        .map_err(|_| Error::new(span, "#size cannot be negative"))?,
+                )
+            }
+            "outp" => {
+                let outp = usize::try_from(value)
+                    .ok()
+                    .filter(|outp| outp % WORD_BITS == 0)
+                    .ok_or_else(|| Error::new(span, "#outp must be a multiple of 32 bits"))?;
+                bank.outp = Some(outp);
+            }
+            _ => return Err(Error::new(span, format!("unknown bank field #{}", field))),
+        }
+        i = end;
+    }
+}
+
+/// Turns the tokens left after [`collect_rules`] into statements, matching
+/// each instruction against the rules and assembling the lines of `asm`
+/// rules in its place.
+struct StatementParser<'a> {
+    rules: &'a RuleSet,
+    /// The first bank is the default one, used until a `#bankdef`.
+    banks: Vec<Bank>,
+    statements: Vec<Statement>,
+    /// Names starting with `.` are local to this: the last global label,
+    /// or the `asm` rule expansion being parsed.
+    scope: String,
+    /// Expansions so far, to give each its own scope.
+    expansions: usize,
+    depth: usize,
+}
+
+impl StatementParser<'_> {
+    fn new(rules: &RuleSet, span: Span) -> StatementParser<'_> {
+        StatementParser {
+            rules,
+            banks: vec![Bank {
+                name: "default".to_string(),
+                addr: 0,
+                size: None,
+                outp: Some(0),
+                fill: false,
+                sections: Vec::new(),
+                span,
+            }],
+            statements: Vec::new(),
+            scope: String::new(),
+            expansions: 0,
+            depth: 0,
+        }
+    }
+    /// The full name of a label, prefixing local names with the scope.
+    fn scoped(&self, name: &str) -> String {
+        if name.starts_with('.') {
+            return format!("{}{}", self.scope, name);
+        }
+        return name.to_string();
+    }
+    fn localize(&self, tokens: &[Token]) -> Vec<Token> {
+        return tokens
+            .iter()
+            .map(|token| match &token.kind {
+                TokenKind::Ident(name) if name.starts_with('.') => Token {
+                    kind: TokenKind::Ident(self.scoped(name)),
+                    span: token.span,
+                },
+                _ => token.clone(),
+            })
+            .collect();
+    }
+    fn parse(&mut self, tokens: &[Token]) -> Result<(), Error> {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            let span = token.span;
+            match &token.kind {
+                TokenKind::Newline => {
+                    i += 1;
+                    continue;
+                }
+                TokenKind::Ident(name) if tokens.get(i + 1).is_some_and(|t| t.is_punct(':')) => {
+                    if !name.starts_with('.') {
+                        self.scope = name.clone();
+                    }
+                    self.statements.push(Statement::Label {
+                        name: self.scoped(name),
+                        span,
+                    });
+                    i += 2;
+                    continue;
+                }
+                TokenKind::Directive(directive) if directive == "bankdef" => {
+                    let (bank, next) = parse_bankdef(tokens, i + 1, span, false)?;
+                    if self
+                        .banks
+                        .iter()
+                        .skip(1)
+                        .any(|other| other.name == bank.name)
+                    {
+                        return Err(Error::new(
+                            span,
+                            format!("bank `{}` is already defined", bank.name),
+                        ));
+                    }
+                    self.banks.push(bank);
+                    self.statements.push(Statement::Bank {
+                        index: self.banks.len() - 1,
+                    });
+                    i = next;
+                    continue;
+                }
+                TokenKind::Directive(directive) => {
+                    let end = line_end(tokens, i + 1);
+                    let line = self.localize(&tokens[i + 1..end]);
+                    self.directive(directive, &line, span, span_at(tokens, end))?;
+                    i = end;
+                }
+                _ => {
+                    let end = line_end(tokens, i);
+                    let line = self.localize(&tokens[i..end]);
+                    let (rule, found) =
+                        self.rules.match_instruction(&line, span_at(tokens, end))?;
+                    match &rule.output {
+                        Output::Value(output) => self.statements.push(Statement::Instruction {
+                            output: output.clone(),
+                            arguments: found.arguments,
+                            span,
+                        }),
+                        Output::Asm(body) => self.expand(body, &line, &found, span)?,
+                    }
+                    i = end;
+                }
+            }
+        }
+        return Ok(());
+    }
+    /// Parses a directive other than `#bankdef`; `line` holds the tokens
+    /// after it and `end` is the end of the line.
+    fn directive(
+        &mut self,
+        directive: &str,
+        line: &[Token],
+        span: Span,
+        end: Span,
+    ) -> Result<(), Error> {
+        match directive {
+            "bank" => {
+                let index = match line {
+                    [Token {
+                        kind: TokenKind::Ident(name),
+                        ..
+                    }] => self
+                        .banks
+                        .iter()
+                        .skip(1)
+                        .position(|bank| &bank.name == name)
+                        .map(|index| index + 1)
+                        .ok_or_else(|| Error::new(span, format!("unknown bank `{}`", name)))?,
+                    _ => return Err(Error::new(span, "expected a bank name")),
+                };
+                self.statements.push(Statement::Bank { index });
+            }
+            "addr" => {
+                let address = expression::parse(line, end)?;
+                self.statements.push(Statement::Addr { address, span });
+            }
+            "const" => {
+                let name = match line {
+                    [Token {
+                        kind: TokenKind::Ident(name),
+                        ..
+                    }, equals, ..]
+                        if equals.is_punct('=') =>
+                    {
+                        name.clone()
+                    }
+                    _ => return Err(Error::new(span, "expected `#const NAME = value`")),
+                };
+                let value = expression::parse(&line[2..], end)?;
+                self.statements.push(Statement::Const { name, value, span });
+            }
+            "include" => return Err(Error::new(span, "#include needs a file name in quotes")),
+            _ if directive.starts_with('d') => {
+                let width = match &directive[1..] {
+                    "" => None,
+                    digits => match digits.parse::<u32>() {
+                        Ok(width) if (1..=expression::MAX_WIDTH).contains(&width) => Some(width),
+                        _ => {
+                            return Err(Error::new(
+                                span,
+                                format!("unknown directive #{}", directive),
+                            ))
+                        }
+                    },
+                };
+                let values = parse_list(line, 0, line.len())?;
+                self.statements.push(Statement::Data { width, values });
+            }
+            _ => {
+                return Err(Error::new(
+                    span,
+                    format!("unknown directive #{}", directive),
+                ))
+            }
+        }
+        return Ok(());
+    }
+    /// Parses the lines of an `asm` rule in place of the instruction
+    /// `line`, which matched as `found`. Lines taken from the rule are
+    /// reported at the instruction.
+    fn expand(
+        &mut self,
+        body: &[Token],
+        line: &[Token],
+        found: &Match,
+        span: Span,
+    ) -> Result<(), Error> {
+        if self.depth >= MAX_EXPANSION_DEPTH {
+            return Err(Error::new(
+                span,
+                "asm rules nested too deeply, does a rule expand to itself?",
+            ));
+        }
+        let mut expanded = Vec::new();
+        let mut i = 0;
+        while i < body.len() {
+            if let Some((name, range)) = parameter_at(body, i)
+                .and_then(|name| found.ranges.iter().find(|(parameter, _)| parameter == name))
+            {
+                let expression = found.arguments.iter().any(|(parameter, argument)| {
+                    parameter == name && matches!(argument, Argument::Expression(_))
+                });
+                // Keep `{a} * 2` meaning (a) * 2
+                let parenthesize = expression && range.len() > 1;
+                if parenthesize {
+                    expanded.push(Token {
+                        kind: TokenKind::Punct('('),
+                        span,
+                    });
+                }
+                expanded.extend_from_slice(&line[range.clone()]);
+                if parenthesize {
+                    expanded.push(Token {
+                        kind: TokenKind::Punct(')'),
+                        span,
+                    });
+                }
+                i += 3;
+                continue;
+            }
+            expanded.push(Token {
+                kind: body[i].kind.clone(),
+                span,
+            });
+            i += 1;
+        }
+        expanded.push(Token {
+            kind: TokenKind::Newline,
+            span,
+        });
+        // `@` cannot start a label, so these scopes never clash with one
+        let outer = std::mem::replace(&mut self.scope, format!("@{}", self.expansions));
+        self.expansions += 1;
+        self.depth += 1;
+        let result = self.parse(&expanded);
+        self.depth -= 1;
+        self.scope = outer;
+        return result;
+    }
+}
+
+/// The parameter name if `tokens[i..]` starts with `{name}`.
+fn parameter_at(tokens: &[Token], i: usize) -> Option<&str> {
+    match &tokens[i..] {
+        [open, Token {
+            kind: TokenKind::Ident(name),
+            ..
+        }, close, ..]
+            if open.is_punct('{') && close.is_punct('}') =>
+        {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// Writes the low `width` bits of `value` at bit `position`, most
+/// significant bit first.
+fn put_bits(words: &mut Vec<u32>, position: usize, value: i128, width: u32) {
+    for bit in 0..width as usize {
+        let set = value >> (width as usize - 1 - bit) & 1 == 1;
+        let at = position + bit;
+        if words.len() <= at / WORD_BITS {
+            words.resize(at / WORD_BITS + 1, 0);
+        }
+        let mask = 1 << (WORD_BITS - 1 - at % WORD_BITS);
+        if set {
+            words[at / WORD_BITS] |= mask;
+        } else {
+            words[at / WORD_BITS] &= !mask;
+        }
+    }
+}
+
+/// Appends a sized value to a bank; objects ignore `#outp`.
+fn emit(
+    bank: &Bank,
+    placed: &mut Placed,
+    value: Value,
+    span: Span,
+    object: bool,
+) -> Result<(), Error> {
+    let Some(width) = value.width else {
+        return Err(Error::new(
+            span,
+            "value has no width; use e.g. #d32 or value`32",
+        ));
+    };
+    if bank.outp.is_none() && !object {
+        return Err(Error::new(
+            span,
+            format!("bank `{}` has no #outp, so it cannot hold data", bank.name),
+        ));
+    }
+    let end = placed.position + width as usize;
+    if bank.size.is_some_and(|size| end > size * WORD_BITS) {
+        return Err(Error::new(span, format!("bank `{}` is full", bank.name)));
+    }
+    if let Some(relocation) = value.relocation {
+        let (shift, field) = relocation.field.expect("sized values are sliced");
+        let first = placed.position + (width - shift - field) as usize;
+        let last = end - 1 - shift as usize;
+        if first / WORD_BITS != last / WORD_BITS {
+            return Err(Error::new(
+                span,
+                "an address the linker fills in must be inside one word",
+            ));
+        }
+        placed.relocations.push(object::Relocation {
+            offset: first / WORD_BITS,
+            shift: (WORD_BITS - 1 - last % WORD_BITS) as u32,
+            width: field,
+            target: relocation.target,
+            addend: i64::try_from(relocation.addend)
+                .map_err(|_| Error::new(span, "address offset out of range"))?,
+        });
+    }
+    put_bits(&mut placed.words, placed.position, value.value, width);
+    placed.position = end;
+    return Ok(());
+}
+
+/// Adds a label or constant found by [`layout`]. On the last pass its value
+/// must be the one the previous pass found, used for lookups.
+fn define(
+    defined: &mut HashMap<String, Value>,
+    previous: &HashMap<String, Value>,
+    last: bool,
+    name: &str,
+    value: Value,
+    span: Span,
+) -> Result<(), Error> {
+    if defined.insert(name.to_string(), value.clone()).is_some() {
+        return Err(Error::new(span, format!("`{}` is already defined", name)));
+    }
+    if last && previous.get(name) != Some(&value) {
+        return Err(Error::new(
+            span,
+            format!("the value of `{}` depends on itself", name),
+        ));
+    }
+    return Ok(());
+}
+
+/// Whether other objects can refer to a label: not `.local` ones or those
+/// inside `asm` rules.
+fn is_global(name: &str) -> bool {
+    return !name.contains('.') && !name.starts_with('@');
+}
+
+/// Places every statement in its bank, looking names up in `symbols` from
+/// the previous pass. Unless `last`, names defined later read as 0.
+fn layout(
+    program: &Program,
+    symbols: &HashMap<String, Value>,
+    last: bool,
+) -> Result<Layout, Error> {
+    let Program {
+        banks,
+        statements,
+        names,
+        object,
+    } = program;
+    let lookup = |name: &str, span: Span| -> Result<Value, Error> {
+        match symbols.get(name) {
+            Some(value) => Ok(value.clone()),
+            None if *object && is_global(name) && !names.contains(name) => {
+                Ok(Value::relocatable(Target::Symbol(name.to_string()), 0))
+            }
+            None if !last => Ok(Value::new(0)),
+            None => Err(Error::new(span, format!("unknown symbol `{}`", name))),
+        }
+    };
+    let mut defined = HashMap::new();
+    let mut labels = Vec::new();
+    let mut placed: Vec<Placed> = banks
+        .iter()
+        .map(|_| Placed {
+            position: 0,
+            words: Vec::new(),
+            relocations: Vec::new(),
+        })
+        .collect();
+    let mut current = 0;
+    for statement in statements {
+        match statement {
+            Statement::Label { name, span } => {
+                let position = placed[current].position;
+                if !position.is_multiple_of(WORD_BITS) {
+                    return Err(Error::new(
+                        *span,
+                        format!("label `{}` is not on a word boundary", name),
+                    ));
+                }
+                let word = position / WORD_BITS;
+                let value = if *object {
+                    Value::relocatable(Target::Section(banks[current].name.clone()), word as i128)
+                } else {
+                    Value::new(banks[current].addr + word as i128)
+                };
+                define(&mut defined, symbols, last, name, value, *span)?;
+                labels.push((name.clone(), current, word));
+            }
+            Statement::Instruction {
+                output,
+                arguments,
+                span,
+            } => {
+                // Errors inside the rule are reported at the instruction
+                let value = rules::eval_output(output, arguments, &lookup).map_err(|error| {
+                    if error.span.source == span.source && error.span.line == span.line {
+                        return error;
+                    }
+                    Error::new(*span, error.message)
+                })?;
+                emit(&banks[current], &mut placed[current], value, *span, *object)?;
+            }
+            Statement::Data { width, values } => {
+                for expr in values {
+                    let value = match *width {
+                        Some(width) => Expr {
+                            kind: ExprKind::Slice(Box::new(expr.clone()), width),
+                            span: expr.span,
+                        }
+                        .eval(&lookup)?,
+                        None => expr.eval(&lookup)?,
+                    };
+                    emit(
+                        &banks[current],
+                        &mut placed[current],
+                        value,
+                        expr.span,
+                        *object,
+                    )?;
+                }
+            }
+            Statement::Bank { index } => current = *index,
+            Statement::Const { name, value, span } => {
+                let value = value.eval(&lookup)?;
+                define(&mut defined, symbols, last, name, value, *span)?;
+            }
+            Statement::Addr { span, .. } if *object => {
+                return Err(Error::new(
+                    *span,
+                    "#addr cannot be used in objects; the linker script places sections",
+                ));
+            }
+            Statement::Addr { address, span } => {
+                let bank = &banks[current];
+                let word = address.eval(&lookup)?.value - bank.addr;
+                let position = usize::try_from(word)
+                    .ok()
+                    .map(|word| word * WORD_BITS)
+                    .filter(|&position| position >= placed[current].position)
+                    .ok_or_else(|| {
+                        Error::new(*span, "#addr cannot move back or before the bank")
+                    })?;
+                if bank.size.is_some_and(|size| position > size * WORD_BITS) {
+                    return Err(Error::new(
+                        *span,
+                        format!("#addr is past the end of bank `{}`", bank.name),
+                    ));
+                }
+                placed[current].position = position;
+            }
+        }
+    }
+    return Ok(Layout {
+        symbols: defined,
+        placed,
+        labels,
+    });
+}
+
+/// Makes a section of every bank with words or labels.
+fn build_object(banks: &[Bank], layout: Layout) -> Object {
+    let mut object = Object::default();
+    for (index, (bank, placed)) in banks.iter().zip(layout.placed).enumerate() {
+        let mut words = placed.words;
+        words.resize(placed.position.div_ceil(WORD_BITS), 0);
+        if words.is_empty() && !layout.labels.iter().any(|(_, bank, _)| *bank == index) {
+            continue;
+        }
+        object.sections.push(object::Section {
+            name: bank.name.clone(),
+            words,
+            relocations: placed.relocations,
+        });
+    }
+    for (name, bank, offset) in layout.labels {
+        object.symbols.push(object::Symbol {
+            global: is_global(&name),
+            name,
+            section: banks[bank].name.clone(),
+            offset,
+        });
+    }
+    return object;
+}
+
+/// Puts the banks at their `#outp` in one image, which must not overlap.
+fn build_image(banks: &[Bank], placed: &[Placed]) -> Result<Vec<u32>, Error> {
+    let mut image = Vec::new();
+    let mut used: Vec<(usize, usize, &Bank)> = Vec::new();
+    for (bank, placed) in banks.iter().zip(placed) {
+        let Some(outp) = bank.outp else {
+            continue;
+        };
+        let mut words = placed.words.clone();
+        words.resize(placed.position.div_ceil(WORD_BITS), 0);
+        if bank.fill {
+            if let Some(size) = bank.size {
+                words.resize(size, 0);
+            }
+        }
+        if words.is_empty() {
+            continue;
+        }
+        let start = outp / WORD_BITS;
+        let end = start + words.len();
+        if let Some((_, _, other)) = used
+            .iter()
+            .find(|(other_start, other_end, _)| start < *other_end && *other_start < end)
+        {
+            return Err(Error::new(
+                bank.span,
+                format!(
+                    "bank `{}` overlaps bank `{}` in the output",
+                    bank.name, other.name
+                ),
+            ));
+        }
+        used.push((start, end, bank));
+        if image.len() < end {
+            image.resize(end, 0);
+        }
+        image[start..end].copy_from_slice(&words);
+    }
+    return Ok(image);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bank for test programs, which puts address 0 at word 0.
+    const BANK: &str = "#bankdef test {\n#bits 32\n#outp 0\n}\n";
+
+    /// Assembles `source` as `test.asm` in [`BANK`].
+    fn assemble(source: &str) -> Result<Vec<u32>, AssembleError> {
+        let mut assembler = Assembler::new();
+        assembler.add_source("bank.asm", BANK);
+        assembler.add_source("test.asm", source);
+        return assembler.assemble();
+    }
+
+    #[test]
+    fn instructions_labels_and_data() {
+        let source = "start:\nADD R1 - R2, R3\nLDI R4 - end ; comment\nJMP start\n\
+            end:\n#d32 0xcafe\n#d16 1, 2\n";
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![0x12310000, 0x64000003, 0x38000000, 0xcafe, 0x00010002]
+        );
+    }
+
+    #[test]
+    fn the_program_bank_starts_at_word_one() {
+        let mut assembler = Assembler::new();
+        assembler.add_source("test.asm", "NOP\nhere:\nLDI R1 - here\n");
+        assert_eq!(assembler.assemble().unwrap(), vec![0, 0, 0x61000001]);
+    }
+
+    #[test]
+    fn errors_name_the_file_line_and_column() {
+        let error = assemble("NOP\n  BOGUS R1\n").unwrap_err();
+        assert_eq!(
+            (error.file.as_str(), error.line, error.column),
+            ("test.asm", 2, 3)
+        );
+        assert!(error.to_string().starts_with("test.asm:2:3: "));
+
+        let error = assemble("LDI R1 - nowhere\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 10));
+        assert!(error.message.contains("nowhere"), "{}", error);
+
+        let error = assemble("NOP\nLDI R1 - 0x1000000\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(error.message.contains("24 bits"), "{}", error);
+
+        let error = assemble("here:\nhere:\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+    }
+
+    #[test]
+    fn expression_precedence() {
+        let source = "#d32 1 + 2 * 3\n#d32 (1 + 2) * 3\n#d32 10 - 4 - 3\n\
+            #d32 1 << 2 + 1\n#d32 6 & 3 | 8\n#d32 1 | 6 ^ 3\n#d32 ~0 & 0xff\n\
+            #d32 -7 % 4 + 17 / 5\n#d32 0x100 >> 4\n";
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![7, 9, 3, 8, 10, 5, 0xff, 0, 0x10]
+        );
+    }
+
+    #[test]
+    fn expression_errors_point_at_the_operator() {
+        let error = assemble("#d32 1 +\n").unwrap_err();
+        assert_eq!(error.line, 1);
+        assert!(
+            error.message.contains("expected an expression"),
+            "{}",
+            error
+        );
+
+        let error = assemble("NOP\n#d32 (1 + 2\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(error.message.contains("`)`"), "{}", error);
+
+        let error = assemble("#d32 4 / (2 - 2)\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 8));
+        assert_eq!(error.message, "division by zero");
+    }
+
+    #[test]
+    fn constants() {
+        let source = "#const SCREEN = 0xFB5000\n#const END = SCREEN + LAST\n\
+            LDI R1 - SCREEN - 1\n#d32 END\n#const LAST = 0x10\n";
+        assert_eq!(assemble(source).unwrap(), vec![0x61fb4fff, 0xfb5010]);
+        let error = assemble("#const A = A + 1\n").unwrap_err();
+        assert!(error.message.contains("itself"), "{}", error);
+    }
+
+    #[test]
+    fn includes_and_include_cycles() {
+        let directory = std::env::temp_dir().join(format!("ss32-include-{}", std::process::id()));
+        fs::create_dir_all(directory.join("lib")).unwrap();
+        fs::write(
+            directory.join("main.asm"),
+            "#include \"lib/a.asm\"\nLDI R1 - A\n",
+        )
+        .unwrap();
+        fs::write(directory.join("lib/a.asm"), "#const A = 5\n").unwrap();
+        fs::write(directory.join("loop.asm"), "NOP\n#include \"lib/b.asm\"\n").unwrap();
+        fs::write(
+            directory.join("lib/b.asm"),
+            "NOP\n#include \"../loop.asm\"\n",
+        )
+        .unwrap();
+
+        let mut assembler = Assembler::new();
+        assembler.add_source("bank.asm", BANK);
+        assembler.add_file(&directory.join("main.asm")).unwrap();
+        assert_eq!(assembler.assemble().unwrap(), vec![0x61000005]);
+
+        let mut assembler = Assembler::new();
+        assembler.add_file(&directory.join("loop.asm")).unwrap();
+        let error = assembler.assemble().unwrap_err();
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(error.file.ends_with("b.asm"), "{}", error);
+        assert_eq!((error.line, error.column), (2, 1));
+        assert!(error.message.contains("includes itself"), "{}", error);
+    }
+
+    #[test]
+    fn local_labels_belong_to_the_last_global_label() {
+        let source = "first:\n.loop:\nJMP .loop\nsecond:\nNOP\n.loop:\nJMP .loop\n\
+            JMP first.loop\n";
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![0x38000000, 0, 0x38000002, 0x38000000]
+        );
+        let error = assemble("first:\n.here:\nsecond:\nJMP .here\n").unwrap_err();
+        assert_eq!(error.line, 4);
+    }
+
+    #[test]
+    fn asm_rules_expand_with_their_own_local_labels() {
+        let source = "#ruledef {\n    WAIT {n} => asm {\n        LDI R1 - {n}\n\
+            .again:\n        SUB R1 - R1, R2\n        JP-NZr .again\n    }\n}\n\
+            WAIT 3\nWAIT 2 + 2\n";
+        let jump = 0x3e000000;
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![
+                0x61000003,
+                0x11210000 | 1 << 12,
+                jump | 1,
+                0x61000004,
+                0x11210000 | 1 << 12,
+                jump | 4,
+            ]
+        );
+        let source = "#ruledef {\n    LOOP => asm {\n        LOOP\n    }\n}\nLOOP\n";
+        assert!(assemble(source).is_err());
+    }
+}
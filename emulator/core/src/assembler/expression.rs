@@ -0,0 +1,393 @@
+use crate::assembler::lexer::{Span, Token, TokenKind};
+use crate::assembler::Error;
+use crate::object::Target;
+
+/// Widest value an expression may produce.
+pub const MAX_WIDTH: u32 = 64;
+
+/// A number and, for sized values, its width in bits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Value {
+    pub value: i128,
+    pub width: Option<u32>,
+    /// Set for addresses only the linker knows, in objects.
+    pub relocation: Option<Box<Relocatable>>,
+}
+
+/// The unknown part of a relocatable value. Without a width the value is
+/// `target` plus `value`; slicing moves `value` into `addend` and leaves
+/// the bits of `field` 0 for the linker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocatable {
+    pub target: Target,
+    /// Shift and width of the bits holding the address, once sliced.
+    pub field: Option<(u32, u32)>,
+    pub addend: i128,
+}
+
+impl Value {
+    pub fn new(value: i128) -> Value {
+        Value {
+            value,
+            width: None,
+            relocation: None,
+        }
+    }
+    /// The address `offset` words after `target`.
+    pub fn relocatable(target: Target, offset: i128) -> Value {
+        Value {
+            value: offset,
+            width: None,
+            relocation: Some(Box::new(Relocatable {
+                target,
+                field: None,
+                addend: 0,
+            })),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    /// `~`, bitwise not.
+    Not,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    ShiftLeft,
+    ShiftRight,
+    And,
+    Xor,
+    Or,
+}
+
+/// Binary operators from the loosest to the tightest binding; `@` is
+/// looser than all of them.
+const PRECEDENCE: [&[(TokenKind, BinaryOp)]; 6] = [
+    &[(TokenKind::Punct('|'), BinaryOp::Or)],
+    &[(TokenKind::Punct('^'), BinaryOp::Xor)],
+    &[(TokenKind::Punct('&'), BinaryOp::And)],
+    &[
+        (TokenKind::ShiftLeft, BinaryOp::ShiftLeft),
+        (TokenKind::ShiftRight, BinaryOp::ShiftRight),
+    ],
+    &[
+        (TokenKind::Punct('+'), BinaryOp::Add),
+        (TokenKind::Punct('-'), BinaryOp::Subtract),
+    ],
+    &[
+        (TokenKind::Punct('*'), BinaryOp::Multiply),
+        (TokenKind::Punct('/'), BinaryOp::Divide),
+        (TokenKind::Punct('%'), BinaryOp::Remainder),
+    ],
+];
+
+#[derive(Clone, Debug)]
+pub enum ExprKind {
+    Number(Value),
+    Symbol(String),
+    /// Arithmetic gives values without a width.
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `` x`N ``: the low `N` bits of `x`, which must fit in them.
+    Slice(Box<Expr>, u32),
+    /// `a @ b`: the bits of `a` followed by the bits of `b`.
+    Concat(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+/// Whether `value` is representable in `width` bits, unsigned or two's
+/// complement.
+fn fits(value: i128, width: u32) -> bool {
+    return value >= -(1 << (width - 1)) && value < 1 << width;
+}
+
+impl Expr {
+    /// Evaluates the expression, resolving names with `lookup`.
+    pub fn eval(
+        &self,
+        lookup: &dyn Fn(&str, Span) -> Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        match &self.kind {
+            ExprKind::Number(value) => Ok(value.clone()),
+            ExprKind::Symbol(name) => lookup(name, self.span),
+            ExprKind::Unary(op, expr) => {
+                let value = expr.eval(lookup)?;
+                if value.relocation.is_some() {
+                    return Err(self.not_relocatable());
+                }
+                Ok(Value::new(match op {
+                    UnaryOp::Negate => -value.value,
+                    UnaryOp::Not => !value.value,
+                }))
+            }
+            ExprKind::Binary(op, left, right) => {
+                let left = left.eval(lookup)?;
+                let right = right.eval(lookup)?;
+                let unsliced = |value: &Value| {
+                    value
+                        .relocation
+                        .as_ref()
+                        .is_none_or(|relocation| relocation.field.is_none())
+                };
+                if !unsliced(&left) || !unsliced(&right) {
+                    return Err(self.not_relocatable());
+                }
+                // An address plus or minus a number, or the distance
+                // between two addresses relative to the same thing
+                let relocation = match (op, left.relocation, right.relocation) {
+                    (_, None, None) => None,
+                    (BinaryOp::Add, Some(relocation), None)
+                    | (BinaryOp::Add, None, Some(relocation))
+                    | (BinaryOp::Subtract, Some(relocation), None) => Some(relocation),
+                    (BinaryOp::Subtract, Some(left), Some(right)) if left == right => None,
+                    _ => return Err(self.not_relocatable()),
+                };
+                let (left, right) = (left.value, right.value);
+                let shift = u32::try_from(right).ok().filter(|shift| *shift < 128);
+                let value = match op {
+                    BinaryOp::Add => left.checked_add(right),
+                    BinaryOp::Subtract => left.checked_sub(right),
+                    BinaryOp::Multiply => left.checked_mul(right),
+                    BinaryOp::Divide | BinaryOp::Remainder if right == 0 => {
+                        return Err(Error::new(self.span, "division by zero"))
+                    }
+                    BinaryOp::Divide => left.checked_div(right),
+                    BinaryOp::Remainder => left.checked_rem(right),
+                    BinaryOp::ShiftLeft => shift.and_then(|shift| left.checked_shl(shift)),
+                    BinaryOp::ShiftRight => shift.and_then(|shift| left.checked_shr(shift)),
+                    BinaryOp::And => Some(left & right),
+                    BinaryOp::Xor => Some(left ^ right),
+                    BinaryOp::Or => Some(left | right),
+                };
+                let Some(value) = value else {
+                    return Err(Error::new(self.span, "arithmetic overflow"));
+                };
+                Ok(Value {
+                    value,
+                    width: None,
+                    relocation,
+                })
+            }
+            ExprKind::Slice(expr, width) => {
+                let value = expr.eval(lookup)?;
+                if let Some(relocation) = value.relocation {
+                    if relocation.field.is_some() {
+                        return Err(Error::new(
+                            self.span,
+                            "the bits of an address the linker fills in cannot be sliced again",
+                        ));
+                    }
+                    // Whether it fits is checked once the linker knows it
+                    return Ok(Value {
+                        value: 0,
+                        width: Some(*width),
+                        relocation: Some(Box::new(Relocatable {
+                            field: Some((0, *width)),
+                            addend: value.value,
+                            ..*relocation
+                        })),
+                    });
+                }
+                if !fits(value.value, *width) {
+                    return Err(Error::new(
+                        self.span,
+                        format!("value 0x{:x} does not fit in {} bits", value.value, width),
+                    ));
+                }
+                Ok(Value {
+                    value: value.value & ((1 << width) - 1),
+                    width: Some(*width),
+                    relocation: None,
+                })
+            }
+            ExprKind::Concat(high, low) => {
+                let high = high.eval(lookup)?;
+                let low = low.eval(lookup)?;
+                let (Some(high_width), Some(low_width)) = (high.width, low.width) else {
+                    return Err(Error::new(
+                        self.span,
+                        "both sides of `@` need a width, e.g. value`8",
+                    ));
+                };
+                if high_width + low_width > MAX_WIDTH {
+                    return Err(Error::new(
+                        self.span,
+                        format!("values wider than {} bits are not supported", MAX_WIDTH),
+                    ));
+                }
+                let relocation = match (high.relocation, low.relocation) {
+                    (None, None) => None,
+                    (Some(mut relocation), None) => {
+                        if let Some((shift, _)) = &mut relocation.field {
+                            *shift += low_width;
+                        }
+                        Some(relocation)
+                    }
+                    (None, Some(relocation)) => Some(relocation),
+                    (Some(_), Some(_)) => {
+                        return Err(Error::new(
+                            self.span,
+                            "a value can only hold one address the linker fills in",
+                        ))
+                    }
+                };
+                Ok(Value {
+                    value: high.value << low_width | low.value,
+                    width: Some(high_width + low_width),
+                    relocation,
+                })
+            }
+        }
+    }
+    fn not_relocatable(&self) -> Error {
+        return Error::new(
+            self.span,
+            "addresses the linker fills in only allow adding or subtracting a number",
+        );
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    /// Reported when the expression ends early.
+    end: Span,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.position);
+    }
+    fn error(&self, message: &str) -> Error {
+        let span = self.peek().map_or(self.end, |token| token.span);
+        return Error::new(span, message);
+    }
+    fn concat(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.binary(0)?;
+        while let Some(token) = self.peek().filter(|token| token.is_punct('@')) {
+            let span = token.span;
+            self.position += 1;
+            let low = self.binary(0)?;
+            expr = Expr {
+                kind: ExprKind::Concat(Box::new(expr), Box::new(low)),
+                span,
+            };
+        }
+        return Ok(expr);
+    }
+    /// Operators of `PRECEDENCE[level]` and tighter, left associative.
+    fn binary(&mut self, level: usize) -> Result<Expr, Error> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut expr = self.binary(level + 1)?;
+        while let Some((span, op)) = self.peek().and_then(|token| {
+            PRECEDENCE[level]
+                .iter()
+                .find(|(kind, _)| *kind == token.kind)
+                .map(|(_, op)| (token.span, *op))
+        }) {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            expr = Expr {
+                kind: ExprKind::Binary(op, Box::new(expr), Box::new(right)),
+                span,
+            };
+        }
+        return Ok(expr);
+    }
+    fn unary(&mut self) -> Result<Expr, Error> {
+        let op = match self.peek().map(|token| &token.kind) {
+            Some(TokenKind::Punct('-')) => UnaryOp::Negate,
+            Some(TokenKind::Punct('~')) => UnaryOp::Not,
+            _ => return self.slice(),
+        };
+        let span = self.tokens[self.position].span;
+        self.position += 1;
+        let expr = self.unary()?;
+        return Ok(Expr {
+            kind: ExprKind::Unary(op, Box::new(expr)),
+            span,
+        });
+    }
+    fn slice(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.primary()?;
+        while let Some(token) = self.peek().filter(|token| token.is_punct('`')) {
+            let span = token.span;
+            self.position += 1;
+            let width = match self.peek().map(|token| &token.kind) {
+                Some(&TokenKind::Number { value, width: None })
+                    if (1..=MAX_WIDTH as i128).contains(&value) =>
+                {
+                    value as u32
+                }
+                _ => {
+                    return Err(self.error(&format!(
+                        "expected a decimal width from 1 to {} after `",
+                        MAX_WIDTH
+                    )))
+                }
+            };
+            self.position += 1;
+            expr = Expr {
+                kind: ExprKind::Slice(Box::new(expr), width),
+                span,
+            };
+        }
+        return Ok(expr);
+    }
+    fn primary(&mut self) -> Result<Expr, Error> {
+        let Some(token) = self.peek() else {
+            return Err(self.error("expected an expression"));
+        };
+        let span = token.span;
+        let kind = match &token.kind {
+            &TokenKind::Number { value, width } => ExprKind::Number(Value {
+                value,
+                width,
+                relocation: None,
+            }),
+            TokenKind::Ident(name) => ExprKind::Symbol(name.clone()),
+            TokenKind::Punct('(') => {
+                self.position += 1;
+                let expr = self.concat()?;
+                if !self.peek().is_some_and(|token| token.is_punct(')')) {
+                    return Err(self.error("expected `)`"));
+                }
+                self.position += 1;
+                return Ok(expr);
+            }
+            _ => return Err(self.error("expected an expression")),
+        };
+        self.position += 1;
+        return Ok(Expr { kind, span });
+    }
+}
+
+/// Parses all of `tokens` as one expression. `end` is where an expression
+/// cut short is reported.
+pub fn parse(tokens: &[Token], end: Span) -> Result<Expr, Error> {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        end,
+    };
+    let expr = parser.concat()?;
+    if parser.position < tokens.len() {
+        return Err(parser.error("unexpected token in expression"));
+    }
+    return Ok(expr);
+}
@@ -0,0 +1,167 @@
+use crate::assembler::Error;
+
+/// Where a token starts: index of the source file, 1-based line and column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub source: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TokenKind {
+    /// Mnemonic parts, register and label names. `JP-NCMP` is three tokens.
+    Ident(String),
+    /// Hex and binary literals are as wide as their digits, decimal ones
+    /// have no width.
+    Number {
+        value: i128,
+        width: Option<u32>,
+    },
+    /// `#d`, `#bankdef`..., without the `#`.
+    Directive(String),
+    /// A double-quoted string, such as an `#include` path.
+    String(String),
+    Punct(char),
+    /// `=>` between a rule's pattern and its output.
+    Arrow,
+    ShiftLeft,
+    ShiftRight,
+    Newline,
+}
+
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn is_punct(&self, c: char) -> bool {
+        return self.kind == TokenKind::Punct(c);
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    return c.is_ascii_alphabetic() || c == '_' || c == '.';
+}
+
+fn is_ident_char(c: char) -> bool {
+    return c.is_ascii_alphanumeric() || c == '_' || c == '.';
+}
+
+fn parse_number(text: &str, span: Span) -> Result<TokenKind, Error> {
+    let digits = text.replace('_', "");
+    let (radix, digits, bits_per_digit) = if let Some(hex) = digits.strip_prefix("0x") {
+        (16, hex.to_string(), Some(4))
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        (2, binary.to_string(), Some(1))
+    } else {
+        (10, digits, None)
+    };
+    let value = u64::from_str_radix(&digits, radix)
+        .map_err(|_| Error::new(span, format!("invalid number `{}`", text)))?;
+    return Ok(TokenKind::Number {
+        value: value as i128,
+        width: bits_per_digit.map(|bits| bits * digits.len() as u32),
+    });
+}
+
+/// Splits the text of source file `source` into tokens. Comments run from
+/// `;` to the end of the line.
+pub fn lex(source: usize, text: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let span = Span {
+                source,
+                line: index + 1,
+                column: i + 1,
+            };
+            if c == ';' {
+                break;
+            }
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            let kind = if is_ident_start(c) {
+                while i < chars.len() && is_ident_char(chars[i]) {
+                    i += 1;
+                }
+                TokenKind::Ident(chars[start..i].iter().collect())
+            } else if c.is_ascii_digit() {
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                parse_number(&chars[start..i].iter().collect::<String>(), span)?
+            } else if c == '#' {
+                i += 1;
+                while i < chars.len() && is_ident_char(chars[i]) {
+                    i += 1;
+                }
+                if i == start + 1 {
+                    return Err(Error::new(span, "expected a directive after `#`"));
+                }
+                TokenKind::Directive(
+                    chars[start + 1..i]
+                        .iter()
+                        .collect::<String>()
+                        .to_lowercase(),
+                )
+            } else if c == '"' {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(Error::new(span, "unterminated string")),
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.get(i + 1) {
+                                Some('n') => text.push('\n'),
+                                Some('t') => text.push('\t'),
+                                Some(&escaped @ ('\\' | '"')) => text.push(escaped),
+                                _ => return Err(Error::new(span, "unknown escape in string")),
+                            }
+                            i += 2;
+                        }
+                        Some(&c) => {
+                            text.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                TokenKind::String(text)
+            } else if c == '=' && chars.get(i + 1) == Some(&'>') {
+                i += 2;
+                TokenKind::Arrow
+            } else if c == '<' && chars.get(i + 1) == Some(&'<') {
+                i += 2;
+                TokenKind::ShiftLeft
+            } else if c == '>' && chars.get(i + 1) == Some(&'>') {
+                i += 2;
+                TokenKind::ShiftRight
+            } else if c.is_ascii_punctuation() {
+                i += 1;
+                TokenKind::Punct(c)
+            } else {
+                return Err(Error::new(span, format!("unexpected character `{}`", c)));
+            };
+            tokens.push(Token { kind, span });
+        }
+        tokens.push(Token {
+            kind: TokenKind::Newline,
+            span: Span {
+                source,
+                line: index + 1,
+                column: chars.len() + 1,
+            },
+        });
+    }
+    return Ok(tokens);
+}
@@ -0,0 +1,374 @@
+use crate::assembler::expression::{self, Expr, Value};
+use crate::assembler::lexer::{Span, Token, TokenKind};
+use crate::assembler::Error;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// One piece of a rule's pattern.
+#[derive(Clone, Debug)]
+pub enum Element {
+    /// A token that must appear as is; identifiers match ignoring case.
+    Literal(TokenKind),
+    /// `{name}` takes an expression, `{name: subrule}` one of the
+    /// subrule's patterns.
+    Parameter {
+        name: String,
+        subrule: Option<String>,
+    },
+}
+
+/// What a rule turns into.
+#[derive(Clone, Debug)]
+pub enum Output {
+    /// Bits, such as `0x1 @ SR1`4 @ ...`.
+    Value(Expr),
+    /// `asm { ... }`: lines assembled in place of the instruction, with
+    /// `{name}` replaced by the tokens the parameter matched.
+    Asm(Vec<Token>),
+}
+
+/// `pattern => output` in a `#ruledef` or `#subruledef`.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub pattern: Vec<Element>,
+    pub output: Output,
+    pub span: Span,
+}
+
+/// What a parameter matched.
+#[derive(Clone, Debug)]
+pub enum Argument {
+    Expression(Expr),
+    /// The output of the matched subrule and its own arguments.
+    Subrule {
+        output: Expr,
+        arguments: Vec<(String, Argument)>,
+    },
+}
+
+impl Argument {
+    /// Evaluates the argument, resolving other names with `lookup`.
+    pub fn eval(
+        &self,
+        lookup: &dyn Fn(&str, Span) -> Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        match self {
+            Argument::Expression(expr) => expr.eval(lookup),
+            Argument::Subrule { output, arguments } => eval_output(output, arguments, lookup),
+        }
+    }
+}
+
+/// Evaluates a rule's output with the arguments of its parameters.
+pub fn eval_output(
+    output: &Expr,
+    arguments: &[(String, Argument)],
+    lookup: &dyn Fn(&str, Span) -> Result<Value, Error>,
+) -> Result<Value, Error> {
+    return output.eval(
+        &|name, span| match arguments.iter().find(|(parameter, _)| parameter == name) {
+            Some((_, argument)) => argument.eval(lookup),
+            None => lookup(name, span),
+        },
+    );
+}
+
+/// Parses the body of a `#ruledef` or `#subruledef` block, starting after
+/// its `{`. Returns the rules and the number of tokens used, including
+/// the closing `}`.
+pub fn parse_block(tokens: &[Token], open: Span) -> Result<(Vec<Rule>, usize), Error> {
+    let mut rules = Vec::new();
+    let mut i = 0;
+    loop {
+        let Some(token) = tokens.get(i) else {
+            return Err(Error::new(open, "unclosed `{`"));
+        };
+        if token.kind == TokenKind::Newline {
+            i += 1;
+            continue;
+        }
+        if token.is_punct('}') {
+            return Ok((rules, i + 1));
+        }
+        let mut pattern = Vec::new();
+        while let Some(token) = tokens.get(i) {
+            match &token.kind {
+                TokenKind::Arrow => break,
+                TokenKind::Newline => return Err(Error::new(token.span, "expected `=>`")),
+                TokenKind::Punct('{') => {
+                    let (element, used) = parse_parameter(&tokens[i..], token.span)?;
+                    pattern.push(element);
+                    i += used;
+                }
+                TokenKind::Ident(name) => {
+                    pattern.push(Element::Literal(TokenKind::Ident(name.to_lowercase())));
+                    i += 1;
+                }
+                kind => {
+                    pattern.push(Element::Literal(kind.clone()));
+                    i += 1;
+                }
+            }
+        }
+        let Some(arrow) = tokens.get(i) else {
+            return Err(Error::new(open, "unclosed `{`"));
+        };
+        if pattern.is_empty() {
+            return Err(Error::new(arrow.span, "expected a pattern before `=>`"));
+        }
+        i += 1;
+        let span = token.span;
+        let is_asm = matches!(tokens.get(i).map(|token| &token.kind), Some(TokenKind::Ident(name)) if name.eq_ignore_ascii_case("asm"))
+            && tokens.get(i + 1).is_some_and(|token| token.is_punct('{'));
+        if is_asm {
+            let body = i + 2;
+            let mut depth = 1;
+            i = body;
+            while depth > 0 {
+                let Some(token) = tokens.get(i) else {
+                    return Err(Error::new(tokens[body - 1].span, "unclosed `{`"));
+                };
+                match token.kind {
+                    TokenKind::Punct('{') => depth += 1,
+                    TokenKind::Punct('}') => depth -= 1,
+                    _ => {}
+                }
+                i += 1;
+            }
+            rules.push(Rule {
+                pattern,
+                output: Output::Asm(tokens[body..i - 1].to_vec()),
+                span,
+            });
+            continue;
+        }
+        let start = i;
+        // The output ends with the line or, for one-line blocks, at the `}`
+        while tokens
+            .get(i)
+            .is_some_and(|token| token.kind != TokenKind::Newline && !token.is_punct('}'))
+        {
+            i += 1;
+        }
+        let end = tokens.get(i).map_or(arrow.span, |token| token.span);
+        let output = expression::parse(&tokens[start..i], end)?;
+        rules.push(Rule {
+            pattern,
+            output: Output::Value(output),
+            span,
+        });
+    }
+}
+
+/// Parses `{name}` or `{name: subrule}`.
+fn parse_parameter(tokens: &[Token], open: Span) -> Result<(Element, usize), Error> {
+    let ident = |index: usize| match tokens.get(index).map(|token| &token.kind) {
+        Some(TokenKind::Ident(name)) => Some(name.clone()),
+        _ => None,
+    };
+    let Some(name) = ident(1) else {
+        return Err(Error::new(open, "expected a parameter name after `{`"));
+    };
+    if tokens.get(2).is_some_and(|token| token.is_punct('}')) {
+        return Ok((
+            Element::Parameter {
+                name,
+                subrule: None,
+            },
+            3,
+        ));
+    }
+    let subrule = ident(3);
+    if !tokens.get(2).is_some_and(|token| token.is_punct(':'))
+        || subrule.is_none()
+        || !tokens.get(4).is_some_and(|token| token.is_punct('}'))
+    {
+        return Err(Error::new(open, "expected `{name}` or `{name: subrule}`"));
+    }
+    return Ok((Element::Parameter { name, subrule }, 5));
+}
+
+/// The source text of a token, for messages.
+fn text(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::Ident(name) => name.clone(),
+        TokenKind::Number { value, .. } => value.to_string(),
+        TokenKind::Directive(name) => format!("#{}", name),
+        TokenKind::Punct(c) => c.to_string(),
+        TokenKind::Arrow => "=>".to_string(),
+        TokenKind::ShiftLeft => "<<".to_string(),
+        TokenKind::ShiftRight => ">>".to_string(),
+        TokenKind::String(text) => format!("{:?}", text),
+        TokenKind::Newline => String::new(),
+    }
+}
+
+fn literal_matches(literal: &TokenKind, token: &TokenKind) -> bool {
+    match (literal, token) {
+        (TokenKind::Ident(literal), TokenKind::Ident(name)) => literal.eq_ignore_ascii_case(name),
+        _ => literal == token,
+    }
+}
+
+/// A way `tokens[start..end]` matches a pattern.
+#[derive(Clone, Debug)]
+pub struct Match {
+    pub end: usize,
+    pub arguments: Vec<(String, Argument)>,
+    /// The tokens each parameter matched.
+    pub ranges: Vec<(String, Range<usize>)>,
+    /// Tokens taken by expression parameters; the match with the fewest is
+    /// the most specific, so `LD R0 - R2` picks the register form.
+    pub loose: usize,
+}
+
+/// The `#ruledef` and `#subruledef` rules of a program.
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+    pub subrules: HashMap<String, Vec<Rule>>,
+}
+
+impl RuleSet {
+    pub fn new() -> RuleSet {
+        RuleSet {
+            rules: Vec::new(),
+            subrules: HashMap::new(),
+        }
+    }
+    /// Every way `pattern` matches the tokens from `start` on.
+    fn match_pattern(
+        &self,
+        pattern: &[Element],
+        tokens: &[Token],
+        start: usize,
+        end: Span,
+    ) -> Result<Vec<Match>, Error> {
+        let mut matches = vec![Match {
+            end: start,
+            arguments: Vec::new(),
+            ranges: Vec::new(),
+            loose: 0,
+        }];
+        for element in pattern {
+            let mut next = Vec::new();
+            for partial in matches {
+                let position = partial.end;
+                match element {
+                    Element::Literal(literal) => {
+                        if tokens
+                            .get(position)
+                            .is_some_and(|token| literal_matches(literal, &token.kind))
+                        {
+                            next.push(Match {
+                                end: position + 1,
+                                ..partial
+                            });
+                        }
+                    }
+                    Element::Parameter {
+                        name,
+                        subrule: None,
+                    } => {
+                        for stop in position + 1..=tokens.len() {
+                            let Ok(expr) = expression::parse(&tokens[position..stop], end) else {
+                                continue;
+                            };
+                            let mut arguments = partial.arguments.clone();
+                            arguments.push((name.clone(), Argument::Expression(expr)));
+                            let mut ranges = partial.ranges.clone();
+                            ranges.push((name.clone(), position..stop));
+                            next.push(Match {
+                                end: stop,
+                                arguments,
+                                ranges,
+                                loose: partial.loose + stop - position,
+                            });
+                        }
+                    }
+                    Element::Parameter {
+                        name,
+                        subrule: Some(subrule),
+                    } => {
+                        let Some(rules) = self.subrules.get(subrule) else {
+                            return Err(Error::new(
+                                tokens.get(position).map_or(end, |token| token.span),
+                                format!("unknown subrule `{}`", subrule),
+                            ));
+                        };
+                        for rule in rules {
+                            let Output::Value(output) = &rule.output else {
+                                return Err(Error::new(
+                                    rule.span,
+                                    "asm blocks are only allowed in #ruledef",
+                                ));
+                            };
+                            for inner in self.match_pattern(&rule.pattern, tokens, position, end)? {
+                                let mut arguments = partial.arguments.clone();
+                                arguments.push((
+                                    name.clone(),
+                                    Argument::Subrule {
+                                        output: output.clone(),
+                                        arguments: inner.arguments,
+                                    },
+                                ));
+                                let mut ranges = partial.ranges.clone();
+                                ranges.push((name.clone(), position..inner.end));
+                                next.push(Match {
+                                    end: inner.end,
+                                    arguments,
+                                    ranges,
+                                    loose: partial.loose + inner.loose,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+            matches = next;
+        }
+        return Ok(matches);
+    }
+    /// Finds the rule for the instruction `tokens`. Of several matching
+    /// rules the most specific wins, then the first defined.
+    pub fn match_instruction(&self, tokens: &[Token], end: Span) -> Result<(&Rule, Match), Error> {
+        let mut best: Option<(&Rule, Match)> = None;
+        for rule in &self.rules {
+            for found in self.match_pattern(&rule.pattern, tokens, 0, end)? {
+                if found.end != tokens.len() {
+                    continue;
+                }
+                let better = match &best {
+                    Some((_, best)) => found.loose < best.loose,
+                    None => true,
+                };
+                if better {
+                    best = Some((rule, found));
+                }
+            }
+        }
+        if let Some(best) = best {
+            return Ok(best);
+        }
+        // Name the mnemonic if the leading literals of some rule match
+        let first = &tokens[0];
+        let mnemonic = self.rules.iter().find_map(|rule| {
+            let literals = rule
+                .pattern
+                .iter()
+                .take_while(|element| matches!(element, Element::Literal(_)))
+                .count();
+            let matched = literals > 0
+                && rule.pattern[..literals].iter().zip(tokens).all(|(element, token)| {
+                    matches!(element, Element::Literal(literal) if literal_matches(literal, &token.kind))
+                })
+                && tokens.len() >= literals;
+            matched.then(|| tokens[..literals].iter().map(|token| text(&token.kind)).collect::<String>())
+        });
+        let message = match (mnemonic, &first.kind) {
+            (Some(mnemonic), _) => format!("invalid operands for `{}`", mnemonic),
+            (None, TokenKind::Ident(name)) => format!("unknown instruction `{}`", name),
+            (None, _) => "expected an instruction".to_string(),
+        };
+        return Err(Error::new(first.span, message));
+    }
+}
@@ -0,0 +1,356 @@
+use crate::memory::{ADDRESS_MASK, RAM_SIZE};
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+use std::any::Any;
+use std::io;
+
+/// Number of word addresses on the memory bus.
+pub const ADDRESS_SPACE: usize = RAM_SIZE;
+
+/// A device answering to a range of bus addresses. `offset` is relative to
+/// the start of the range the device was mapped at.
+pub trait BusDevice: Any + Send {
+    fn read(&mut self, offset: usize) -> u32;
+    fn write(&mut self, offset: usize, value: u32);
+    /// Reads without side effects, for debuggers and the GUI.
+    fn peek(&self, offset: usize) -> u32;
+    /// Advances the device to CPU cycle `clock`. Called before every
+    /// instruction, so devices can keep time without the wall clock.
+    fn tick(&mut self, _clock: u64) {}
+    /// Interrupt lines the device wants raised, as a bit mask. Called once
+    /// per instruction; a device returns each request only once.
+    fn poll_interrupts(&mut self) -> u32 {
+        return 0;
+    }
+    /// Writes everything [`BusDevice::load_state`] needs to restore the
+    /// device exactly. Stateless devices keep the default.
+    fn save_state(&self, _out: &mut StateWriter) -> io::Result<()> {
+        return Ok(());
+    }
+    fn load_state(&mut self, _input: &mut StateReader) -> Result<(), SnapshotError> {
+        return Ok(());
+    }
+    /// Writes without side effects, used to undo stores when stepping
+    /// backwards. Devices that leave words out of
+    /// [`BusDevice::save_registers`] store the word here. The default does
+    /// nothing: register devices are restored through
+    /// [`BusDevice::load_registers`] afterwards, and replaying a store to
+    /// them could reach the hardware behind them.
+    fn poke(&mut self, _offset: usize, _value: u32) {}
+    /// Like [`BusDevice::save_state`] but leaves out words only changed by
+    /// bus writes, which the history already records. Called before every
+    /// instruction while history is on, so memory devices save nothing.
+    fn save_registers(&self, out: &mut StateWriter) -> io::Result<()> {
+        return self.save_state(out);
+    }
+    fn load_registers(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        return self.load_state(input);
+    }
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct Mapping {
+    start: usize,
+    end: usize,
+    device: Box<dyn BusDevice>,
+}
+
+/// The memory bus every load, store, stack access and fetch goes through.
+///
+/// Devices claim address ranges with [`Bus::map`]; a later mapping takes
+/// precedence over an earlier one it overlaps, so devices can be mapped on
+/// top of RAM. Reads of unmapped addresses return 0 and writes are dropped.
+pub struct Bus {
+    mappings: Vec<Mapping>,
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Bus {
+            mappings: Vec::new(),
+        }
+    }
+    /// Maps `device` at the `len` words starting at `start`.
+    pub fn map(&mut self, start: usize, len: usize, device: Box<dyn BusDevice>) {
+        self.mappings.push(Mapping {
+            start,
+            end: start + len,
+            device,
+        });
+    }
+    fn find(&self, address: usize) -> Option<usize> {
+        self.mappings
+            .iter()
+            .rposition(|m| m.start <= address && address < m.end)
+    }
+    pub fn read(&mut self, address: usize) -> u32 {
+        let address = address & ADDRESS_MASK;
+        let Some(index) = self.find(address) else {
+            return 0;
+        };
+        let mapping = &mut self.mappings[index];
+        return mapping.device.read(address - mapping.start);
+    }
+    pub fn write(&mut self, address: usize, value: u32) {
+        let address = address & ADDRESS_MASK;
+        let Some(index) = self.find(address) else {
+            return;
+        };
+        let mapping = &mut self.mappings[index];
+        mapping.device.write(address - mapping.start, value);
+    }
+    pub fn peek(&self, address: usize) -> u32 {
+        let address = address & ADDRESS_MASK;
+        let Some(index) = self.find(address) else {
+            return 0;
+        };
+        let mapping = &self.mappings[index];
+        return mapping.device.peek(address - mapping.start);
+    }
+    pub fn tick(&mut self, clock: u64) {
+        for mapping in self.mappings.iter_mut() {
+            mapping.device.tick(clock);
+        }
+    }
+    /// Collects the interrupt requests of every mapped device.
+    pub fn poll_interrupts(&mut self) -> u32 {
+        self.mappings
+            .iter_mut()
+            .fold(0, |lines, m| lines | m.device.poll_interrupts())
+    }
+    /// Saves every device in mapping order.
+    pub fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        out.put_u32(self.mappings.len() as u32)?;
+        for mapping in self.mappings.iter() {
+            out.put_u64(mapping.start as u64)?;
+            mapping.device.save_state(out)?;
+        }
+        return Ok(());
+    }
+    /// Restores a state saved from a bus with the same mappings.
+    pub fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        let count = input.get_u32()? as usize;
+        if count != self.mappings.len() {
+            return Err(SnapshotError::Mismatch(format!(
+                "{} devices saved, {} mapped",
+                count,
+                self.mappings.len()
+            )));
+        }
+        for mapping in self.mappings.iter_mut() {
+            let start = input.get_u64()? as usize;
+            if start != mapping.start {
+                return Err(SnapshotError::Mismatch(format!(
+                    "device saved at 0x{:06x} is mapped at 0x{:06x}",
+                    start, mapping.start
+                )));
+            }
+            mapping.device.load_state(input)?;
+        }
+        return Ok(());
+    }
+    pub fn poke(&mut self, address: usize, value: u32) {
+        let address = address & ADDRESS_MASK;
+        let Some(index) = self.find(address) else {
+            return;
+        };
+        let mapping = &mut self.mappings[index];
+        mapping.device.poke(address - mapping.start, value);
+    }
+    /// Saves the registers of every device, see [`BusDevice::save_registers`].
+    pub fn save_registers(&self, out: &mut StateWriter) -> io::Result<()> {
+        for mapping in self.mappings.iter() {
+            mapping.device.save_registers(out)?;
+        }
+        return Ok(());
+    }
+    pub fn load_registers(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        for mapping in self.mappings.iter_mut() {
+            mapping.device.load_registers(input)?;
+        }
+        return Ok(());
+    }
+    /// The first mapped device of type `T`.
+    pub fn device<T: BusDevice>(&self) -> Option<&T> {
+        self.mappings
+            .iter()
+            .find_map(|m| m.device.as_any().downcast_ref::<T>())
+    }
+    pub fn device_mut<T: BusDevice>(&mut self) -> Option<&mut T> {
+        self.mappings
+            .iter_mut()
+            .find_map(|m| m.device.as_any_mut().downcast_mut::<T>())
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Bus::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A block of words that counts bus reads and raises `lines` once.
+    struct Block {
+        words: Vec<u32>,
+        reads: usize,
+        lines: u32,
+    }
+
+    impl Block {
+        fn new(len: usize, fill: u32) -> Box<Block> {
+            Box::new(Block {
+                words: vec![fill; len],
+                reads: 0,
+                lines: 0,
+            })
+        }
+    }
+
+    impl BusDevice for Block {
+        fn read(&mut self, offset: usize) -> u32 {
+            self.reads += 1;
+            return self.words[offset];
+        }
+        fn write(&mut self, offset: usize, value: u32) {
+            self.words[offset] = value;
+        }
+        fn peek(&self, offset: usize) -> u32 {
+            return self.words[offset];
+        }
+        fn poll_interrupts(&mut self) -> u32 {
+            return std::mem::take(&mut self.lines);
+        }
+        fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+            return out.put_words(&self.words);
+        }
+        fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+            return input.get_words_into(&mut self.words);
+        }
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    /// A stateless device answering every read with its offset.
+    struct Offsets;
+
+    impl BusDevice for Offsets {
+        fn read(&mut self, offset: usize) -> u32 {
+            return offset as u32;
+        }
+        fn write(&mut self, _offset: usize, _value: u32) {}
+        fn peek(&self, offset: usize) -> u32 {
+            return offset as u32;
+        }
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn unmapped_addresses_read_zero_and_drop_writes() {
+        let mut bus = Bus::new();
+        bus.map(0x100, 4, Block::new(4, 7));
+        bus.write(0xFF, 1);
+        bus.write(0x104, 1);
+        assert_eq!(bus.read(0xFF), 0);
+        assert_eq!(bus.read(0x104), 0);
+        assert_eq!(bus.peek(0x104), 0);
+        assert_eq!(bus.device::<Block>().unwrap().words, [7; 4]);
+    }
+
+    #[test]
+    fn devices_see_offsets_from_their_start() {
+        let mut bus = Bus::new();
+        bus.map(0x100, 4, Block::new(4, 0));
+        bus.map(0x200, 4, Box::new(Offsets));
+        bus.write(0x102, 5);
+        assert_eq!(bus.device::<Block>().unwrap().words, [0, 0, 5, 0]);
+        assert_eq!(bus.read(0x102), 5);
+        assert_eq!(bus.read(0x203), 3);
+    }
+
+    #[test]
+    fn addresses_are_masked_to_the_bus_width() {
+        let mut bus = Bus::new();
+        bus.map(0x100, 4, Block::new(4, 0));
+        bus.write(0x1000101, 9);
+        assert_eq!(bus.read(0x101), 9);
+        assert_eq!(bus.peek(0xFF000101), 9);
+    }
+
+    #[test]
+    fn a_later_mapping_covers_an_earlier_one() {
+        let mut bus = Bus::new();
+        bus.map(0, 0x10, Block::new(0x10, 1));
+        bus.map(0x4, 2, Box::new(Offsets));
+        assert_eq!(bus.read(0x3), 1);
+        assert_eq!(bus.read(0x4), 0);
+        assert_eq!(bus.read(0x5), 1);
+        assert_eq!(bus.read(0x6), 1);
+        bus.write(0x4, 8);
+        assert_eq!(bus.device::<Block>().unwrap().words[4], 1);
+    }
+
+    #[test]
+    fn peek_and_poke_have_no_side_effects() {
+        let mut bus = Bus::new();
+        bus.map(0, 4, Block::new(4, 3));
+        assert_eq!(bus.peek(1), 3);
+        assert_eq!(bus.device::<Block>().unwrap().reads, 0);
+        assert_eq!(bus.read(1), 3);
+        assert_eq!(bus.device::<Block>().unwrap().reads, 1);
+        // The default poke leaves register devices alone.
+        bus.poke(1, 4);
+        assert_eq!(bus.peek(1), 3);
+    }
+
+    #[test]
+    fn interrupt_requests_of_all_devices_are_combined() {
+        let mut bus = Bus::new();
+        bus.map(0, 4, Block::new(4, 0));
+        bus.map(4, 4, Block::new(4, 0));
+        bus.device_mut::<Block>().unwrap().lines = 0b001;
+        assert_eq!(bus.poll_interrupts(), 0b001);
+        assert_eq!(bus.poll_interrupts(), 0);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut bus = Bus::new();
+        bus.map(0x10, 4, Block::new(4, 0));
+        bus.map(0x20, 4, Box::new(Offsets));
+        bus.write(0x11, 6);
+        let mut saved = Vec::new();
+        bus.save_state(&mut StateWriter::new(&mut saved)).unwrap();
+
+        let mut loaded = Bus::new();
+        loaded.map(0x10, 4, Block::new(4, 0));
+        loaded.map(0x20, 4, Box::new(Offsets));
+        loaded
+            .load_state(&mut StateReader::new(&mut saved.as_slice()))
+            .unwrap();
+        assert_eq!(loaded.peek(0x11), 6);
+
+        let mut moved = Bus::new();
+        moved.map(0x30, 4, Block::new(4, 0));
+        moved.map(0x20, 4, Box::new(Offsets));
+        let result = moved.load_state(&mut StateReader::new(&mut saved.as_slice()));
+        assert!(matches!(result, Err(SnapshotError::Mismatch(_))));
+
+        let mut fewer = Bus::new();
+        fewer.map(0x10, 4, Block::new(4, 0));
+        let result = fewer.load_state(&mut StateReader::new(&mut saved.as_slice()));
+        assert!(matches!(result, Err(SnapshotError::Mismatch(_))));
+    }
+}
@@ -0,0 +1,1800 @@
+use crate::bus::{Bus, ADDRESS_SPACE};
+use crate::cpu::registers::{Registers, PRIVILEGE_FLAG};
+use crate::debugger::{Debugger, WatchpointHit};
+use crate::decoder::{decode, Decoded};
+use crate::display::{
+    Framebuffer, PixelDisplay, FRAMEBUFFER_ADDRESS, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_WIDTH,
+    PIXEL_DISPLAY_DECODES, PIXEL_DISPLAY_SIZE,
+};
+use crate::history::History;
+use crate::interrupt::{
+    InterruptController, INTERRUPT_CONTROLLER_END, INTERRUPT_CONTROLLER_START,
+    INTERRUPT_VECTOR_TABLE,
+};
+use crate::io::{IoWindow, IO_WINDOW_END, IO_WINDOW_START};
+use crate::memory::{Ram, RamInit, RAM_SIZE};
+use crate::mmu::{Mmu, MMU_END, MMU_START};
+use crate::mpu::{Access, Mpu, MPU_END, MPU_START};
+use crate::port::{Latch, OutputPort};
+use crate::timer::{Timer, TIMER_END, TIMER_START};
+use log::{error, info, trace};
+mod fpu;
+mod paging;
+pub mod protection;
+pub mod registers;
+mod signed;
+
+use std::fmt;
+
+/// Bits 10-11 of ALU calculate/compare instructions select the operation
+/// group the 4-bit ALU op indexes into.
+pub const ALU_GROUP_INTEGER: u32 = 0;
+pub const ALU_GROUP_SIGNED: u32 = 1;
+pub const ALU_GROUP_FLOAT: u32 = 2;
+
+/// Address of the word holding the `SYS` handler.
+pub const SYSTEM_CALL_VECTOR: usize = 0x40;
+/// `SYS` stores its immediate (the system call number) here.
+pub const SYSTEM_CALL_NUMBER: usize = 0x41;
+/// Address of the word holding the illegal-instruction trap handler.
+pub const ILLEGAL_INSTRUCTION_VECTOR: usize = 0x42;
+/// Address of the word holding the divide-by-zero trap handler.
+pub const DIVIDE_BY_ZERO_VECTOR: usize = 0x43;
+/// Address of the word holding the protection fault handler.
+pub const PROTECTION_FAULT_VECTOR: usize = 0x44;
+/// A protection fault stores the protected address it hit here, or
+/// [`protection::PRIVILEGED_INSTRUCTION`].
+pub const PROTECTION_FAULT_ADDRESS: usize = 0x45;
+/// Address of the word holding the MPU fault handler.
+pub const MEMORY_FAULT_VECTOR: usize = 0x46;
+/// Trapping an MPU fault stores the address it was denied here.
+pub const MEMORY_FAULT_ADDRESS: usize = 0x47;
+/// Address of the word holding the page fault handler, the faulting
+/// address is in the MMU fault registers.
+pub const PAGE_FAULT_VECTOR: usize = 0x50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CPUError {
+    Ok,
+    PcOutOfBounds,
+    Halt,
+    IllegalOpcode(InstructionFault),
+    IllegalAluOp(InstructionFault),
+    IllegalJumpCondition(InstructionFault),
+    IllegalStackOp(InstructionFault),
+    DivideByZero(InstructionFault),
+    /// A user mode access the MPU denied, `sub_op` holds the address.
+    MemoryFault(InstructionFault),
+    /// A translation the MMU could not make, `sub_op` holds the virtual
+    /// address.
+    PageFault(InstructionFault),
+    /// A read of a RAM word nothing wrote, reported after the instruction
+    /// ran when the shadow memory is on (see [`Ram::set_shadow`]).
+    UninitializedRead(UninitializedRead),
+    /// The PC reached an enabled breakpoint; the instruction has not run.
+    Breakpoint(usize),
+    /// An instruction hit a watchpoint, reported after it ran.
+    Watchpoint(WatchpointHit),
+}
+
+/// Where a read of never-written RAM happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UninitializedRead {
+    /// The instruction that read the word, the PC has moved past it.
+    pub pc: usize,
+    pub ir: u32,
+    /// Physical address of the word.
+    pub address: usize,
+}
+
+/// An instruction that could not be executed. The PC is left pointing at
+/// it so the caller can pick a [`FaultAction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InstructionFault {
+    pub pc: usize,
+    pub decoded: Decoded,
+    /// The sub-opcode field of the faulting instruction.
+    pub sub_op: u32,
+}
+
+/// What to do after an instruction fault, see [`CPU::recover`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultAction {
+    /// Leave the CPU at the faulting instruction.
+    Stop,
+    /// Enter supervisor mode at the fault's handler (see
+    /// [`CPUError::trap_vector`]), saving the flags like `SYS` and the
+    /// faulting PC in `reti`.
+    Trap,
+    /// Continue with the next instruction.
+    Skip,
+}
+
+impl CPUError {
+    pub fn instruction_fault(&self) -> Option<&InstructionFault> {
+        match self {
+            CPUError::IllegalOpcode(fault)
+            | CPUError::IllegalAluOp(fault)
+            | CPUError::IllegalJumpCondition(fault)
+            | CPUError::IllegalStackOp(fault)
+            | CPUError::DivideByZero(fault)
+            | CPUError::MemoryFault(fault)
+            | CPUError::PageFault(fault) => Some(fault),
+            _ => None,
+        }
+    }
+    /// Address of the word holding the handler [`FaultAction::Trap`] enters.
+    pub fn trap_vector(&self) -> Option<usize> {
+        match self {
+            CPUError::DivideByZero(_) => Some(DIVIDE_BY_ZERO_VECTOR),
+            CPUError::MemoryFault(_) => Some(MEMORY_FAULT_VECTOR),
+            CPUError::PageFault(_) => Some(PAGE_FAULT_VECTOR),
+            _ => self.instruction_fault().map(|_| ILLEGAL_INSTRUCTION_VECTOR),
+        }
+    }
+}
+
+impl fmt::Display for CPUError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            CPUError::Ok => return write!(f, "None"),
+            CPUError::PcOutOfBounds => return write!(f, "Program Count Out of Bound"),
+            CPUError::Halt => return write!(f, "CPU Halted"),
+            CPUError::UninitializedRead(read) => {
+                return write!(
+                    f,
+                    "Read of uninitialised RAM at 0x{:06x} by PC 0x{:06x} (IR: 0x{:08x})",
+                    read.address, read.pc, read.ir
+                )
+            }
+            CPUError::Breakpoint(pc) => return write!(f, "Breakpoint at PC 0x{:06x}", pc),
+            CPUError::Watchpoint(hit) => {
+                return write!(
+                    f,
+                    "Watchpoint ({}) at 0x{:06x} by PC 0x{:06x}: 0x{:08x} -> 0x{:08x}",
+                    hit.kind, hit.address, hit.pc, hit.old, hit.value
+                )
+            }
+            CPUError::IllegalOpcode(_) => "Illegal opcode",
+            CPUError::IllegalAluOp(_) => "Illegal ALU operation",
+            CPUError::IllegalJumpCondition(_) => "Illegal jump condition",
+            CPUError::IllegalStackOp(_) => "Illegal stack operation",
+            CPUError::DivideByZero(_) => "Divide by zero in ALU operation",
+            CPUError::MemoryFault(_) => "MPU denied access to",
+            CPUError::PageFault(_) => "Page fault on",
+        };
+        let fault = self.instruction_fault().unwrap();
+        let sub_op = match self {
+            CPUError::MemoryFault(_) | CPUError::PageFault(_) => {
+                format!("0x{:06x}", fault.sub_op)
+            }
+            _ => fault.sub_op.to_string(),
+        };
+        write!(
+            f,
+            "{} {} at PC 0x{:06x} (IR: 0x{:08x}, Opcode: 0x{:x}, DR: {}, SR1: {}, SR2: {}, Immediate: 0x{:06x})",
+            kind,
+            sub_op,
+            fault.pc,
+            fault.decoded.ir,
+            fault.decoded.opcode,
+            fault.decoded.dr,
+            fault.decoded.sr1,
+            fault.decoded.sr2,
+            fault.decoded.immediate
+        )
+    }
+}
+
+pub struct CPU {
+    pub registers: Registers,
+    pub bus: Bus,
+    pub log: bool,
+    pub clock: u64,
+    pub opcode: u32,
+    pub ir: u32,
+    pub dr: usize,
+    pub sr2: usize,
+    pub sr1: usize,
+    pub immediate: u32,
+    pub recent_memory_accesses: (u32, u32),
+    /// How RAM was filled, [`CPU::restart`] fills it the same way.
+    pub ram_init: RamInit,
+    pub debugger: Debugger,
+    /// Recorded while reverse debugging is on, see [`CPU::set_history`].
+    pub(crate) history: Option<History>,
+}
+
+impl CPU {
+    pub fn new(initial_ram_content: Vec<u32>, log: bool) -> CPU {
+        return CPU::with_ram_init(initial_ram_content, RamInit::default(), log);
+    }
+    pub fn with_ram_init(initial_ram_content: Vec<u32>, ram_init: RamInit, log: bool) -> CPU {
+        if log {
+            info!("Initializing CPU");
+        }
+        let mut port = OutputPort::new();
+        for decode in PIXEL_DISPLAY_DECODES {
+            let display = PixelDisplay::new(PIXEL_DISPLAY_SIZE, PIXEL_DISPLAY_SIZE);
+            port.attach(decode, Box::new(display));
+        }
+        let mut bus = Bus::new();
+        bus.map(
+            0,
+            RAM_SIZE,
+            Box::new(Ram::new(&initial_ram_content, ram_init, log)),
+        );
+        bus.map(
+            FRAMEBUFFER_ADDRESS,
+            FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT,
+            Box::new(Framebuffer::new()),
+        );
+        bus.map(
+            IO_WINDOW_START,
+            IO_WINDOW_END - IO_WINDOW_START + 1,
+            Box::new(IoWindow::new(port)),
+        );
+        bus.map(
+            INTERRUPT_CONTROLLER_START,
+            INTERRUPT_CONTROLLER_END - INTERRUPT_CONTROLLER_START + 1,
+            Box::new(InterruptController::new()),
+        );
+        bus.map(MMU_START, MMU_END - MMU_START + 1, Box::new(Mmu::new()));
+        bus.map(
+            TIMER_START,
+            TIMER_END - TIMER_START + 1,
+            Box::new(Timer::new()),
+        );
+        bus.map(MPU_START, MPU_END - MPU_START + 1, Box::new(Mpu::new()));
+        return CPU {
+            registers: Registers::new(),
+            bus,
+            log,
+            clock: 0,
+            opcode: 0,
+            ir: 0,
+            dr: 0,
+            sr2: 0,
+            sr1: 0,
+            immediate: 0,
+            recent_memory_accesses: (0, 0),
+            ram_init,
+            debugger: Debugger::new(),
+            history: None,
+        };
+    }
+    pub fn reset(&mut self) {
+        self.registers = Registers::new();
+        self.port_mut().reset();
+        self.interrupts_mut().reset();
+        self.mpu_mut().reset();
+        self.mmu_mut().reset();
+        self.timer_mut().reset();
+        self.checkpoint_history();
+    }
+    /// The output port, which lives in the IO window.
+    pub fn port(&self) -> &OutputPort {
+        &self.bus.device::<IoWindow>().unwrap().port
+    }
+    pub fn port_mut(&mut self) -> &mut OutputPort {
+        &mut self.bus.device_mut::<IoWindow>().unwrap().port
+    }
+    /// The interrupt controller, mapped at `0x7fffc0`.
+    pub fn interrupts(&self) -> &InterruptController {
+        self.bus.device::<InterruptController>().unwrap()
+    }
+    pub fn interrupts_mut(&mut self) -> &mut InterruptController {
+        self.bus.device_mut::<InterruptController>().unwrap()
+    }
+    /// The memory protection unit, mapped at `0x7fffd0`.
+    pub fn mpu(&self) -> &Mpu {
+        self.bus.device::<Mpu>().unwrap()
+    }
+    pub fn mpu_mut(&mut self) -> &mut Mpu {
+        self.bus.device_mut::<Mpu>().unwrap()
+    }
+    /// The paging unit, mapped at `0x7fffc4`.
+    pub fn mmu(&self) -> &Mmu {
+        self.bus.device::<Mmu>().unwrap()
+    }
+    pub fn mmu_mut(&mut self) -> &mut Mmu {
+        self.bus.device_mut::<Mmu>().unwrap()
+    }
+    /// The cycle timer, mapped at `0x7ffff8`.
+    pub fn timer(&self) -> &Timer {
+        self.bus.device::<Timer>().unwrap()
+    }
+    pub fn timer_mut(&mut self) -> &mut Timer {
+        self.bus.device_mut::<Timer>().unwrap()
+    }
+    /// Marks interrupt `line` pending, it is taken before the next
+    /// instruction once unmasked and enabled.
+    pub fn raise_interrupt(&mut self, line: u8) {
+        if self.log {
+            trace!("Interrupt {} raised", line);
+        }
+        self.interrupts_mut().raise(line);
+        self.checkpoint_history();
+    }
+    fn checkpoint_history(&mut self) {
+        if let Some(history) = &mut self.history {
+            history.checkpoint_next();
+        }
+    }
+    /// The RAM behind the bus.
+    pub fn ram(&self) -> &Ram {
+        self.bus.device::<Ram>().unwrap()
+    }
+    pub fn ram_mut(&mut self) -> &mut Ram {
+        self.bus.device_mut::<Ram>().unwrap()
+    }
+    /// Powers on a fresh machine with `initial_ram_content` loaded, keeping
+    /// the RAM init policy, whether the shadow memory is on, whether
+    /// history is recorded and the breakpoints and watchpoints.
+    pub fn reload(&mut self, initial_ram_content: Vec<u32>) {
+        let shadow = self.ram().shadow_enabled();
+        let history = self.history.is_some();
+        let debugger = std::mem::take(&mut self.debugger);
+        *self = CPU::with_ram_init(initial_ram_content, self.ram_init, self.log);
+        if shadow {
+            self.ram_mut().set_shadow_at_power_on();
+        }
+        self.set_history(history);
+        self.debugger = debugger;
+    }
+    pub fn restart(&mut self) {
+        self.reload(Vec::new());
+    }
+    /// Writes the virtual `address`. Instructions check their accesses
+    /// before running, so a failed translation here (only possible while
+    /// entering an interrupt handler) drops the write.
+    fn set_ram(&mut self, address: usize, value: u32) {
+        self.recent_memory_accesses = (address as u32, value);
+        match self.translate(address, Access::Write, !self.registers.privilege) {
+            Ok(physical) => {
+                self.record_write(physical);
+                let bus = &self.bus;
+                self.debugger
+                    .check_write(address, || bus.peek(physical), value);
+                self.bus.write(physical, value);
+            }
+            Err(cause) => {
+                if self.log {
+                    error!("Dropped write to 0x{:06x}: {:?}", address, cause);
+                }
+            }
+        }
+    }
+    /// Reads the virtual `address` as a data load, which watchpoints see.
+    fn get_ram(&mut self, address: usize) -> u32 {
+        let value = self.read_ram(address);
+        self.debugger.check_read(address, value);
+        return value;
+    }
+    /// Reads the virtual `address` for an instruction fetch or a vector,
+    /// which watchpoints do not see.
+    fn read_ram(&mut self, address: usize) -> u32 {
+        let value = match self.translate(address, Access::Read, !self.registers.privilege) {
+            Ok(physical) => self.bus.read(physical),
+            Err(cause) => {
+                if self.log {
+                    error!("Read of 0x{:06x} failed: {:?}", address, cause);
+                }
+                0
+            }
+        };
+        self.recent_memory_accesses = (address as u32, value);
+        return value;
+    }
+    /// Sets the zero and negative flags from `R[dr]` along with the given
+    /// carry/borrow and overflow flags. Every ALU calculate op ends here.
+    pub(crate) fn set_alu_flags(&mut self, dr: usize, carry: bool, overflow: bool) {
+        let result = self.registers[dr];
+        self.registers.zero_f = result == 0;
+        self.registers.negative_f = result >> 31 == 1;
+        self.registers.carry_f = carry;
+        self.registers.overflow_f = overflow;
+        if self.log {
+            trace!(
+                "Flags: Carry = {}, Zero = {}, Overflow = {}, Negative = {}",
+                self.registers.carry_f,
+                self.registers.zero_f,
+                self.registers.overflow_f,
+                self.registers.negative_f
+            );
+        }
+    }
+    fn illegal_instruction(
+        &mut self,
+        kind: fn(InstructionFault) -> CPUError,
+        pc: usize,
+        decoded: Decoded,
+        sub_op: u32,
+    ) -> CPUError {
+        // Leave the PC on the faulting word so the fault is precise
+        self.registers.pc = pc;
+        let error = kind(InstructionFault {
+            pc,
+            decoded,
+            sub_op,
+        });
+        if self.log {
+            error!("{}", error);
+        }
+        return error;
+    }
+    /// Enters supervisor mode at the handler stored at `vector` the way
+    /// `SYS`, traps and interrupts do: `return_pc` goes to `reti`, the flags
+    /// and the mode they left are pushed (already in supervisor mode) and
+    /// interrupts are disabled until `RETI` pops them.
+    fn enter_handler(&mut self, return_pc: usize, vector: usize) -> u32 {
+        self.registers.reti = return_pc as u32;
+        let mut flags = self.registers.flags();
+        if self.registers.privilege {
+            flags |= PRIVILEGE_FLAG;
+        }
+        self.registers.privilege = true;
+        self.registers.interrupt_enable = false;
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.set_ram((self.registers.sp & 0xFFFFFF) as usize, flags);
+        self.registers.pc = (self.read_ram(vector) & 0xFFFFFF) as usize;
+        return flags;
+    }
+    /// Takes the highest priority pending interrupt if interrupts are
+    /// enabled and its line is unmasked.
+    fn take_interrupt(&mut self) -> bool {
+        if !self.registers.interrupt_enable {
+            return false;
+        }
+        let Some(line) = self.interrupts().next() else {
+            return false;
+        };
+        self.interrupts_mut().acknowledge(line);
+        let flags = self.enter_handler(self.registers.pc, INTERRUPT_VECTOR_TABLE + line as usize);
+        if self.log {
+            trace!(
+                "Interrupt {}: PC = {}, Flags = 0x{:X}",
+                line,
+                self.registers.pc,
+                flags
+            );
+        }
+        self.clock += 3;
+        return true;
+    }
+    /// Resumes after an instruction fault returned by
+    /// [`CPU::execute_instruction`]. Does nothing for other errors.
+    pub fn recover(&mut self, error: &CPUError, action: FaultAction) {
+        let (Some(fault), Some(vector)) = (error.instruction_fault(), error.trap_vector()) else {
+            return;
+        };
+        self.checkpoint_history();
+        match action {
+            FaultAction::Stop => {
+                self.registers.pc = fault.pc;
+            }
+            FaultAction::Skip => {
+                self.registers.pc = fault.pc + 1;
+            }
+            FaultAction::Trap => {
+                let flags = self.enter_handler(fault.pc, vector);
+                if let CPUError::MemoryFault(fault) = error {
+                    self.set_ram(MEMORY_FAULT_ADDRESS, fault.sub_op);
+                }
+                if self.log {
+                    trace!(
+                        "Fault Trap: PC = {}, Flags = 0x{:X}",
+                        self.registers.pc,
+                        flags
+                    );
+                }
+            }
+        }
+    }
+    /// Executes one instruction, or enters an interrupt handler instead if
+    /// one is due. `interrupt` raises line `interrupt_number` first.
+    pub fn execute_instruction(&mut self, interrupt: bool, interrupt_number: u8) -> CPUError {
+        if interrupt {
+            self.raise_interrupt(interrupt_number);
+        }
+        let pc = self.registers.pc;
+        if self.debugger.check_breakpoint(pc) {
+            if self.log {
+                info!("Breakpoint at 0x{:06x}", pc);
+            }
+            return CPUError::Breakpoint(pc);
+        }
+        self.debugger.clear_hit();
+        self.record_history();
+        let error = self.execute();
+        let uninitialized_read = self.ram_mut().take_uninitialized_read();
+        // A fault or halt says more about what went wrong
+        if error != CPUError::Ok {
+            return error;
+        }
+        let error = if let Some(address) = uninitialized_read {
+            CPUError::UninitializedRead(UninitializedRead {
+                pc,
+                ir: self.ir,
+                address,
+            })
+        } else if let Some(hit) = self.debugger.take_hit(pc) {
+            CPUError::Watchpoint(hit)
+        } else {
+            return CPUError::Ok;
+        };
+        if self.log {
+            error!("{}", error);
+        }
+        return error;
+    }
+    fn execute(&mut self) -> CPUError {
+        self.bus.tick(self.clock);
+        let lines = self.bus.poll_interrupts();
+        self.interrupts_mut().pending |= lines;
+        if self.take_interrupt() {
+            return CPUError::Ok;
+        }
+        // Fetch instruction from memory
+        if self.registers.pc >= ADDRESS_SPACE {
+            if self.log {
+                error!("PC out of bounds");
+            }
+            return CPUError::PcOutOfBounds;
+        }
+        if !self.registers.privilege && protection::is_supervisor_address(self.registers.pc) {
+            self.protection_fault(self.registers.pc, self.registers.pc as u32);
+            return CPUError::Ok;
+        }
+        let user = !self.registers.privilege;
+        let pc = self.registers.pc;
+        let physical_pc = match self.translate(pc, Access::Execute, user) {
+            Ok(physical) => physical,
+            Err(_) => {
+                return self.illegal_instruction(CPUError::PageFault, pc, decode(0), pc as u32);
+            }
+        };
+        if user && !self.mpu().allows(physical_pc, Access::Execute) {
+            let decoded = decode(self.bus.peek(physical_pc));
+            return self.illegal_instruction(CPUError::MemoryFault, pc, decoded, pc as u32);
+        }
+        let instr = self.read_ram(self.registers.pc);
+        if self.log {
+            trace!("PC: {}, Instruction: {}", self.registers.pc, instr);
+        }
+        let fault_pc = self.registers.pc;
+        self.registers.pc += 1;
+        self.clock += 2;
+        // Decode & Execute instruction
+        let decoded = decode(instr);
+        let Decoded {
+            ir,
+            opcode,
+            dr,
+            sr2,
+            sr1,
+            immediate,
+        } = decoded;
+        self.ir = ir;
+        self.opcode = opcode;
+        self.dr = dr;
+        self.sr2 = sr2;
+        self.sr1 = sr1;
+        self.immediate = immediate;
+        if !self.registers.privilege {
+            if let Some(cause) = self.user_violation(instr, decoded) {
+                self.protection_fault(fault_pc, cause);
+                return CPUError::Ok;
+            }
+        }
+        if let Some(address) = self.page_fault_address(instr, decoded) {
+            return self.illegal_instruction(
+                CPUError::PageFault,
+                fault_pc,
+                decoded,
+                address as u32,
+            );
+        }
+        if !self.registers.privilege {
+            // Every access translates now, the MPU checks where it lands
+            let denied =
+                self.data_accesses(instr, decoded)
+                    .into_iter()
+                    .find(|&(address, access)| {
+                        let physical = self.translate(address, access, true).unwrap_or(address);
+                        !self.mpu().allows(physical, access)
+                    });
+            if let Some((address, _)) = denied {
+                return self.illegal_instruction(
+                    CPUError::MemoryFault,
+                    fault_pc,
+                    decoded,
+                    (address & 0xFFFFFF) as u32,
+                );
+            }
+        }
+        match opcode {
+            0 => {
+                // NOP
+                if self.log {
+                    trace!("NOP");
+                }
+                self.clock += 1;
+                return CPUError::Ok;
+            }
+            1 => {
+                // ALU Calculate
+                let alu_op = (instr >> 12) & 0x0F;
+                let alu_group = (instr >> 10) & 0x03;
+                if self.log {
+                    trace!("ALU Group: {}, Opcode: {}", alu_group, alu_op);
+                }
+                match alu_group {
+                    ALU_GROUP_INTEGER => {}
+                    ALU_GROUP_SIGNED => return self.execute_signed(fault_pc, decoded, alu_op),
+                    ALU_GROUP_FLOAT => return self.execute_fpu(fault_pc, decoded, alu_op),
+                    _ => {
+                        return self.illegal_instruction(
+                            CPUError::IllegalAluOp,
+                            fault_pc,
+                            decoded,
+                            alu_op,
+                        );
+                    }
+                }
+                let a = self.registers[sr1];
+                let b = self.registers[sr2];
+                // Shifts & rotates use the low 5 bits of the amount
+                let n = b & 0x1F;
+                let (carry, overflow) = match alu_op {
+                    0 => {
+                        // ADD
+                        let (result, carry) = a.overflowing_add(b);
+                        self.registers[dr] = result;
+                        if self.log {
+                            trace!("ADD: R{} = R{} + R{}", dr, sr1, sr2);
+                        }
+                        (carry, (a as i32).overflowing_add(b as i32).1)
+                    }
+                    1 => {
+                        // SUB, Carry is the borrow
+                        let (result, borrow) = a.overflowing_sub(b);
+                        self.registers[dr] = result;
+                        if self.log {
+                            trace!("SUB: R{} = R{} - R{}", dr, sr1, sr2);
+                        }
+                        (borrow, (a as i32).overflowing_sub(b as i32).1)
+                    }
+                    2 => {
+                        // MUL
+                        let (result, carry) = a.overflowing_mul(b);
+                        self.registers[dr] = result;
+                        if self.log {
+                            trace!("MUL: R{} = R{} * R{}", dr, sr1, sr2);
+                        }
+                        (carry, (a as i32).overflowing_mul(b as i32).1)
+                    }
+                    3 => {
+                        // Nig, Carry is the borrow of 0 - R[sr1]
+                        self.registers[dr] = a.wrapping_neg();
+                        if self.log {
+                            trace!("Nig: R{} = R{}", dr, sr1);
+                        }
+                        (a != 0, a == 0x80000000)
+                    }
+                    4 => {
+                        // And
+                        self.registers[dr] = a & b;
+                        if self.log {
+                            trace!("AND: R{} = R{} & R{}", dr, sr1, sr2);
+                        }
+                        (false, false)
+                    }
+                    5 => {
+                        // OR
+                        self.registers[dr] = a | b;
+                        if self.log {
+                            trace!("OR: R{} = R{} | R{}", dr, sr1, sr2);
+                        }
+                        (false, false)
+                    }
+                    6 => {
+                        // Not
+                        self.registers[dr] = !a;
+                        if self.log {
+                            trace!("NOT: R{} = !R{}", dr, sr1);
+                        }
+                        (false, false)
+                    }
+                    7 => {
+                        // NAnd
+                        self.registers[dr] = !(a & b);
+                        if self.log {
+                            trace!("NAND: R{} = !(R{} & R{})", dr, sr1, sr2);
+                        }
+                        (false, false)
+                    }
+                    8 => {
+                        // XOr
+                        self.registers[dr] = a ^ b;
+                        if self.log {
+                            trace!("XOR: R{} = R{} ^ R{}", dr, sr1, sr2);
+                        }
+                        (false, false)
+                    }
+                    9 => {
+                        // XNor
+                        self.registers[dr] = !(a ^ b);
+                        if self.log {
+                            trace!("XNOR: R{} = !(R{} ^ R{})", dr, sr1, sr2);
+                        }
+                        (false, false)
+                    }
+                    10 => {
+                        // SFT Log Left, Carry is the last bit shifted out
+                        self.registers[dr] = a.wrapping_shl(b);
+                        if self.log {
+                            trace!("SFT_LOG_LEFT: R{} = R{} << R{}", dr, sr1, sr2);
+                        }
+                        (n != 0 && (a >> (32 - n)) & 0x01 == 1, false)
+                    }
+                    11 => {
+                        // SFT Log Right, Carry is the last bit shifted out
+                        self.registers[dr] = a.wrapping_shr(b);
+                        if self.log {
+                            trace!("SFT_LOG_RIGHT: R{} = R{} >> R{}", dr, sr1, sr2);
+                        }
+                        (n != 0 && (a >> (n - 1)) & 0x01 == 1, false)
+                    }
+                    12 => {
+                        // ArtM Right, Carry is the last bit shifted out
+                        self.registers[dr] = (a as i32).wrapping_shr(b) as u32;
+                        if self.log {
+                            trace!("ARTM_RIGHT: R{} = R{} >> R{}", dr, sr1, sr2);
+                        }
+                        (n != 0 && (a >> (n - 1)) & 0x01 == 1, false)
+                    }
+                    13 => {
+                        // Rotate Left, Carry is the bit rotated into bit 0
+                        self.registers[dr] = a.rotate_left(b);
+                        if self.log {
+                            trace!("ROTATE_LEFT: R{} = R{} rotate_left R{}", dr, sr1, sr2);
+                        }
+                        (n != 0 && self.registers[dr] & 0x01 == 1, false)
+                    }
+                    14 => {
+                        // Rotate Right, Carry is the bit rotated into bit 31
+                        self.registers[dr] = a.rotate_right(b);
+                        if self.log {
+                            trace!("ROTATE_RIGHT: R{} = R{} rotate_right R{}", dr, sr1, sr2);
+                        }
+                        (n != 0 && self.registers[dr] >> 31 == 1, false)
+                    }
+                    15 => {
+                        // Default 0
+                        self.registers[dr] = 0;
+                        if self.log {
+                            trace!("DEFAULT: R{} = 0", dr);
+                        }
+                        (false, false)
+                    }
+                    _ => {
+                        return self.illegal_instruction(
+                            CPUError::IllegalAluOp,
+                            fault_pc,
+                            decoded,
+                            alu_op,
+                        );
+                    }
+                };
+                self.set_alu_flags(dr, carry, overflow);
+                self.clock += 1;
+                return CPUError::Ok;
+            }
+            2 => {
+                // ALU Compare
+                let alu_op = (instr >> 12) & 0x0F;
+                let alu_group = (instr >> 10) & 0x03;
+                match alu_group {
+                    ALU_GROUP_INTEGER => {}
+                    ALU_GROUP_SIGNED => return self.compare_signed(decoded, alu_op),
+                    ALU_GROUP_FLOAT => return self.compare_fpu(decoded, alu_op),
+                    _ => {
+                        return self.illegal_instruction(
+                            CPUError::IllegalAluOp,
+                            fault_pc,
+                            decoded,
+                            alu_op,
+                        );
+                    }
+                }
+                match alu_op {
+                    0 => {
+                        // Greater Than
+                        self.registers.comp_f = self.registers[sr1] > self.registers[sr2];
+                        if self.log {
+                            trace!("ALU_COMPARE: R{} > R{}", sr1, sr2);
+                            trace!("Flags: Comp = {}", self.registers.comp_f);
+                        }
+                    }
+                    1 => {
+                        // Equal To
+                        self.registers.comp_f = self.registers[sr1] == self.registers[sr2];
+                        if self.log {
+                            trace!("ALU_COMPARE: R{} == R{}", sr1, sr2);
+                            trace!("Flags: Comp = {}", self.registers.comp_f);
+                        }
+                    }
+                    2 => {
+                        // Less Than
+                        self.registers.comp_f = self.registers[sr1] < self.registers[sr2];
+                        if self.log {
+                            trace!("ALU_COMPARE: R{} < R{}", sr1, sr2);
+                            trace!("Flags: Comp = {}", self.registers.comp_f);
+                        }
+                    }
+                    3 => {
+                        // Greater Than or Equal To
+                        self.registers.comp_f = self.registers[sr1] >= self.registers[sr2];
+                        if self.log {
+                            trace!("ALU_COMPARE: R{} >= R{}", sr1, sr2);
+                            trace!("Flags: Comp = {}", self.registers.comp_f);
+                        }
+                    }
+                    4 => {
+                        // Less Than or Equal To
+                        self.registers.comp_f = self.registers[sr1] <= self.registers[sr2];
+                        if self.log {
+                            trace!("ALU_COMPARE: R{} <= R{}", sr1, sr2);
+                            trace!("Flags: Comp = {}", self.registers.comp_f);
+                        }
+                    }
+                    5..16 => {
+                        // Default False
+                        self.registers.comp_f = false;
+                        if self.log {
+                            trace!("ALU_COMPARE: Default False");
+                            trace!("Flags: Comp = false");
+                        }
+                    }
+                    _ => {
+                        return self.illegal_instruction(
+                            CPUError::IllegalAluOp,
+                            fault_pc,
+                            decoded,
+                            alu_op,
+                        );
+                    }
+                }
+                self.clock += 1;
+                return CPUError::Ok;
+            }
+            3 => {
+                // Jump
+                // The condition is 4 bits: bits 24-26 plus bit 23 of the
+                // target for immediate jumps (program memory is 23 bit) or
+                // bit 19 for register jumps.
+                let (jmp_if, target) = if instr >> 27 & 0x01 == 1 {
+                    // Jump Immediate
+                    (
+                        (instr >> 24) & 0x07 | (instr >> 20) & 0x08,
+                        (immediate & 0x7FFFFF) as usize,
+                    )
+                } else {
+                    // Jump Register
+                    (
+                        (instr >> 24) & 0x07 | (instr >> 16) & 0x08,
+                        (self.registers[sr2] & 0xFFFFFF) as usize,
+                    )
+                };
+                if self.log {
+                    trace!("Jump Opcode: {}", jmp_if);
+                }
+                let flags = &self.registers;
+                let jump = match jmp_if {
+                    // Jump if Any
+                    0 => true,
+                    // Jump if Carry
+                    1 => flags.carry_f,
+                    // Jump if Not Carry
+                    2 => !flags.carry_f,
+                    // Jump if Comp
+                    3 => flags.comp_f,
+                    // Jump if Not Comp
+                    4 => !flags.comp_f,
+                    // Jump if Zero
+                    5 => flags.zero_f,
+                    // Jump if Not Zero
+                    6 => !flags.zero_f,
+                    // Default Jump
+                    7 => true,
+                    // Jump if Overflow
+                    8 => flags.overflow_f,
+                    // Jump if Not Overflow
+                    9 => !flags.overflow_f,
+                    // Jump if Negative
+                    10 => flags.negative_f,
+                    // Jump if Not Negative
+                    11 => !flags.negative_f,
+                    // Signed >
+                    12 => !flags.zero_f && flags.negative_f == flags.overflow_f,
+                    // Signed <=
+                    13 => flags.zero_f || flags.negative_f != flags.overflow_f,
+                    // Signed >=
+                    14 => flags.negative_f == flags.overflow_f,
+                    // Signed <
+                    15 => flags.negative_f != flags.overflow_f,
+                    _ => {
+                        return self.illegal_instruction(
+                            CPUError::IllegalJumpCondition,
+                            fault_pc,
+                            decoded,
+                            jmp_if,
+                        );
+                    }
+                };
+                if jump {
+                    self.registers.pc = target;
+                    if self.log {
+                        trace!("Jumping to: {}", target);
+                    }
+                }
+                self.clock += 1;
+                return CPUError::Ok;
+            }
+            4 => {
+                // Load Full-bit
+                self.registers[sr1] = self.get_ram(immediate as usize);
+                self.clock += 2;
+                if self.log {
+                    trace!(
+                        "Load Full-bit: Register[{}] = RAM[{}]",
+                        sr1,
+                        immediate as usize
+                    );
+                }
+                return CPUError::Ok;
+            }
+            5 => {
+                // Load From Reg
+                self.clock += 2;
+                self.registers[dr] = self.get_ram(self.registers[sr2] as usize);
+                if self.log {
+                    trace!("Load From Reg: Register[{}] = Register[{}]", dr, sr2);
+                }
+                return CPUError::Ok;
+            }
+            6 => {
+                // Load Immediate
+                self.clock += 1;
+                self.registers[sr1] = immediate;
+                if self.log {
+                    trace!("Load Immediate: Register[{}] = {}", sr1, immediate);
+                }
+                return CPUError::Ok;
+            }
+            7 => {
+                // Store
+                self.set_ram(immediate as usize, self.registers[sr1]);
+                self.clock += 1;
+                if self.log {
+                    trace!("Store: RAM[{}] = Register[{}]", immediate as usize, sr1);
+                }
+                return CPUError::Ok;
+            }
+            8 => {
+                // Store To
+                self.set_ram(
+                    (self.registers[sr2] & 0xFFFFFF) as usize,
+                    self.registers[sr1],
+                );
+                self.clock += 1;
+                if self.log {
+                    trace!(
+                        "Store To: RAM[{}] = Register[{}]",
+                        self.registers[sr2] & 0xFFFFFF,
+                        sr1
+                    );
+                }
+                return CPUError::Ok;
+            }
+            9 => {
+                // Mov
+                self.registers[dr] = self.registers[sr1];
+                self.clock += 1;
+                if self.log {
+                    trace!("Mov: Register[{}] = Register[{}]", dr, sr1);
+                }
+                return CPUError::Ok;
+            }
+            10 => {
+                // Output Port
+                if instr >> 7 & 0x01 == 1 {
+                    // Write Decode Register
+                    let decode = (instr >> 9 & 0x07) as u8;
+                    self.port_mut().set_decode(decode);
+                    if self.log {
+                        trace!("Output Port Decode = {}", decode);
+                    }
+                } else {
+                    // Write Data Latch
+                    let latch = if instr >> 8 & 0x01 == 1 {
+                        Latch::D2
+                    } else {
+                        Latch::D1
+                    };
+                    let value = self.registers[sr1];
+                    self.port_mut().write(latch, value);
+                    if self.log {
+                        trace!("Output Port {:?} = Register[{}]", latch, sr1);
+                    }
+                }
+                self.clock += 1;
+                return CPUError::Ok;
+            }
+            11 => {
+                // Push
+                self.registers.sp = self.registers.sp.wrapping_sub(1);
+                self.set_ram((self.registers.sp & 0xFFFFFF) as usize, self.registers[sr1]);
+                if self.log {
+                    trace!(
+                        "Push: RAM[{}] = Register[{}]",
+                        self.registers.sp & 0xFFFFFF,
+                        sr1
+                    );
+                }
+                self.clock += 1;
+                return CPUError::Ok;
+            }
+            12 => {
+                // Stack Operation
+                let stack_op = (instr >> 22) & 0x03;
+                if self.log {
+                    trace!("Stack Operation Opcode: {}", stack_op);
+                }
+                match stack_op {
+                    0 => {
+                        // Push
+                        self.registers.sp = self.registers.sp.wrapping_sub(1);
+                        self.set_ram((self.registers.sp & 0xFFFFFF) as usize, self.registers[sr1]);
+                        if self.log {
+                            trace!(
+                                "Push: RAM[{}] = Register[{}]",
+                                self.registers.sp & 0xFFFFFF,
+                                sr1
+                            );
+                        }
+                        self.clock += 1;
+                        return CPUError::Ok;
+                    }
+                    1 => {
+                        // Pop
+                        self.registers[dr] = self.get_ram((self.registers.sp & 0xFFFFFF) as usize);
+                        self.registers.sp = self.registers.sp.wrapping_add(1);
+                        if self.log {
+                            trace!(
+                                "Pop: Register[{}] = RAM[{}]",
+                                dr,
+                                (self.registers.sp.wrapping_sub(1) & 0xFFFFFF) as usize
+                            );
+                        }
+                        self.clock += 1;
+                        return CPUError::Ok;
+                    }
+                    2 => {
+                        // Top
+                        self.registers[dr] = self.get_ram(self.registers.sp as usize);
+                        if self.log {
+                            trace!(
+                                "Top: Register[{}] = RAM[{}]",
+                                dr,
+                                self.registers.sp & 0xFFFFFF
+                            );
+                        }
+                        self.clock += 1;
+                        return CPUError::Ok;
+                    }
+                    3 => {
+                        // Default 0
+                        self.registers[dr] = 0;
+                        if self.log {
+                            trace!("Default 0: Register[{}] = 0", dr);
+                        }
+                        self.clock += 1;
+                        return CPUError::Ok;
+                    }
+                    _ => {
+                        return self.illegal_instruction(
+                            CPUError::IllegalStackOp,
+                            fault_pc,
+                            decoded,
+                            stack_op,
+                        );
+                    }
+                }
+            }
+            13 => {
+                // Function
+                let ret = instr >> 25 & 0x01;
+                if self.log {
+                    trace!(
+                        "Function Operation: {}",
+                        if ret == 1 { "Return" } else { "Call" }
+                    );
+                }
+                if ret == 1 {
+                    // Return
+                    self.registers.pc =
+                        self.get_ram((self.registers.sp & 0xFFFFFF) as usize) as usize;
+                    self.registers.sp = self.registers.sp.wrapping_add(1);
+                    let flags = self.get_ram((self.registers.sp & 0xFFFFFF) as usize);
+                    self.registers.sp = self.registers.sp.wrapping_add(1);
+                    // EI / DI inside a function outlive its return
+                    let interrupt_enable = self.registers.interrupt_enable;
+                    self.registers.set_flags(flags);
+                    self.registers.interrupt_enable = interrupt_enable;
+                    if self.log {
+                        trace!("Return: PC = {}, Flags = 0x{:X}", self.registers.pc, flags);
+                    }
+                    self.clock += 2;
+                    return CPUError::Ok;
+                } else {
+                    // Call
+                    self.registers.sp = self.registers.sp.wrapping_sub(1);
+                    self.set_ram(
+                        (self.registers.sp & 0xFFFFFF) as usize,
+                        (self.registers.pc & 0xFFFFFF) as u32,
+                    );
+                    self.registers.pc = immediate as usize;
+                    self.registers.sp = self.registers.sp.wrapping_sub(1);
+                    let flags = self.registers.flags();
+                    self.set_ram((self.registers.sp & 0xFFFFFF) as usize, flags);
+                    if self.log {
+                        trace!("Call: PC = {}, Flags = 0x{:X}", immediate, flags);
+                    }
+                    self.clock += 3;
+                    return CPUError::Ok;
+                }
+            }
+            14 => {
+                // System
+                let do_reti = instr >> 27 & 0x01;
+                let interrupt_control = instr >> 26 & 0x01;
+                if do_reti == 0 && interrupt_control == 1 {
+                    // Enable / Disable Interrupts
+                    self.registers.interrupt_enable = instr >> 25 & 0x01 == 1;
+                    if self.log {
+                        trace!("Interrupts Enabled: {}", self.registers.interrupt_enable);
+                    }
+                    self.clock += 1;
+                    return CPUError::Ok;
+                }
+                if do_reti == 1 && interrupt_control == 1 {
+                    // Set RETI, so the kernel can enter user code with RETI
+                    self.registers.reti = self.registers[sr2] & 0xFFFFFF;
+                    if self.log {
+                        trace!("Set RETI: RETI = Register[{}]", sr2);
+                    }
+                    self.clock += 1;
+                    return CPUError::Ok;
+                }
+                if do_reti == 1 {
+                    // Return from System Call
+                    let flags = self.get_ram((self.registers.sp & 0xFFFFFF) as usize);
+                    self.registers.set_flags(flags);
+                    self.registers.sp = self.registers.sp.wrapping_add(1);
+                    self.registers.privilege = flags & PRIVILEGE_FLAG != 0;
+                    self.registers.pc = self.registers.reti as usize;
+                    if self.log {
+                        trace!(
+                            "Return from System Call: PC = {}, Flags = 0x{:X}",
+                            self.registers.pc,
+                            flags
+                        );
+                    }
+                    self.clock += 3;
+                    return CPUError::Ok;
+                } else {
+                    // System Call
+                    let flags = self.enter_handler(self.registers.pc, SYSTEM_CALL_VECTOR);
+                    self.set_ram(SYSTEM_CALL_NUMBER, immediate);
+                    if self.log {
+                        trace!(
+                            "System Call: PC = {}, Flags = 0x{:X}",
+                            self.registers.pc,
+                            flags
+                        );
+                    }
+                    self.clock += 3;
+                    return CPUError::Ok;
+                }
+            }
+            15 => {
+                // Halt
+                if self.log {
+                    trace!("Halting CPU");
+                }
+                self.clock += 1;
+                return CPUError::Halt;
+            }
+            _ => {
+                return self.illegal_instruction(
+                    CPUError::IllegalOpcode,
+                    fault_pc,
+                    decoded,
+                    opcode,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interrupt::INTERRUPT_LINES;
+    use crate::mmu::{PAGE_BITS, PTE_PRESENT, PTE_USER, PTE_WRITE};
+    use crate::mpu::{MpuRegion, MPU_EXECUTE, MPU_READ};
+    use crate::{Assembler, WatchKind};
+
+    /// Bank for test programs, which puts address 0 at word 0.
+    pub(super) const BANK: &str = "#bankdef test {\n#bits 32\n#outp 0\n}\n";
+
+    /// A CPU with `source` assembled with `astCPU.asm` in zeroed RAM.
+    pub(super) fn load(source: &str) -> CPU {
+        let mut assembler = Assembler::new();
+        assembler.add_source("test.asm", source);
+        let words = assembler.assemble().unwrap();
+        return CPU::with_ram_init(words, RamInit::Zero, false);
+    }
+
+    /// Runs `source` for `steps` instructions.
+    pub(super) fn run(source: &str, steps: usize) -> CPU {
+        let mut cpu = load(source);
+        for _ in 0..steps {
+            assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        }
+        return cpu;
+    }
+
+    #[test]
+    fn loads_go_to_the_register_the_assembler_encodes() {
+        let source = format!(
+            "{}LDI R1 - 0x1234\nLD R2 - value\nvalue:\n#d32 0xcafe\n",
+            BANK
+        );
+        let cpu = run(&source, 2);
+        assert_eq!(cpu.registers[1], 0x1234);
+        assert_eq!(cpu.registers[2], 0xcafe);
+        assert_eq!(cpu.registers[0], 0);
+    }
+
+    #[test]
+    fn breakpoints_stop_before_and_pass_over_once() {
+        let mut cpu = load(&format!("{}NOP\nLDI R1 - 1\nLDI R2 - 2\n", BANK));
+        cpu.debugger.add_breakpoint(1);
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Breakpoint(1));
+        assert_eq!(cpu.registers.pc, 1);
+        assert_eq!(cpu.registers[1], 0);
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert_eq!(cpu.registers[1], 1);
+        cpu.registers.pc = 1;
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Breakpoint(1));
+        cpu.debugger.breakpoints[0].enabled = false;
+        cpu.registers.pc = 1;
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+    }
+
+    #[test]
+    fn read_watchpoints_see_loads_but_not_fetches_or_vectors() {
+        let source = format!(
+            "{}LDI R1 - 5\nST R1 - 0x30\nLD R2 - 0x30\nSYS 0\n#d32 0xf0000000\n",
+            BANK
+        );
+        let mut cpu = load(&source);
+        cpu.bus.write(SYSTEM_CALL_VECTOR, 4);
+        cpu.debugger
+            .add_watchpoint(0, SYSTEM_CALL_VECTOR, WatchKind::Read);
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        let CPUError::Watchpoint(hit) = cpu.execute_instruction(false, 0) else {
+            panic!("the load did not hit the watchpoint");
+        };
+        assert_eq!(
+            (hit.pc, hit.address, hit.kind, hit.value),
+            (2, 0x30, WatchKind::Read, 5)
+        );
+        assert_eq!(cpu.registers[2], 5);
+        // SYS reads its vector at 0x40 and the handler at 4 is fetched
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert_eq!(cpu.registers.pc, 4);
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Halt);
+    }
+
+    #[test]
+    fn change_watchpoints_skip_stores_of_the_same_value() {
+        let source = format!("{}LDI R1 - 5\nST R1 - 0x30\nST R1 - 0x30\n", BANK);
+        let mut cpu = load(&source);
+        cpu.debugger.add_watchpoint(0x30, 0x30, WatchKind::Change);
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        let CPUError::Watchpoint(hit) = cpu.execute_instruction(false, 0) else {
+            panic!("the store did not hit the watchpoint");
+        };
+        assert_eq!((hit.old, hit.value), (0, 5));
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        cpu.debugger.watchpoints[0].kind = WatchKind::Write;
+        cpu.registers.pc = 2;
+        assert!(matches!(
+            cpu.execute_instruction(false, 0),
+            CPUError::Watchpoint(_)
+        ));
+    }
+
+    #[test]
+    fn the_test_example_plots_on_the_second_display() {
+        let mut cpu = load(include_str!("../../../examples/test.asm"));
+        let mut steps = 0;
+        while cpu.execute_instruction(false, 0) == CPUError::Ok {
+            steps += 1;
+            assert!(steps < 1000, "examples/test.asm does not halt");
+        }
+        for decode in PIXEL_DISPLAY_DECODES {
+            let display = cpu.port().device::<PixelDisplay>(decode);
+            let pixels = &display.unwrap().pixels;
+            if decode == 2 {
+                assert_eq!(pixels[..11], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0]);
+            } else {
+                assert!(pixels.iter().all(|&pixel| pixel == 0));
+            }
+        }
+    }
+
+    #[test]
+    fn the_io_window_drives_the_output_port() {
+        let source = format!(
+            "{}LDI R1 - 2\nST R1 - 0x7fffa1\nLDI R1 - 7\nST R1 - 0x7fffa2\n\
+            LDI R1 - 0x123\nST R1 - 0x7fffa3\nLD R2 - 0x7fffa0\nLD R3 - 0x7fffa2\n",
+            BANK
+        );
+        let cpu = run(&source, 8);
+        assert_eq!(
+            (cpu.port().decode, cpu.port().d1, cpu.port().d2),
+            (2, 7, 0x123)
+        );
+        assert_eq!(
+            cpu.port().device::<PixelDisplay>(2).unwrap().pixels[7],
+            0x123
+        );
+        let attached: u32 = PIXEL_DISPLAY_DECODES.iter().map(|d| 1 << d).sum();
+        assert_eq!(cpu.registers[2], attached << 8 | 1 << 3 | 2);
+        assert_eq!(cpu.registers[3], 7);
+    }
+
+    #[test]
+    fn port_writes_reach_the_selected_display() {
+        let mut source = BANK.to_string();
+        for decode in PIXEL_DISPLAY_DECODES {
+            source += &format!(
+                "OPW-En - {}\nLDI R1 - {}\nOPD1W - R1\nLDI R2 - 0x{:x}\nOPD2W - R2\n",
+                decode,
+                decode,
+                0x100000 * decode as u32
+            );
+        }
+        let cpu = run(&source, 5 * PIXEL_DISPLAY_DECODES.len());
+        for decode in PIXEL_DISPLAY_DECODES {
+            let display = cpu.port().device::<PixelDisplay>(decode).unwrap();
+            let plotted: Vec<(usize, u32)> = display
+                .pixels
+                .iter()
+                .copied()
+                .enumerate()
+                .filter(|&(_, pixel)| pixel != 0)
+                .collect();
+            assert_eq!(plotted, [(decode as usize, 0x100000 * decode as u32)]);
+        }
+    }
+
+    #[test]
+    fn the_shadow_memory_does_not_cover_the_framebuffer() {
+        let source = format!(
+            "{}PUSH R1\nPOP R2\nPOP R3\nLD R4 - 0xfb5000\nLD R5 - 0x30\n",
+            BANK
+        );
+        let mut cpu = load(&source);
+        cpu.ram_mut().set_shadow_at_power_on();
+        cpu.registers.sp = FRAMEBUFFER_ADDRESS as u32 + 2;
+        // Stack words and pixels read as written, never-written RAM does not
+        for _ in 0..4 {
+            assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        }
+        let CPUError::UninitializedRead(read) = cpu.execute_instruction(false, 0) else {
+            panic!("the read of never-written RAM was not reported");
+        };
+        assert_eq!((read.address, read.pc), (0x30, 4));
+    }
+
+    /// A kernel that drops to user code, whose `SYS 1` enters the handler
+    /// at 0x60, which returns with `RETI`.
+    const KERNEL: &str = "LDI R1 - user\nSTRETI R1\nRETI\nuser:\nSYS 1\nHLT\n\
+        #addr 0x40\n#d32 handler\n#addr 0x60\nhandler:\nRETI\n";
+
+    #[test]
+    fn traps_from_user_mode_return_to_user_mode() {
+        let mut cpu = load(&format!("{}{}", BANK, KERNEL));
+        cpu.registers.sp = 0x1000;
+        for _ in 0..3 {
+            assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        }
+        assert!(!cpu.registers.privilege);
+        assert_eq!(cpu.registers.pc, 3);
+
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert!(cpu.registers.privilege);
+        assert_eq!(cpu.registers.pc, 0x60);
+        assert_eq!(cpu.read_ram(0xfff) & PRIVILEGE_FLAG, 0);
+
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert!(!cpu.registers.privilege);
+        assert_eq!(cpu.registers.pc, 4);
+    }
+
+    #[test]
+    fn traps_from_supervisor_mode_return_to_supervisor_mode() {
+        let mut cpu = load(&format!("{}SYS 2\nHLT\n{}", BANK, KERNEL));
+        cpu.registers.sp = 0x1000;
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert!(cpu.registers.privilege);
+        assert_eq!(cpu.registers.pc, 0x60);
+        assert_ne!(cpu.read_ram(0xfff) & PRIVILEGE_FLAG, 0);
+
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert!(cpu.registers.privilege);
+        assert_eq!(cpu.registers.pc, 1);
+        assert_eq!(cpu.registers.sp, 0x1000);
+    }
+
+    /// Runs `steps` instructions of `words` with R1 = `a`, R2 = `b` and
+    /// R4 = 0x10.
+    fn run_words(words: &[u32], a: u32, b: u32, steps: usize) -> CPU {
+        let mut cpu = CPU::with_ram_init(words.to_vec(), RamInit::Zero, false);
+        cpu.registers[1] = a;
+        cpu.registers[2] = b;
+        cpu.registers[4] = 0x10;
+        for _ in 0..steps {
+            assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        }
+        return cpu;
+    }
+
+    fn assemble(source: &str) -> Vec<u32> {
+        let mut assembler = Assembler::new();
+        assembler.add_source("test.asm", format!("{}{}", BANK, source));
+        return assembler.assemble().unwrap();
+    }
+
+    #[test]
+    fn add_sub_and_shift_flags() {
+        // Result, then the carry, overflow and negative flags
+        let cases = [
+            ("ADD", 0xFFFFFFFF, 1, 0, true, false, false),
+            ("ADD", 0x7FFFFFFF, 1, 0x80000000, false, true, true),
+            ("ADD", 0x80000000, 0x80000000, 0, true, true, false),
+            ("ADD", 0xFFFFFFFF, 0xFFFFFFFF, 0xFFFFFFFE, true, false, true),
+            ("ADD", 1, 1, 2, false, false, false),
+            ("SUB", 0, 1, 0xFFFFFFFF, true, false, true),
+            ("SUB", 0x80000000, 1, 0x7FFFFFFF, false, true, false),
+            ("SUB", 0x7FFFFFFF, 0xFFFFFFFF, 0x80000000, true, true, true),
+            ("SUB", 5, 5, 0, false, false, false),
+            ("LS", 0x80000001, 1, 2, true, false, false),
+            ("LS", 1, 31, 0x80000000, false, false, true),
+            ("LS", 2, 31, 0, true, false, false),
+            ("LS", 0x80000000, 0, 0x80000000, false, false, true),
+            ("LS", 0x80000000, 32, 0x80000000, false, false, true),
+            ("RS", 3, 1, 1, true, false, false),
+            ("RS", 0x80000000, 31, 1, false, false, false),
+            ("RS", 0xC0000000, 31, 1, true, false, false),
+            ("RS", 0x80000000, 32, 0x80000000, false, false, true),
+        ];
+        let words = assemble("ADD R3 - R1, R2\nSUB R3 - R1, R2\nLS R3 - R1, R2\nRS R3 - R1, R2\n");
+        for (op, a, b, result, carry, overflow, negative) in cases {
+            let index = ["ADD", "SUB", "LS", "RS"]
+                .iter()
+                .position(|&o| o == op)
+                .unwrap();
+            let cpu = run_words(&words[index..=index], a, b, 1);
+            let flags = &cpu.registers;
+            let case = format!("{} 0x{:08x}, 0x{:08x}", op, a, b);
+            assert_eq!(cpu.registers[3], result, "{}", case);
+            assert_eq!(flags.carry_f, carry, "carry {}", case);
+            assert_eq!(flags.overflow_f, overflow, "overflow {}", case);
+            assert_eq!(flags.negative_f, negative, "negative {}", case);
+            assert_eq!(flags.zero_f, result == 0, "zero {}", case);
+        }
+    }
+
+    #[test]
+    fn overflow_and_negative_jumps() {
+        // Whether JP-Or, JP-NOr, JP-Nr and JP-NNr jump after the add
+        let cases = [
+            (0x7FFFFFFF, 1, [true, false, true, false]),
+            (0x80000000, 0x80000000, [true, false, false, true]),
+            (0xFFFFFFFF, 0, [false, true, true, false]),
+            (1, 2, [false, true, false, true]),
+        ];
+        for (i, jump) in ["JP-Or", "JP-NOr", "JP-Nr", "JP-NNr"].iter().enumerate() {
+            for target in ["0x10", "R4"] {
+                let jump = format!("{} {}", jump, target);
+                let words = assemble(&format!("ADD R3 - R1, R2\n{}\n", jump));
+                for (a, b, expected) in cases {
+                    let jumped = run_words(&words, a, b, 2).registers.pc == 0x10;
+                    assert_eq!(
+                        jumped, expected[i],
+                        "{} after 0x{:08x} + 0x{:08x}",
+                        jump, a, b
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn signed_jumps_after_a_subtract() {
+        let pairs: [(i32, i32); 7] = [
+            (-1, 1),
+            (1, -1),
+            (5, 5),
+            (i32::MIN, 1),
+            (i32::MAX, -1),
+            (i32::MIN, i32::MAX),
+            (-3, -2),
+        ];
+        for (i, jump) in ["JP-GTS", "JP-LES", "JP-GES", "JP-LTS"].iter().enumerate() {
+            for target in ["0x10", "R4"] {
+                let jump = format!("{} {}", jump, target);
+                let words = assemble(&format!("SUB R3 - R1, R2\n{}\n", jump));
+                for (a, b) in pairs {
+                    let expected = [a > b, a <= b, a >= b, a < b][i];
+                    let jumped = run_words(&words, a as u32, b as u32, 2).registers.pc == 0x10;
+                    assert_eq!(jumped, expected, "{} after {} - {}", jump, a, b);
+                }
+            }
+        }
+    }
+
+    /// `source` with a `RETI` handler at 0x60 + line for every interrupt
+    /// line.
+    fn with_interrupt_handlers(source: &str) -> String {
+        let mut source = format!("{}#addr 0x48\n", source);
+        for line in 0..INTERRUPT_LINES {
+            source += &format!("#d32 0x{:x}\n", 0x60 + line);
+        }
+        source += "#addr 0x60\n";
+        source += &"RETI\n".repeat(INTERRUPT_LINES);
+        return source;
+    }
+
+    #[test]
+    fn interrupts_wait_for_ei() {
+        let mut cpu = load(&format!(
+            "{}{}",
+            BANK,
+            with_interrupt_handlers("DI\nNOP\nEI\nNOP\n")
+        ));
+        cpu.registers.sp = 0x1000;
+        cpu.interrupts_mut().mask = 0xFF;
+        cpu.raise_interrupt(0);
+        run_steps(&mut cpu, 3);
+        assert_eq!(cpu.registers.pc, 3);
+        assert_eq!(cpu.interrupts().pending, 0x01);
+
+        run_steps(&mut cpu, 1);
+        assert_eq!(cpu.registers.pc, 0x60);
+        assert_eq!(cpu.registers.reti, 3);
+        assert!(!cpu.registers.interrupt_enable);
+        assert_eq!(cpu.interrupts().pending, 0);
+
+        // RETI enables interrupts again from the pushed flags
+        run_steps(&mut cpu, 1);
+        assert_eq!(cpu.registers.pc, 3);
+        assert!(cpu.registers.interrupt_enable);
+    }
+
+    #[test]
+    fn masked_lines_stay_pending_and_lower_lines_go_first() {
+        let source = "LDI R1 - 0xa4\nST R1 - 0x7fffc2\nEI\nNOP\n\
+            LDI R2 - 0xa0\nST R2 - 0x7fffc1\nNOP\nNOP\n";
+        let mut cpu = load(&format!("{}{}", BANK, with_interrupt_handlers(source)));
+        cpu.registers.sp = 0x1000;
+        run_steps(&mut cpu, 4);
+        assert_eq!(cpu.registers.pc, 4);
+        assert_eq!(cpu.interrupts().pending, 0xA4);
+
+        run_steps(&mut cpu, 3);
+        assert_eq!(cpu.registers.pc, 0x65);
+        run_steps(&mut cpu, 2);
+        assert_eq!(cpu.registers.pc, 0x67);
+        run_steps(&mut cpu, 1);
+        assert_eq!(cpu.registers.pc, 6);
+        run_steps(&mut cpu, 1);
+        assert_eq!(cpu.registers.pc, 7);
+        assert_eq!(cpu.interrupts().pending, 0x04);
+    }
+
+    #[test]
+    fn each_line_enters_its_vector() {
+        let words = assemble(&with_interrupt_handlers("NOP\n"));
+        for line in 0..INTERRUPT_LINES as u8 {
+            let mut cpu = CPU::with_ram_init(words.clone(), RamInit::Zero, false);
+            cpu.registers.sp = 0x1000;
+            cpu.registers.interrupt_enable = true;
+            cpu.interrupts_mut().mask = 0xFF;
+            cpu.raise_interrupt(line);
+            run_steps(&mut cpu, 1);
+            assert_eq!(cpu.registers.pc, 0x60 + line as usize);
+            assert_eq!(cpu.registers.reti, 0);
+            assert!(cpu.registers.privilege);
+            // The pushed flags hold the interrupt enable bit
+            assert_eq!(cpu.read_ram(0xfff) & 0x20, 0x20);
+        }
+    }
+
+    fn run_steps(cpu: &mut CPU, steps: usize) {
+        for _ in 0..steps {
+            assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        }
+    }
+
+    /// `source` in user mode with the MPU granting execute and read on the
+    /// first page and read on physical 0x2000 - 0x23ff.
+    fn sandboxed(source: &str) -> CPU {
+        let mut cpu = load(&format!("{}{}", BANK, source));
+        cpu.registers.privilege = false;
+        let mpu = cpu.mpu_mut();
+        mpu.enabled = true;
+        mpu.regions[0] = MpuRegion {
+            start: 0,
+            limit: 0x3ff,
+            permissions: MPU_READ | MPU_EXECUTE,
+        };
+        mpu.regions[1] = MpuRegion {
+            start: 0x2000,
+            limit: 0x23ff,
+            permissions: MPU_READ,
+        };
+        return cpu;
+    }
+
+    /// Maps virtual pages to physical frames with a page table at 0x10000.
+    pub(super) fn map_pages(cpu: &mut CPU, pages: &[(usize, usize)]) {
+        for &(page, frame) in pages {
+            let pte = (frame as u32) << PAGE_BITS | PTE_PRESENT | PTE_WRITE | PTE_USER;
+            cpu.bus.write(0x10000 + page, pte);
+        }
+        let mmu = cpu.mmu_mut();
+        mmu.page_table = 0x10000;
+        mmu.enabled = true;
+    }
+
+    fn memory_fault_address(error: CPUError) -> u32 {
+        let CPUError::MemoryFault(fault) = error else {
+            panic!("expected an MPU fault, got {:?}", error);
+        };
+        return fault.sub_op;
+    }
+
+    #[test]
+    fn the_mpu_checks_user_fetches_and_loads() {
+        let mut cpu = sandboxed("LD R1 - 0x2000\nLD R2 - 0x1000\n");
+        cpu.bus.write(0x2000, 0x55);
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert_eq!(cpu.registers[1], 0x55);
+        assert_eq!(
+            memory_fault_address(cpu.execute_instruction(false, 0)),
+            0x1000
+        );
+        assert_eq!(cpu.registers[2], 0);
+
+        cpu.mpu_mut().regions[0].permissions = MPU_READ;
+        cpu.registers.pc = 0;
+        assert_eq!(memory_fault_address(cpu.execute_instruction(false, 0)), 0);
+        // Supervisor mode is never checked
+        cpu.registers.privilege = true;
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+    }
+
+    #[test]
+    fn the_mpu_checks_the_physical_address_after_paging() {
+        let mut cpu = sandboxed("LD R1 - 0x1000\nLD R2 - 0x2000\nLD R3 - 0x5000\n");
+        // Virtual 0x1000 is in physical 0x2000, virtual 0x2000 in 0x3000
+        map_pages(&mut cpu, &[(0, 0), (4, 8), (8, 12)]);
+        cpu.bus.write(0x2000, 0x55);
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        assert_eq!(cpu.registers[1], 0x55);
+        // The fault reports the virtual address
+        assert_eq!(
+            memory_fault_address(cpu.execute_instruction(false, 0)),
+            0x2000
+        );
+
+        // An unmapped page faults before the MPU looks at it
+        cpu.registers.pc = 2;
+        let error = cpu.execute_instruction(false, 0);
+        assert!(matches!(error, CPUError::PageFault(_)), "{:?}", error);
+    }
+
+    #[test]
+    fn user_fetches_are_checked_where_the_page_lands() {
+        let mut cpu = sandboxed("NOP\n");
+        // Virtual page 0 runs the code copied to physical 0x2000, which
+        // grants read but not execute
+        cpu.bus.write(0x2000, 0);
+        map_pages(&mut cpu, &[(0, 8)]);
+        assert_eq!(memory_fault_address(cpu.execute_instruction(false, 0)), 0);
+        cpu.mpu_mut().regions[1].permissions |= MPU_EXECUTE;
+        cpu.registers.pc = 0;
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+    }
+
+    #[test]
+    fn illegal_alu_operations_fault_on_the_word() {
+        // Integer op 15 and compares past 4 are legal, they give 0 and false
+        let words = [
+            (0x11233400, 3),
+            (0x11239800, 9),
+            (0x11230C00, 0),
+            (0x21200C00, 0),
+        ];
+        for (word, sub_op) in words {
+            let mut cpu = CPU::with_ram_init(vec![word], RamInit::Zero, false);
+            cpu.registers[3] = 0x1234;
+            let error = cpu.execute_instruction(false, 0);
+            let CPUError::IllegalAluOp(fault) = error else {
+                panic!("0x{:08x} gave {:?}", word, error);
+            };
+            assert_eq!(
+                (fault.pc, fault.decoded.ir, fault.sub_op),
+                (0, word, sub_op)
+            );
+            assert_eq!(cpu.registers.pc, 0);
+            assert_eq!(cpu.registers[3], 0x1234);
+            assert_eq!(error.trap_vector(), Some(ILLEGAL_INSTRUCTION_VECTOR));
+        }
+    }
+
+    #[test]
+    fn instruction_faults_stop_skip_or_trap() {
+        let source = format!("{}NOP\n#d32 0x11233400\n#addr 0x42\n#d32 0x60\n", BANK);
+        let faulted = || {
+            let mut cpu = load(&source);
+            cpu.registers.sp = 0x1000;
+            assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+            let error = cpu.execute_instruction(false, 0);
+            assert!(matches!(error, CPUError::IllegalAluOp(_)));
+            return (cpu, error);
+        };
+        let (mut cpu, error) = faulted();
+        assert_eq!(
+            error.to_string(),
+            "Illegal ALU operation 3 at PC 0x000001 (IR: 0x11233400, Opcode: 0x1, \
+             DR: 3, SR1: 1, SR2: 2, Immediate: 0x233400)"
+        );
+        cpu.recover(&error, FaultAction::Stop);
+        assert_eq!(cpu.registers.pc, 1);
+        assert!(matches!(
+            cpu.execute_instruction(false, 0),
+            CPUError::IllegalAluOp(_)
+        ));
+
+        let (mut cpu, error) = faulted();
+        cpu.recover(&error, FaultAction::Skip);
+        assert_eq!(cpu.registers.pc, 2);
+
+        let (mut cpu, error) = faulted();
+        cpu.recover(&error, FaultAction::Trap);
+        assert_eq!(cpu.registers.pc, 0x60);
+        assert_eq!(cpu.registers.reti, 1);
+        assert!(cpu.registers.privilege);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::cpu::{CPUError, CPU};
+use crate::decoder::Decoded;
+use log::trace;
+
+/// The NaN float operations return.
+pub const CANONICAL_NAN: u32 = 0x7FC00000;
+
+/// Operations of the ALU float group (`alu_group == ALU_GROUP_FLOAT`).
+///
+/// All values are IEEE-754 single precision stored bit-for-bit in the
+/// 32-bit registers. Arithmetic follows IEEE-754: NaN operands propagate
+/// and division by zero gives an infinity. Every NaN result is the quiet
+/// NaN [`CANONICAL_NAN`], so results don't depend on the host's NaN
+/// payloads. Overflow is set when a finite computation gives an infinity,
+/// or when `FTI` / `FTU` saturate or see a NaN.
+impl CPU {
+    pub(crate) fn execute_fpu(&mut self, pc: usize, decoded: Decoded, alu_op: u32) -> CPUError {
+        let Decoded { dr, sr1, sr2, .. } = decoded;
+        let a = f32::from_bits(self.registers[sr1]);
+        let b = f32::from_bits(self.registers[sr2]);
+        let result = match alu_op {
+            0 => {
+                // ADF
+                if self.log {
+                    trace!("ADF: R{} = R{} + R{}", dr, sr1, sr2);
+                }
+                (a + b).to_bits()
+            }
+            1 => {
+                // SBF
+                if self.log {
+                    trace!("SBF: R{} = R{} - R{}", dr, sr1, sr2);
+                }
+                (a - b).to_bits()
+            }
+            2 => {
+                // MULF
+                if self.log {
+                    trace!("MULF: R{} = R{} * R{}", dr, sr1, sr2);
+                }
+                (a * b).to_bits()
+            }
+            3 => {
+                // DIVF
+                if self.log {
+                    trace!("DIVF: R{} = R{} / R{}", dr, sr1, sr2);
+                }
+                (a / b).to_bits()
+            }
+            4 => {
+                // NF
+                if self.log {
+                    trace!("NF: R{} = -R{}", dr, sr1);
+                }
+                (-a).to_bits()
+            }
+            5 => {
+                // UITF
+                if self.log {
+                    trace!("UITF: R{} = R{} as f32", dr, sr1);
+                }
+                (self.registers[sr1] as f32).to_bits()
+            }
+            6 => {
+                // SITF
+                if self.log {
+                    trace!("SITF: R{} = R{} as i32 as f32", dr, sr1);
+                }
+                (self.registers[sr1] as i32 as f32).to_bits()
+            }
+            7 => {
+                // FTI: truncates toward zero and saturates, NaN becomes 0
+                if self.log {
+                    trace!("FTI: R{} = R{} as i32", dr, sr1);
+                }
+                a as i32 as u32
+            }
+            8 => {
+                // FTU: truncates toward zero and saturates, NaN becomes 0
+                if self.log {
+                    trace!("FTU: R{} = R{} as u32", dr, sr1);
+                }
+                a as u32
+            }
+            _ => {
+                return self.illegal_instruction(CPUError::IllegalAluOp, pc, decoded, alu_op);
+            }
+        };
+        let overflow = match alu_op {
+            5 | 6 => false,
+            7 => !(-2147483648.0..2147483648.0).contains(&a.trunc()),
+            8 => !(0.0..4294967296.0).contains(&a.trunc()),
+            _ => f32::from_bits(result).is_infinite() && a.is_finite() && b.is_finite(),
+        };
+        let is_float = alu_op < 7;
+        let result = if is_float && f32::from_bits(result).is_nan() {
+            CANONICAL_NAN
+        } else {
+            result
+        };
+        self.registers[dr] = result;
+        self.set_alu_flags(dr, false, overflow);
+        if is_float {
+            // -0.0 is zero too
+            self.registers.zero_f = f32::from_bits(result) == 0.0;
+        }
+        self.clock += 1;
+        return CPUError::Ok;
+    }
+
+    /// Float compares. Any compare involving a NaN is false.
+    pub(crate) fn compare_fpu(&mut self, decoded: Decoded, alu_op: u32) -> CPUError {
+        let Decoded { sr1, sr2, .. } = decoded;
+        let a = f32::from_bits(self.registers[sr1]);
+        let b = f32::from_bits(self.registers[sr2]);
+        self.registers.comp_f = match alu_op {
+            0 => a > b,  // CGTF
+            1 => a == b, // CEQF
+            2 => a < b,  // CLTF
+            3 => a >= b, // CGEF
+            4 => a <= b, // CLEF
+            _ => false,  // Default False
+        };
+        if self.log {
+            trace!("FPU_COMPARE {}: R{}, R{}", alu_op, sr1, sr2);
+            trace!("Flags: Comp = {}", self.registers.comp_f);
+        }
+        self.clock += 1;
+        return CPUError::Ok;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::{load, BANK};
+
+    /// Runs the float instruction `line` with R1 = `a` and R2 = `b`.
+    fn float_op(line: &str, a: u32, b: u32) -> CPU {
+        let mut cpu = load(&format!("{}{}\n", BANK, line));
+        cpu.registers[1] = a;
+        cpu.registers[2] = b;
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        return cpu;
+    }
+
+    fn bits(value: f32) -> u32 {
+        value.to_bits()
+    }
+
+    #[test]
+    fn nan_results_are_canonical() {
+        let infinity = bits(f32::INFINITY);
+        let cases = [
+            ("ADF R3 - R1, R2", 0x7FC12345, bits(1.0)),
+            ("ADF R3 - R1, R2", 0xFF800001, bits(1.0)),
+            ("SBF R3 - R1, R2", infinity, infinity),
+            ("MULF R3 - R1, R2", bits(0.0), infinity),
+            ("DIVF R3 - R1, R2", bits(0.0), bits(0.0)),
+            ("NF R3 - R1", 0x7FC12345, 0),
+        ];
+        for (line, a, b) in cases {
+            let cpu = float_op(line, a, b);
+            assert_eq!(cpu.registers[3], CANONICAL_NAN, "{}", line);
+            assert!(!cpu.registers.overflow_f, "{}", line);
+        }
+    }
+
+    #[test]
+    fn results_round_to_nearest_even() {
+        let cases = [
+            ("UITF R3 - R1", 16777217, 0, bits(16777216.0)),
+            ("UITF R3 - R1", 16777219, 0, bits(16777220.0)),
+            ("UITF R3 - R1", 0xFFFFFFFF, 0, bits(4294967296.0)),
+            ("SITF R3 - R1", 0xFFFFFFFF, 0, bits(-1.0)),
+            (
+                "ADF R3 - R1, R2",
+                bits(1.0),
+                bits(2f32.powi(-24)),
+                bits(1.0),
+            ),
+            (
+                "ADF R3 - R1, R2",
+                bits(1.0),
+                bits(3.0 * 2f32.powi(-24)),
+                0x3F800002,
+            ),
+            ("DIVF R3 - R1, R2", bits(1.0), bits(3.0), 0x3EAAAAAB),
+        ];
+        for (line, a, b, expected) in cases {
+            assert_eq!(float_op(line, a, b).registers[3], expected, "{}", line);
+        }
+    }
+
+    #[test]
+    fn conversions_truncate_and_saturate() {
+        let nan = CANONICAL_NAN;
+        let cases = [
+            ("FTI R3 - R1", bits(2.7), 2, false),
+            ("FTI R3 - R1", bits(-2.7), -2i32 as u32, false),
+            ("FTI R3 - R1", bits(-2147483648.0), 0x80000000, false),
+            ("FTI R3 - R1", bits(3e9), 0x7FFFFFFF, true),
+            ("FTI R3 - R1", bits(-3e9), 0x80000000, true),
+            ("FTI R3 - R1", nan, 0, true),
+            ("FTU R3 - R1", bits(3.9), 3, false),
+            ("FTU R3 - R1", bits(-0.5), 0, false),
+            ("FTU R3 - R1", bits(3e9), 3000000000, false),
+            ("FTU R3 - R1", bits(5e9), 0xFFFFFFFF, true),
+            ("FTU R3 - R1", bits(-1.0), 0, true),
+            ("FTU R3 - R1", nan, 0, true),
+        ];
+        for (line, a, expected, overflow) in cases {
+            let cpu = float_op(line, a, 0);
+            assert_eq!(cpu.registers[3], expected, "{} 0x{:08x}", line, a);
+            assert_eq!(cpu.registers.overflow_f, overflow, "{} 0x{:08x}", line, a);
+        }
+    }
+
+    #[test]
+    fn overflow_is_a_finite_computation_giving_an_infinity() {
+        let (max, infinity) = (bits(f32::MAX), bits(f32::INFINITY));
+        let cases = [
+            ("MULF R3 - R1, R2", max, bits(2.0), infinity, true),
+            ("ADF R3 - R1, R2", max, max, infinity, true),
+            (
+                "SBF R3 - R1, R2",
+                bits(-f32::MAX),
+                max,
+                bits(f32::NEG_INFINITY),
+                true,
+            ),
+            ("DIVF R3 - R1, R2", bits(1.0), bits(0.0), infinity, true),
+            ("ADF R3 - R1, R2", infinity, bits(1.0), infinity, false),
+            (
+                "MULF R3 - R1, R2",
+                max,
+                bits(0.5),
+                bits(f32::MAX / 2.0),
+                false,
+            ),
+        ];
+        for (line, a, b, expected, overflow) in cases {
+            let cpu = float_op(line, a, b);
+            assert_eq!(cpu.registers[3], expected, "{}", line);
+            assert_eq!(cpu.registers.overflow_f, overflow, "{}", line);
+        }
+    }
+}
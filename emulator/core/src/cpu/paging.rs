@@ -0,0 +1,184 @@
+use crate::cpu::{CPU, SYSTEM_CALL_NUMBER, SYSTEM_CALL_VECTOR};
+use crate::decoder::Decoded;
+use crate::memory::ADDRESS_MASK;
+use crate::mmu::{
+    Mmu, PageFaultCause, PAGE_SIZE, PTE_FRAME_MASK, PTE_PRESENT, PTE_USER, PTE_WRITE,
+};
+use crate::mpu::Access;
+use log::trace;
+
+/// Address translation. With paging off virtual addresses are physical;
+/// with it on every CPU access (fetch, loads, stores, the stack and the
+/// handler vectors) goes through the TLB, walking the page table at
+/// `MMU_PAGE_TABLE` on a miss. Devices and the GUI use physical addresses.
+impl CPU {
+    /// The physical address of `address`, accessed from user mode if
+    /// `user`. A failed translation is recorded in the MMU fault registers.
+    pub fn translate(
+        &mut self,
+        address: usize,
+        access: Access,
+        user: bool,
+    ) -> Result<usize, PageFaultCause> {
+        let mmu = self.mmu();
+        if !mmu.enabled {
+            return Ok(address & ADDRESS_MASK);
+        }
+        let page = Mmu::page(address);
+        let pte = match mmu.lookup(page) {
+            Some(pte) => pte,
+            None => {
+                let pte = self.bus.read(mmu.pte_address(page));
+                if pte & PTE_PRESENT != 0 {
+                    self.mmu_mut().refill(page, pte);
+                }
+                if self.log {
+                    trace!("TLB miss: page 0x{:x}, PTE = 0x{:08x}", page, pte);
+                }
+                pte
+            }
+        };
+        let cause = if pte & PTE_PRESENT == 0 {
+            PageFaultCause::NotPresent
+        } else if user && pte & PTE_USER == 0 {
+            PageFaultCause::Supervisor
+        } else if access == Access::Write && pte & PTE_WRITE == 0 {
+            PageFaultCause::ReadOnly
+        } else {
+            return Ok((pte & PTE_FRAME_MASK) as usize | (address & (PAGE_SIZE - 1)));
+        };
+        let mmu = self.mmu_mut();
+        mmu.fault_address = (address & ADDRESS_MASK) as u32;
+        mmu.fault_cause = cause as u32;
+        return Err(cause);
+    }
+    /// The accesses `SYS` and `RETI` make in supervisor mode.
+    pub(crate) fn supervisor_accesses(&self, instr: u32, decoded: Decoded) -> Vec<(usize, Access)> {
+        let sp = self.registers.sp as usize;
+        if decoded.opcode != 14 {
+            return vec![];
+        }
+        match instr >> 26 & 0x03 {
+            // SYS
+            0 => vec![
+                (sp.wrapping_sub(1), Access::Write),
+                (SYSTEM_CALL_VECTOR, Access::Read),
+                (SYSTEM_CALL_NUMBER, Access::Write),
+            ],
+            // RETI
+            2 => vec![(sp, Access::Read)],
+            _ => vec![],
+        }
+    }
+    /// The first address `instr` would page fault on.
+    pub(crate) fn page_fault_address(&mut self, instr: u32, decoded: Decoded) -> Option<usize> {
+        if !self.mmu().enabled {
+            return None;
+        }
+        let user = !self.registers.privilege;
+        let accesses = self
+            .data_accesses(instr, decoded)
+            .into_iter()
+            .map(|(address, access)| (address, access, user))
+            .chain(
+                self.supervisor_accesses(instr, decoded)
+                    .into_iter()
+                    .map(|(address, access)| (address, access, false)),
+            )
+            .collect::<Vec<_>>();
+        return accesses
+            .into_iter()
+            .find(|&(address, access, user)| self.translate(address, access, user).is_err())
+            .map(|(address, _, _)| address & ADDRESS_MASK);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::{load, map_pages, BANK};
+    use crate::cpu::{CPUError, FaultAction, PAGE_FAULT_VECTOR};
+    use crate::mmu::{MMU_FAULT_ADDRESS, MMU_FAULT_CAUSE, MMU_START, PAGE_BITS};
+
+    /// The IO page, which the kernel maps to itself.
+    const IO_PAGE: usize = 0x7fffc0 >> PAGE_BITS;
+
+    fn step(cpu: &mut CPU) {
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+    }
+
+    #[test]
+    fn the_page_table_walk_translates_and_fills_the_tlb() {
+        let mut cpu = load(&format!("{}LD R1 - 0x1005\nST R1 - 0x1006\n", BANK));
+        map_pages(&mut cpu, &[(0, 0), (4, 8)]);
+        cpu.bus.write(0x2005, 0x77);
+        assert_eq!(cpu.mmu().lookup(4), None);
+        step(&mut cpu);
+        assert_eq!(cpu.registers[1], 0x77);
+        let pte = cpu.bus.peek(0x10004);
+        assert_eq!(cpu.mmu().lookup(4), Some(pte));
+        step(&mut cpu);
+        assert_eq!(cpu.bus.peek(0x2006), 0x77);
+        assert_eq!(cpu.bus.peek(0x1006), 0);
+    }
+
+    #[test]
+    fn writing_the_page_table_base_flushes_the_tlb() {
+        let source = "LD R1 - 0x1000\nLD R1 - 0x1000\nST R2 - 0x7fffc5\nLD R1 - 0x1000\n";
+        let mut cpu = load(&format!("{}{}", BANK, source));
+        map_pages(&mut cpu, &[(0, 0), (4, 8), (IO_PAGE, IO_PAGE)]);
+        cpu.registers[2] = 0x10000;
+        cpu.bus.write(0x2000, 8);
+        cpu.bus.write(0x3000, 12);
+        step(&mut cpu);
+        assert_eq!(cpu.registers[1], 8);
+
+        // Software remaps the page; the TLB keeps the old frame until flushed
+        cpu.bus.write(0x10004, 12 << PAGE_BITS | PTE_PRESENT);
+        step(&mut cpu);
+        assert_eq!(cpu.registers[1], 8);
+        step(&mut cpu);
+        assert_eq!(cpu.mmu().lookup(4), None);
+        step(&mut cpu);
+        assert_eq!(cpu.registers[1], 12);
+    }
+
+    #[test]
+    fn page_faults_set_the_fault_registers() {
+        let cases = [
+            (PTE_PRESENT | PTE_USER, PageFaultCause::ReadOnly),
+            (0, PageFaultCause::NotPresent),
+            (PTE_PRESENT | PTE_WRITE, PageFaultCause::Supervisor),
+        ];
+        for (flags, cause) in cases {
+            let mut cpu = load(&format!("{}ST R1 - 0x1003\n", BANK));
+            map_pages(&mut cpu, &[(0, 0)]);
+            cpu.bus.write(0x10004, 8 << PAGE_BITS | flags);
+            cpu.registers.privilege = false;
+            let error = cpu.execute_instruction(false, 0);
+            let CPUError::PageFault(fault) = error else {
+                panic!("{:?} gave {:?}", cause, error);
+            };
+            assert_eq!((fault.pc, fault.sub_op), (0, 0x1003));
+            assert_eq!(cpu.bus.peek(MMU_START + MMU_FAULT_ADDRESS), 0x1003);
+            assert_eq!(cpu.bus.peek(MMU_START + MMU_FAULT_CAUSE), cause as u32);
+            assert_eq!(cpu.bus.peek(0x2003), 0);
+        }
+    }
+
+    #[test]
+    fn page_faults_trap_through_vector_0x50() {
+        let source = format!("{}LD R1 - 0x5000\n#addr 0x50\n#d32 0x60\n", BANK);
+        let mut cpu = load(&source);
+        map_pages(&mut cpu, &[(0, 0)]);
+        cpu.registers.sp = 0x400;
+        cpu.registers.privilege = false;
+        let error = cpu.execute_instruction(false, 0);
+        assert_eq!(error.trap_vector(), Some(PAGE_FAULT_VECTOR));
+        cpu.recover(&error, FaultAction::Trap);
+        assert_eq!(cpu.registers.pc, 0x60);
+        assert_eq!(cpu.registers.reti, 0);
+        assert!(cpu.registers.privilege);
+        assert_eq!(cpu.mmu().fault_address, 0x5000);
+    }
+}
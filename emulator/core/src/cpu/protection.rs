@@ -0,0 +1,96 @@
+use crate::cpu::{CPU, PROTECTION_FAULT_ADDRESS, PROTECTION_FAULT_VECTOR};
+use crate::decoder::Decoded;
+use crate::io::PROGRAM_MEMORY_END;
+use crate::memory::ADDRESS_MASK;
+use crate::mpu::Access;
+use log::info;
+
+/// First word of the vectors user mode may not touch (`SYS`, traps and
+/// interrupt handlers).
+pub const SUPERVISOR_VECTORS_START: usize = 0x40;
+/// Last protected vector word.
+pub const SUPERVISOR_VECTORS_END: usize = 0x5F;
+/// Last word of the IO page above program memory, which holds the IO
+/// window and the interrupt controller.
+pub const SUPERVISOR_IO_END: usize = 0x7fffff;
+/// Stored at [`PROTECTION_FAULT_ADDRESS`] when the fault was caused by a
+/// supervisor-only instruction rather than a memory access.
+pub const PRIVILEGED_INSTRUCTION: u32 = 0xFFFFFFFF;
+
+/// Whether `address` is reserved to supervisor mode: the vectors and the
+/// IO page. The framebuffer (where the stack grows from `SP = 0`) stays
+/// open to user code.
+pub fn is_supervisor_address(address: usize) -> bool {
+    let address = address & ADDRESS_MASK;
+    return (SUPERVISOR_VECTORS_START..=SUPERVISOR_VECTORS_END).contains(&address)
+        || (PROGRAM_MEMORY_END + 1..=SUPERVISOR_IO_END).contains(&address);
+}
+
+/// User mode (`privilege == false`) checks. Supervisor-only instructions
+/// are the output port, `RETI`, `STRETI`, `EI`, `DI` and `HLT`; every other
+/// instruction faults if it would read or write a supervisor address.
+impl CPU {
+    /// The data accesses `instr` makes with the current privilege, known
+    /// before it runs so faults stay precise.
+    pub(crate) fn data_accesses(&self, instr: u32, decoded: Decoded) -> Vec<(usize, Access)> {
+        let Decoded {
+            opcode,
+            sr2,
+            immediate,
+            ..
+        } = decoded;
+        let sp = self.registers.sp as usize;
+        match opcode {
+            4 => vec![(immediate as usize, Access::Read)],
+            5 => vec![(self.registers[sr2] as usize, Access::Read)],
+            7 => vec![(immediate as usize, Access::Write)],
+            8 => vec![(self.registers[sr2] as usize, Access::Write)],
+            11 => vec![(sp.wrapping_sub(1), Access::Write)],
+            12 => match (instr >> 22) & 0x03 {
+                0 => vec![(sp.wrapping_sub(1), Access::Write)],
+                1 | 2 => vec![(sp, Access::Read)],
+                _ => vec![],
+            },
+            13 if instr >> 25 & 0x01 == 1 => {
+                vec![(sp, Access::Read), (sp.wrapping_add(1), Access::Read)]
+            }
+            13 => vec![
+                (sp.wrapping_sub(1), Access::Write),
+                (sp.wrapping_sub(2), Access::Write),
+            ],
+            // SYS pushes & reads its vector once already in supervisor mode,
+            // see `CPU::supervisor_accesses`
+            _ => vec![],
+        }
+    }
+    /// The reason the user mode instruction `instr` may not run: the first
+    /// protected address it would access, or [`PRIVILEGED_INSTRUCTION`].
+    pub(crate) fn user_violation(&self, instr: u32, decoded: Decoded) -> Option<u32> {
+        match decoded.opcode {
+            // Output port, Halt
+            10 | 15 => return Some(PRIVILEGED_INSTRUCTION),
+            // RETI, STRETI, EI / DI
+            14 if instr >> 26 & 0x03 != 0 => return Some(PRIVILEGED_INSTRUCTION),
+            _ => {}
+        }
+        return self
+            .data_accesses(instr, decoded)
+            .into_iter()
+            .map(|(address, _)| address)
+            .find(|&address| is_supervisor_address(address))
+            .map(|address| (address & ADDRESS_MASK) as u32);
+    }
+    /// Traps into the handler at [`PROTECTION_FAULT_VECTOR`] with the
+    /// faulting PC in `reti` and the cause at [`PROTECTION_FAULT_ADDRESS`].
+    pub(crate) fn protection_fault(&mut self, pc: usize, cause: u32) {
+        self.enter_handler(pc, PROTECTION_FAULT_VECTOR);
+        self.set_ram(PROTECTION_FAULT_ADDRESS, cause);
+        if self.log {
+            info!(
+                "Protection fault at PC 0x{:06x} (cause: 0x{:08x}), entering handler at {}",
+                pc, cause, self.registers.pc
+            );
+        }
+        self.clock += 3;
+    }
+}
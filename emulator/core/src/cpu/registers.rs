@@ -1,5 +1,11 @@
+use std::fmt;
 use std::ops::{Index, IndexMut};
 
+/// Set in the flags word `SYS`, traps & interrupts push when they are taken
+/// in supervisor mode, so `RETI` returns to supervisor mode.
+pub const PRIVILEGE_FLAG: u32 = 1 << 6;
+
+#[derive(Clone)]
 pub struct Registers {
     pub r0: u32,
     pub r1: u32,
@@ -24,6 +30,10 @@ pub struct Registers {
     pub carry_f: bool,
     pub zero_f: bool,
     pub comp_f: bool,
+    pub overflow_f: bool,
+    pub negative_f: bool,
+    /// Set by `EI`, cleared by `DI` and on entry to a handler.
+    pub interrupt_enable: bool,
 }
 
 impl Registers {
@@ -48,12 +58,35 @@ impl Registers {
             pc: 0,
             sp: 0,
             reti: 0,
-            privilege: false,
+            // The CPU boots in supervisor mode, `RETI` drops to user mode
+            privilege: true,
             carry_f: false,
             zero_f: false,
             comp_f: false,
+            overflow_f: false,
+            negative_f: false,
+            interrupt_enable: false,
         }
     }
+    /// The flags packed into the word `CALL`, `SYS` & traps push on the
+    /// stack: bit 0 carry, 1 zero, 2 compare, 3 overflow, 4 negative,
+    /// 5 interrupt enable. Traps add `PRIVILEGE_FLAG` in bit 6.
+    pub fn flags(&self) -> u32 {
+        self.carry_f as u32
+            | (self.zero_f as u32) << 1
+            | (self.comp_f as u32) << 2
+            | (self.overflow_f as u32) << 3
+            | (self.negative_f as u32) << 4
+            | (self.interrupt_enable as u32) << 5
+    }
+    pub fn set_flags(&mut self, flags: u32) {
+        self.carry_f = flags & 0x01 == 1;
+        self.zero_f = flags >> 1 & 0x01 == 1;
+        self.comp_f = flags >> 2 & 0x01 == 1;
+        self.overflow_f = flags >> 3 & 0x01 == 1;
+        self.negative_f = flags >> 4 & 0x01 == 1;
+        self.interrupt_enable = flags >> 5 & 0x01 == 1;
+    }
     pub fn increment_sp(&mut self) {
         self.sp += 1;
     }
@@ -62,6 +95,31 @@ impl Registers {
     }
 }
 
+impl Default for Registers {
+    fn default() -> Self {
+        Registers::new()
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in 0..15 {
+            writeln!(f, "R{}: 0x{:08x}", i, self[i])?;
+        }
+        writeln!(f, "tmp: 0x{:08x}", self.tmp)?;
+        writeln!(f, "PC: 0x{:08x}", self.pc)?;
+        writeln!(f, "SP: 0x{:08x}", self.sp)?;
+        writeln!(f, "RETI: 0x{:08x}", self.reti)?;
+        writeln!(f, "Privilege: {}", self.privilege)?;
+        writeln!(f, "Interrupts: {}", self.interrupt_enable)?;
+        writeln!(
+            f,
+            "Flags: Carry = {}, Zero = {}, Comp = {}, Overflow = {}, Negative = {}",
+            self.carry_f, self.zero_f, self.comp_f, self.overflow_f, self.negative_f
+        )
+    }
+}
+
 impl Index<usize> for Registers {
     type Output = u32;
     fn index(&self, index: usize) -> &Self::Output {
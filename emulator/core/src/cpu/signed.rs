@@ -0,0 +1,185 @@
+use crate::cpu::{CPUError, CPU};
+use crate::decoder::Decoded;
+use log::trace;
+
+/// Operations of the ALU signed group (`alu_group == ALU_GROUP_SIGNED`):
+/// signed multiply and the divides. Division by zero faults with
+/// [`CPUError::DivideByZero`]; `i32::MIN / -1` wraps to `i32::MIN` and sets
+/// overflow.
+impl CPU {
+    pub(crate) fn execute_signed(&mut self, pc: usize, decoded: Decoded, alu_op: u32) -> CPUError {
+        let Decoded { dr, sr1, sr2, .. } = decoded;
+        let a = self.registers[sr1];
+        let b = self.registers[sr2];
+        if (alu_op == 1 || alu_op == 2) && b == 0 {
+            return self.illegal_instruction(CPUError::DivideByZero, pc, decoded, alu_op);
+        }
+        let overflow = match alu_op {
+            0 => {
+                // MULIS
+                let (result, overflow) = (a as i32).overflowing_mul(b as i32);
+                self.registers[dr] = result as u32;
+                if self.log {
+                    trace!("MULIS: R{} = R{} * R{}", dr, sr1, sr2);
+                }
+                overflow
+            }
+            1 => {
+                // DIVIU
+                self.registers[dr] = a / b;
+                if self.log {
+                    trace!("DIVIU: R{} = R{} / R{}", dr, sr1, sr2);
+                }
+                false
+            }
+            2 => {
+                // DIVIS
+                let (result, overflow) = (a as i32).overflowing_div(b as i32);
+                self.registers[dr] = result as u32;
+                if self.log {
+                    trace!("DIVIS: R{} = R{} / R{}", dr, sr1, sr2);
+                }
+                overflow
+            }
+            _ => {
+                return self.illegal_instruction(CPUError::IllegalAluOp, pc, decoded, alu_op);
+            }
+        };
+        self.set_alu_flags(dr, false, overflow);
+        self.clock += 1;
+        return CPUError::Ok;
+    }
+
+    /// Signed integer compares.
+    pub(crate) fn compare_signed(&mut self, decoded: Decoded, alu_op: u32) -> CPUError {
+        let Decoded { sr1, sr2, .. } = decoded;
+        let a = self.registers[sr1] as i32;
+        let b = self.registers[sr2] as i32;
+        self.registers.comp_f = match alu_op {
+            0 => a > b,  // CGTSI
+            1 => a == b, // CEQSI
+            2 => a < b,  // CLTSI
+            3 => a >= b, // CGESI
+            4 => a <= b, // CLESI
+            _ => false,  // Default False
+        };
+        if self.log {
+            trace!("SIGNED_COMPARE {}: R{}, R{}", alu_op, sr1, sr2);
+            trace!("Flags: Comp = {}", self.registers.comp_f);
+        }
+        self.clock += 1;
+        return CPUError::Ok;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::tests::{load, BANK};
+    use crate::cpu::{CPUError, FaultAction, CPU, DIVIDE_BY_ZERO_VECTOR};
+
+    /// Runs `line` with R1 = `a` and R2 = `b`.
+    fn alu_op(line: &str, a: i32, b: i32) -> CPU {
+        let mut cpu = load(&format!("{}{}\n", BANK, line));
+        cpu.registers[1] = a as u32;
+        cpu.registers[2] = b as u32;
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        return cpu;
+    }
+
+    #[test]
+    fn signed_multiply_and_divide() {
+        let cases = [
+            ("MULIS R3 - R1, R2", -3, 7, -21, false),
+            ("MULIS R3 - R1, R2", 0x10000, 0x10000, 0, true),
+            ("MULIS R3 - R1, R2", i32::MIN, -1, i32::MIN, true),
+            ("DIVIU R3 - R1, R2", -2, 2, 0x7FFFFFFF, false),
+            ("DIVIU R3 - R1, R2", 7, -1, 0, false),
+            ("DIVIS R3 - R1, R2", -7, 2, -3, false),
+            ("DIVIS R3 - R1, R2", 7, -2, -3, false),
+            ("DIVIS R3 - R1, R2", i32::MIN, -1, i32::MIN, true),
+        ];
+        for (line, a, b, expected, overflow) in cases {
+            let cpu = alu_op(line, a, b);
+            assert_eq!(cpu.registers[3] as i32, expected, "{} {} {}", line, a, b);
+            assert_eq!(cpu.registers.overflow_f, overflow, "{} {} {}", line, a, b);
+            assert_eq!(
+                cpu.registers.negative_f,
+                expected < 0,
+                "{} {} {}",
+                line,
+                a,
+                b
+            );
+            assert_eq!(cpu.registers.zero_f, expected == 0, "{} {} {}", line, a, b);
+        }
+    }
+
+    #[test]
+    fn dividing_by_zero_faults_before_writing() {
+        for line in ["DIVIU R3 - R1, R2", "DIVIS R3 - R1, R2"] {
+            let mut cpu = load(&format!("{}{}\n", BANK, line));
+            cpu.registers[1] = 7;
+            cpu.registers[3] = 0x1234;
+            let error = cpu.execute_instruction(false, 0);
+            let CPUError::DivideByZero(fault) = error else {
+                panic!("{} gave {:?}", line, error);
+            };
+            assert_eq!(fault.pc, 0);
+            assert_eq!(error.trap_vector(), Some(DIVIDE_BY_ZERO_VECTOR));
+            assert_eq!(cpu.registers[3], 0x1234);
+        }
+    }
+
+    #[test]
+    fn divide_by_zero_traps_through_vector_0x43() {
+        let source = format!("{}NOP\nDIVIS R3 - R1, R2\n#addr 0x43\n#d32 0x60\n", BANK);
+        let mut cpu = load(&source);
+        cpu.registers.sp = 0x1000;
+        assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        let error = cpu.execute_instruction(false, 0);
+        assert!(matches!(error, CPUError::DivideByZero(_)));
+        cpu.recover(&error, FaultAction::Trap);
+        assert_eq!(cpu.registers.pc, 0x60);
+        assert_eq!(cpu.registers.reti, 1);
+        assert_eq!(cpu.registers.sp, 0xfff);
+    }
+
+    #[test]
+    fn arithmetic_shift_right_keeps_the_sign() {
+        let cases = [
+            (0x80000010u32, 4, 0xF8000001u32, false),
+            (0x80000018, 4, 0xF8000001, true),
+            (0x70000018, 4, 0x07000001, true),
+            (0xFFFFFFFF, 31, 0xFFFFFFFF, true),
+            (0x40000000, 31, 0, true),
+            (0x80000001, 0, 0x80000001, false),
+            (0x80000001, 33, 0xC0000000, true),
+        ];
+        for (a, b, expected, carry) in cases {
+            let cpu = alu_op("ARS R3 - R1, R2", a as i32, b);
+            assert_eq!(cpu.registers[3], expected, "0x{:08x} >> {}", a, b);
+            assert_eq!(cpu.registers.carry_f, carry, "0x{:08x} >> {}", a, b);
+        }
+    }
+
+    #[test]
+    fn signed_compares() {
+        let compares = ["CGTSI", "CEQSI", "CLTSI", "CGESI", "CLESI"];
+        // Expected results in the order of `compares`
+        let cases = [
+            (-1, 1, [false, false, true, false, true]),
+            (1, -1, [true, false, false, true, false]),
+            (5, 5, [false, true, false, true, true]),
+            (i32::MIN, i32::MAX, [false, false, true, false, true]),
+            (-2, -3, [true, false, false, true, false]),
+        ];
+        for (a, b, expected) in cases {
+            for (compare, expected) in compares.iter().zip(expected) {
+                let cpu = alu_op(&format!("{} R1, R2", compare), a, b);
+                assert_eq!(cpu.registers.comp_f, expected, "{} {}, {}", compare, a, b);
+            }
+        }
+        // The unsigned compare sees -1 as 0xffffffff
+        assert!(alu_op("CMP-GT R1, R2", -1, 1).registers.comp_f);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::memory::ADDRESS_MASK;
+use std::fmt;
+
+/// What a [`Watchpoint`] stops on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// A read or a write.
+    Access,
+    /// A write storing a value different from the one in memory.
+    Change,
+}
+
+impl fmt::Display for WatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchKind::Read => write!(f, "read"),
+            WatchKind::Write => write!(f, "write"),
+            WatchKind::Access => write!(f, "access"),
+            WatchKind::Change => write!(f, "change"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: usize,
+    pub enabled: bool,
+}
+
+/// Watches the virtual addresses `start..=end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: usize,
+    pub end: usize,
+    pub kind: WatchKind,
+    pub enabled: bool,
+}
+
+impl Watchpoint {
+    fn contains(&self, address: usize) -> bool {
+        return self.enabled && (self.start..=self.end).contains(&address);
+    }
+}
+
+/// The access that triggered a watchpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchpointHit {
+    /// The instruction that made the access, the PC has moved past it.
+    pub pc: usize,
+    /// Virtual address of the access.
+    pub address: usize,
+    pub kind: WatchKind,
+    /// The word before the access; equal to `value` for reads.
+    pub old: u32,
+    pub value: u32,
+}
+
+/// PC breakpoints and memory watchpoints.
+///
+/// [`crate::CPU::execute_instruction`] returns [`crate::CPUError::Breakpoint`]
+/// instead of running an instruction with an enabled breakpoint, and
+/// [`crate::CPUError::Watchpoint`] after an instruction whose load or store
+/// hit an enabled watchpoint. Execution carries on from a breakpoint when
+/// called again: the breakpoint at the PC it stopped on is passed over once.
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// PC whose breakpoint the next instruction ignores.
+    resume: Option<usize>,
+    hit: Option<WatchpointHit>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            resume: None,
+            hit: None,
+        }
+    }
+    /// Adds an enabled breakpoint at `address` unless there is one already.
+    pub fn add_breakpoint(&mut self, address: usize) {
+        let address = address & ADDRESS_MASK;
+        if self.breakpoints.iter().any(|b| b.address == address) {
+            return;
+        }
+        self.breakpoints.push(Breakpoint {
+            address,
+            enabled: true,
+        });
+    }
+    pub fn remove_breakpoint(&mut self, address: usize) {
+        let address = address & ADDRESS_MASK;
+        self.breakpoints.retain(|b| b.address != address);
+    }
+    pub fn add_watchpoint(&mut self, start: usize, end: usize, kind: WatchKind) {
+        self.watchpoints.push(Watchpoint {
+            start: start & ADDRESS_MASK,
+            end: end & ADDRESS_MASK,
+            kind,
+            enabled: true,
+        });
+    }
+    pub fn remove_watchpoint(&mut self, index: usize) {
+        if index < self.watchpoints.len() {
+            self.watchpoints.remove(index);
+        }
+    }
+    /// Lets the instruction at `pc` run once even if it has a breakpoint,
+    /// for stepping or continuing from a breakpoint.
+    pub fn resume(&mut self, pc: usize) {
+        self.resume = Some(pc);
+    }
+    /// Whether to stop before the instruction at `pc`.
+    pub(crate) fn check_breakpoint(&mut self, pc: usize) -> bool {
+        if self.resume.take() == Some(pc) {
+            return false;
+        }
+        let hit = self
+            .breakpoints
+            .iter()
+            .any(|b| b.enabled && b.address == pc);
+        if hit {
+            self.resume = Some(pc);
+        }
+        return hit;
+    }
+    pub(crate) fn check_read(&mut self, address: usize, value: u32) {
+        self.check(address, value, value, |kind| {
+            matches!(kind, WatchKind::Read | WatchKind::Access)
+        });
+    }
+    /// `old` is only read when a watchpoint covers `address`.
+    pub(crate) fn check_write(&mut self, address: usize, old: impl Fn() -> u32, value: u32) {
+        if !self
+            .watchpoints
+            .iter()
+            .any(|w| w.contains(address & ADDRESS_MASK))
+        {
+            return;
+        }
+        let old = old();
+        self.check(address, old, value, |kind| match kind {
+            WatchKind::Read => false,
+            WatchKind::Write | WatchKind::Access => true,
+            WatchKind::Change => old != value,
+        });
+    }
+    fn check(
+        &mut self,
+        address: usize,
+        old: u32,
+        value: u32,
+        triggers: impl Fn(WatchKind) -> bool,
+    ) {
+        let address = address & ADDRESS_MASK;
+        if self.hit.is_some() {
+            return;
+        }
+        let Some(watchpoint) = self
+            .watchpoints
+            .iter()
+            .find(|w| w.contains(address) && triggers(w.kind))
+        else {
+            return;
+        };
+        self.hit = Some(WatchpointHit {
+            pc: 0,
+            address,
+            kind: watchpoint.kind,
+            old,
+            value,
+        });
+    }
+    pub(crate) fn clear_hit(&mut self) {
+        self.hit = None;
+    }
+    /// The first watchpoint the instruction at `pc` hit.
+    pub(crate) fn take_hit(&mut self, pc: usize) -> Option<WatchpointHit> {
+        let mut hit = self.hit.take()?;
+        hit.pc = pc;
+        return Some(hit);
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
@@ -0,0 +1,24 @@
+/// The fields latched out of an instruction word.
+///
+/// Every field is extracted for every instruction; which of them are
+/// meaningful depends on the opcode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Decoded {
+    pub ir: u32,
+    pub opcode: u32,
+    pub dr: usize,
+    pub sr2: usize,
+    pub sr1: usize,
+    pub immediate: u32,
+}
+
+pub fn decode(instr: u32) -> Decoded {
+    Decoded {
+        ir: instr,
+        opcode: instr >> 28 & 0x0F,
+        dr: ((instr >> 16) & 0x0F) as usize,
+        sr2: ((instr >> 20) & 0x0F) as usize,
+        sr1: ((instr >> 24) & 0x0F) as usize,
+        immediate: instr & 0xFFFFFF,
+    }
+}
@@ -0,0 +1,249 @@
+use crate::cpu::{ALU_GROUP_FLOAT, ALU_GROUP_INTEGER, ALU_GROUP_SIGNED};
+use crate::decoder::decode;
+
+/// Integer ALU operations by op, `None` where there is no mnemonic. The
+/// bool is set for single operand operations.
+const INTEGER_OPS: [Option<(&str, bool)>; 16] = [
+    Some(("ADD", false)),
+    Some(("SUB", false)),
+    Some(("MUL", false)),
+    Some(("NIG", true)),
+    Some(("AND", false)),
+    Some(("OR", false)),
+    Some(("NOT", true)),
+    Some(("NAND", false)),
+    Some(("XOR", false)),
+    Some(("XNOR", false)),
+    Some(("LS", false)),
+    Some(("RS", false)),
+    Some(("ARS", false)),
+    Some(("RRS", false)),
+    Some(("RLS", false)),
+    None,
+];
+const SIGNED_OPS: [(&str, bool); 3] = [("MULIS", false), ("DIVIU", false), ("DIVIS", false)];
+const FLOAT_OPS: [(&str, bool); 9] = [
+    ("ADF", false),
+    ("SBF", false),
+    ("MULF", false),
+    ("DIVF", false),
+    ("NF", true),
+    ("UITF", true),
+    ("SITF", true),
+    ("FTI", true),
+    ("FTU", true),
+];
+const INTEGER_COMPARES: [&str; 5] = ["CMP-GT", "CMP-EQ", "CMP-LT", "CMP-GE", "CMP-LE"];
+const SIGNED_COMPARES: [&str; 5] = ["CGTSI", "CEQSI", "CLTSI", "CGESI", "CLESI"];
+const FLOAT_COMPARES: [&str; 5] = ["CGTF", "CEQF", "CLTF", "CGEF", "CLEF"];
+/// Jumps by 4-bit condition. Condition 7 always jumps like 0 but has no
+/// mnemonic.
+const JUMPS: [Option<&str>; 16] = [
+    Some("JMP"),
+    Some("JP-Cr"),
+    Some("JP-NCr"),
+    Some("JP-CMP"),
+    Some("JP-NCMP"),
+    Some("JP-Zr"),
+    Some("JP-NZr"),
+    None,
+    Some("JP-Or"),
+    Some("JP-NOr"),
+    Some("JP-Nr"),
+    Some("JP-NNr"),
+    Some("JP-GTS"),
+    Some("JP-LES"),
+    Some("JP-GES"),
+    Some("JP-LTS"),
+];
+
+/// The name of register `index` in `astCPU.asm`.
+pub fn register_name(index: usize) -> String {
+    if index == 15 {
+        return "tmp".to_string();
+    }
+    return format!("R{}", index);
+}
+
+/// Turns `word` back into the mnemonic syntax of `assembler/astCPU.asm`,
+/// e.g. `ADD R1 - R0, R2` or `LDI R3 - 0xfb4fff`. Returns `None` for words
+/// no rule assembles to, such as illegal ALU operations or words with bits
+/// set that the rule leaves clear (even where the CPU ignores them), so the
+/// result always assembles back to `word`.
+pub fn disassemble(word: u32) -> Option<String> {
+    let decoded = decode(word);
+    let dr = register_name(decoded.dr);
+    let sr1 = register_name(decoded.sr1);
+    let sr2 = register_name(decoded.sr2);
+    let immediate = decoded.immediate;
+    let op = (word >> 12 & 0x0F) as usize;
+    let group = word >> 10 & 0x03;
+    // The bits the rule may set, every other bit has to be clear
+    let (instruction, fields) = match decoded.opcode {
+        0 => ("NOP".to_string(), 0xF0000000),
+        1 => {
+            let (mnemonic, unary) = match group {
+                ALU_GROUP_INTEGER => INTEGER_OPS[op]?,
+                ALU_GROUP_SIGNED => *SIGNED_OPS.get(op)?,
+                ALU_GROUP_FLOAT => *FLOAT_OPS.get(op)?,
+                _ => return None,
+            };
+            if unary {
+                (format!("{} {} - {}", mnemonic, dr, sr1), 0xFF0FFC00)
+            } else {
+                (
+                    format!("{} {} - {}, {}", mnemonic, dr, sr1, sr2),
+                    0xFFFFFC00,
+                )
+            }
+        }
+        2 => {
+            let compares = match group {
+                ALU_GROUP_INTEGER => INTEGER_COMPARES,
+                ALU_GROUP_SIGNED => SIGNED_COMPARES,
+                ALU_GROUP_FLOAT => FLOAT_COMPARES,
+                _ => return None,
+            };
+            (
+                format!("{} {}, {}", compares.get(op)?, sr1, sr2),
+                0xFFF0FC00,
+            )
+        }
+        3 => {
+            if word >> 27 & 0x01 == 1 {
+                let condition = (word >> 24 & 0x07 | word >> 20 & 0x08) as usize;
+                let target = immediate & 0x7FFFFF;
+                (
+                    format!("{} 0x{:06x}", JUMPS[condition]?, target),
+                    0xFFFFFFFF,
+                )
+            } else {
+                let condition = (word >> 24 & 0x07 | word >> 16 & 0x08) as usize;
+                (format!("{} {}", JUMPS[condition]?, sr2), 0xFFF80000)
+            }
+        }
+        4 => (format!("LD {} - 0x{:06x}", sr1, immediate), 0xFFFFFFFF),
+        5 => (format!("LD {} - {}", dr, sr2), 0xF0FF0000),
+        6 => (format!("LDI {} - 0x{:06x}", sr1, immediate), 0xFFFFFFFF),
+        7 => (format!("ST {} - 0x{:06x}", sr1, immediate), 0xFFFFFFFF),
+        8 => (format!("ST {} - {}", sr1, sr2), 0xFFF00000),
+        9 => (format!("MOV {} - {}", sr1, dr), 0xFF0F0000),
+        10 => {
+            if word >> 7 & 0x01 == 1 {
+                (format!("OPW-En - {}", word >> 9 & 0x07), 0xF0000E80)
+            } else if word >> 8 & 0x01 == 1 {
+                (format!("OPD2W - {}", sr1), 0xFF000100)
+            } else {
+                (format!("OPD1W - {}", sr1), 0xFF000000)
+            }
+        }
+        11 => (format!("PUSH {}", sr1), 0xFF000000),
+        // Opcode 12 also pushes with bits 22-23 clear, but only 11 has a rule
+        12 => match word >> 22 & 0x03 {
+            0 => return None,
+            1 => (format!("POP {}", dr), 0xF0CF0000),
+            2 => (format!("TOP {}", dr), 0xF0CF0000),
+            _ => (format!("CLR {}", dr), 0xF0CF0000),
+        },
+        13 => {
+            if word >> 25 & 0x01 == 1 {
+                ("RET".to_string(), 0xF2000000)
+            } else {
+                (format!("CALL 0x{:06x}", immediate), 0xF0FFFFFF)
+            }
+        }
+        14 => match word >> 26 & 0x03 {
+            0 => (format!("SYS 0x{:06x}", immediate), 0xF0FFFFFF),
+            1 if word >> 25 & 0x01 == 1 => ("EI".to_string(), 0xF6000000),
+            1 => ("DI".to_string(), 0xF4000000),
+            2 => ("RETI".to_string(), 0xF8000000),
+            _ => (format!("STRETI {}", sr2), 0xFCF00000),
+        },
+        _ if word == 0xFFFFFFFF => ("HLT".to_string(), 0xFFFFFFFF),
+        _ => return None,
+    };
+    if word & !fields != 0 {
+        return None;
+    }
+    return Some(instruction);
+}
+
+/// [`disassemble`], or a `#d` directive marked as not an instruction, so
+/// every word gets a line that assembles back to it.
+pub fn disassemble_line(word: u32) -> String {
+    return disassemble(word)
+        .unwrap_or_else(|| format!("#d 0x{:08x} ; ??? not an instruction", word));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Assembler;
+
+    /// Bank for test programs, which puts address 0 at word 0.
+    const BANK: &str = "#bankdef test {\n#bits 32\n#outp 0\n}\n";
+
+    fn assemble(source: &str) -> Vec<u32> {
+        let mut assembler = Assembler::new();
+        assembler.add_source("test.asm", format!("{}{}", BANK, source));
+        return assembler.assemble().unwrap();
+    }
+
+    /// Every rule of `astCPU.asm`, with operands that fill their fields.
+    const EVERY_RULE: &str = "NOP
+        ADD R1 - R2, R3\nSUB R4 - R5, R6\nMUL R7 - R8, R9\nNIG R10 - R11
+        MULIS R12 - R13, R14\nDIVIU tmp - R0, R1\nDIVIS R2 - R3, R4
+        AND R1 - R2, R3\nOR R1 - R2, R3\nNOT R1 - R2\nNAND R1 - R2, R3
+        XOR R1 - R2, R3\nXNOR R1 - R2, R3\nLS R1 - R2, R3\nRS R1 - R2, R3
+        ARS R1 - R2, R3\nRRS R1 - R2, R3\nRLS R1 - R2, R3
+        ADF R1 - R2, R3\nSBF R1 - R2, R3\nMULF R1 - R2, R3\nDIVF R1 - R2, R3
+        NF R1 - R2\nUITF R1 - R2\nSITF R1 - R2\nFTI R1 - R2\nFTU R1 - R2
+        CMP-GT R1, R2\nCMP-EQ R1, R2\nCMP-LT R1, R2\nCMP-GE R1, R2\nCMP-LE R1, R2
+        CGTSI R1, R2\nCEQSI R1, R2\nCLTSI R1, R2\nCGESI R1, R2\nCLESI R1, R2
+        CGTF R1, R2\nCEQF R1, R2\nCLTF R1, R2\nCGEF R1, R2\nCLEF R1, R2
+        JMP R5\nJMP 0x7fffff\nJP-Cr R5\nJP-Cr 0x123\nJP-NCr R5\nJP-NCr 0x123
+        JP-CMP R5\nJP-CMP 0x123\nJP-NCMP R5\nJP-NCMP 0x123\nJP-Zr R5\nJP-Zr 0x123
+        JP-NZr R5\nJP-NZr 0x123\nJP-Or R5\nJP-Or 0x123\nJP-NOr R5\nJP-NOr 0x123
+        JP-Nr R5\nJP-Nr 0x123\nJP-NNr R5\nJP-NNr 0x123\nJP-GTS R5\nJP-GTS 0x123
+        JP-LES R5\nJP-LES 0x123\nJP-GES R5\nJP-GES 0x123\nJP-LTS R5\nJP-LTS 0x123
+        LD R1 - 0xabcdef\nLD R2 - R3\nLDI R4 - 0xffffff\nST R5 - R6\nST R7 - 0x000010
+        MOV R8 - R9\nMOV [R10] - R11\nMOV R12 - [R13]
+        OPW-En - 7\nOPD1W - R1\nOPD2W - R2
+        PUSH R3\nPOP R4\nTOP R5\nCLR R6\nCALL 0x000020\nRET
+        SYS 0x000042\nRETI\nSTRETI R7\nEI\nDI\nHLT\n";
+
+    #[test]
+    fn every_rule_disassembles_to_a_line_that_assembles_back() {
+        let words = assemble(EVERY_RULE);
+        assert_eq!(words.len(), EVERY_RULE.lines().count());
+        for word in words {
+            let line =
+                disassemble(word).unwrap_or_else(|| panic!("0x{:08x} did not disassemble", word));
+            assert_eq!(assemble(&line), [word], "{}", line);
+        }
+    }
+
+    #[test]
+    fn words_without_a_rule_become_data() {
+        let words = [
+            // Opcode 12 pushes like 11, but only 11 has a rule
+            0xC1000000, // Condition 7 jumps always, but has no mnemonic
+            0x3F000010, 0x37500000, // Bits the CPU ignores
+            0x00000001, 0xB3000001, 0xF0000000, // Unused ALU operations and groups
+            0x1123F000, 0x11233400, 0x11239800, 0x11230C00, 0x21205000,
+        ];
+        for word in words {
+            assert_eq!(disassemble(word), None, "0x{:08x}", word);
+            let line = disassemble_line(word);
+            assert!(line.starts_with("#d "), "{}", line);
+            assert_eq!(assemble(&line), [word], "{}", line);
+        }
+    }
+
+    #[test]
+    fn register_operands_use_the_assembler_names() {
+        assert_eq!(disassemble(0x1123_0000).unwrap(), "ADD R3 - R1, R2");
+        assert_eq!(disassemble(0x6F00_0010).unwrap(), "LDI tmp - 0x000010");
+        assert_eq!(disassemble(0xC04F_0000).unwrap(), "POP tmp");
+    }
+}
@@ -0,0 +1,112 @@
+use crate::bus::BusDevice;
+use crate::port::{Latch, PortDevice};
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+use std::any::Any;
+use std::io;
+
+/// First word of the memory-mapped framebuffer.
+pub const FRAMEBUFFER_ADDRESS: usize = 0xFB5000;
+pub const FRAMEBUFFER_WIDTH: usize = 640;
+pub const FRAMEBUFFER_HEIGHT: usize = 480;
+
+/// The 640x480 memory-mapped framebuffer, one `0x00RRGGBB` word per pixel.
+/// `dirty` is set by every store so front ends only redraw on change.
+pub struct Framebuffer {
+    pub pixels: Vec<u32>,
+    pub dirty: bool,
+}
+
+impl Framebuffer {
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            pixels: vec![0; FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT],
+            dirty: true,
+        }
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer::new()
+    }
+}
+
+impl BusDevice for Framebuffer {
+    fn read(&mut self, offset: usize) -> u32 {
+        return self.pixels[offset];
+    }
+    fn write(&mut self, offset: usize, value: u32) {
+        self.pixels[offset] = value;
+        self.dirty = true;
+    }
+    fn peek(&self, offset: usize) -> u32 {
+        return self.pixels[offset];
+    }
+    fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        return out.put_words(&self.pixels);
+    }
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        input.get_words_into(&mut self.pixels)?;
+        // Host side flag, the screen has to be redrawn either way
+        self.dirty = true;
+        return Ok(());
+    }
+    fn poke(&mut self, offset: usize, value: u32) {
+        self.write(offset, value);
+    }
+    fn save_registers(&self, _out: &mut StateWriter) -> io::Result<()> {
+        return Ok(());
+    }
+    fn load_registers(&mut self, _input: &mut StateReader) -> Result<(), SnapshotError> {
+        return Ok(());
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Port decode values of the six 256x256 RGB Video displays in
+/// `logisim/curcuit.circ`, in the order of their write enables: `dsp1_1W`,
+/// `dsp2_1W`, `dsp3_1W` (top row), then `dsp1_2W`, `dsp2_2W`, `dsp3_2W`.
+pub const PIXEL_DISPLAY_DECODES: [u8; 6] = [1, 2, 3, 4, 5, 6];
+pub const PIXEL_DISPLAY_SIZE: usize = 256;
+
+/// A pixel display like Logisim's RGB Video component. D1 holds the linear
+/// pixel coordinate (`y * width + x`) and writing D2 plots the `0x00RRGGBB`
+/// color it holds at that coordinate.
+pub struct PixelDisplay {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>,
+}
+
+impl PixelDisplay {
+    pub fn new(width: usize, height: usize) -> PixelDisplay {
+        PixelDisplay {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+}
+
+impl PortDevice for PixelDisplay {
+    fn write(&mut self, latch: Latch, d1: u32, d2: u32) {
+        if latch == Latch::D2 {
+            let index = d1 as usize % self.pixels.len();
+            self.pixels[index] = d2 & 0xFFFFFF;
+        }
+    }
+    fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        return out.put_words(&self.pixels);
+    }
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        return input.get_words_into(&mut self.pixels);
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
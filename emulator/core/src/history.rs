@@ -0,0 +1,470 @@
+use crate::cpu::registers::Registers;
+use crate::cpu::{CPUError, CPU};
+use crate::memory::ADDRESS_MASK;
+use crate::snapshot::{StateReader, StateWriter};
+use log::info;
+use std::collections::{HashMap, VecDeque};
+
+/// Instructions [`CPU::step_back`] can undo one at a time by default.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 10_000;
+/// Instructions between two checkpoints by default.
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 1_000;
+/// Checkpoints kept by default, so rewinds reach about a million
+/// instructions back.
+pub const DEFAULT_MAX_CHECKPOINTS: usize = 1_000;
+
+/// Everything but the memory devices, as it was before an instruction.
+#[derive(Clone)]
+struct MachineState {
+    registers: Registers,
+    clock: u64,
+    opcode: u32,
+    ir: u32,
+    dr: usize,
+    sr2: usize,
+    sr1: usize,
+    immediate: u32,
+    recent_memory_accesses: (u32, u32),
+    /// The bus devices' [`crate::BusDevice::save_registers`].
+    devices: Vec<u8>,
+}
+
+/// Undo delta of one instruction.
+struct UndoRecord {
+    state: MachineState,
+    /// Physical address and previous value of every word written, in order.
+    writes: Vec<(usize, u32)>,
+}
+
+/// The machine at some past instruction, without a copy of memory: it
+/// keeps the value every word written since had at the checkpoint, so it
+/// only grows with the words a program touches.
+struct Checkpoint {
+    instruction: u64,
+    state: MachineState,
+    first_writes: HashMap<usize, u32>,
+}
+
+/// Execution history for reverse debugging.
+///
+/// Before every instruction the CPU pushes an undo record to a ring buffer
+/// of `capacity` records and logs the old value of each word it writes, so
+/// [`CPU::step_back`] restores the previous instruction exactly. Every
+/// `checkpoint_interval` instructions it also starts a [`Checkpoint`];
+/// rewinding further back than the ring buffer restores the nearest one
+/// and executes forward again, which is exact as execution is
+/// deterministic. Changes made from outside (host interrupts, fault
+/// recovery, reset) start a new checkpoint so replays never cross them.
+///
+/// Output already sent to port devices, such as plotted pixels, is not
+/// taken back.
+pub struct History {
+    pub capacity: usize,
+    pub checkpoint_interval: u64,
+    pub max_checkpoints: usize,
+    records: VecDeque<UndoRecord>,
+    checkpoints: VecDeque<Checkpoint>,
+    /// Instructions executed since recording started, minus those undone.
+    instructions: u64,
+    checkpoint_next: bool,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            capacity: DEFAULT_HISTORY_CAPACITY,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            max_checkpoints: DEFAULT_MAX_CHECKPOINTS,
+            records: VecDeque::new(),
+            checkpoints: VecDeque::new(),
+            instructions: 0,
+            checkpoint_next: true,
+        }
+    }
+    /// Instructions [`CPU::step_back`] can undo without a replay.
+    pub fn len(&self) -> usize {
+        return self.records.len();
+    }
+    pub fn is_empty(&self) -> bool {
+        return self.records.is_empty();
+    }
+    pub fn instructions(&self) -> u64 {
+        return self.instructions;
+    }
+    /// The earliest instruction count the history can rewind to.
+    pub fn earliest(&self) -> u64 {
+        let undo = self.instructions - self.records.len() as u64;
+        return self
+            .checkpoints
+            .front()
+            .map_or(undo, |checkpoint| checkpoint.instruction.min(undo));
+    }
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.checkpoints.clear();
+        self.instructions = 0;
+        self.checkpoint_next = true;
+    }
+    /// Makes the next instruction start a checkpoint, after the machine
+    /// was changed from outside.
+    pub fn checkpoint_next(&mut self) {
+        self.checkpoint_next = true;
+    }
+    fn push(&mut self, state: MachineState) {
+        let due = match self.checkpoints.back() {
+            Some(checkpoint) => {
+                self.instructions >= checkpoint.instruction + self.checkpoint_interval
+            }
+            None => true,
+        };
+        if self.checkpoint_next || due {
+            self.checkpoint_next = false;
+            self.checkpoints.push_back(Checkpoint {
+                instruction: self.instructions,
+                state: state.clone(),
+                first_writes: HashMap::new(),
+            });
+            if self.checkpoints.len() > self.max_checkpoints {
+                self.checkpoints.pop_front();
+            }
+        }
+        self.records.push_back(UndoRecord {
+            state,
+            writes: Vec::new(),
+        });
+        if self.records.len() > self.capacity {
+            self.records.pop_front();
+        }
+        self.instructions += 1;
+    }
+    fn log_write(&mut self, address: usize, old: u32) {
+        if let Some(record) = self.records.back_mut() {
+            record.writes.push((address, old));
+        }
+        if let Some(checkpoint) = self.checkpoints.back_mut() {
+            checkpoint.first_writes.entry(address).or_insert(old);
+        }
+    }
+    fn pop(&mut self) -> Option<UndoRecord> {
+        let record = self.records.pop_back()?;
+        self.instructions -= 1;
+        while self
+            .checkpoints
+            .back()
+            .is_some_and(|checkpoint| checkpoint.instruction > self.instructions)
+        {
+            self.checkpoints.pop_back();
+        }
+        return Some(record);
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+/// Reverse execution, see [`History`].
+impl CPU {
+    /// Starts recording with a [`History`] of default size, or stops and
+    /// drops the recorded history.
+    pub fn set_history(&mut self, enabled: bool) {
+        self.history = enabled.then(History::new);
+    }
+    pub fn history(&self) -> Option<&History> {
+        return self.history.as_ref();
+    }
+    pub fn history_mut(&mut self) -> Option<&mut History> {
+        return self.history.as_mut();
+    }
+    fn capture_state(&self) -> MachineState {
+        let mut devices = Vec::new();
+        self.bus
+            .save_registers(&mut StateWriter::new(&mut devices))
+            .expect("writing to memory cannot fail");
+        return MachineState {
+            registers: self.registers.clone(),
+            clock: self.clock,
+            opcode: self.opcode,
+            ir: self.ir,
+            dr: self.dr,
+            sr2: self.sr2,
+            sr1: self.sr1,
+            immediate: self.immediate,
+            recent_memory_accesses: self.recent_memory_accesses,
+            devices,
+        };
+    }
+    fn restore_state(&mut self, state: &MachineState) {
+        self.registers = state.registers.clone();
+        self.clock = state.clock;
+        self.opcode = state.opcode;
+        self.ir = state.ir;
+        self.dr = state.dr;
+        self.sr2 = state.sr2;
+        self.sr1 = state.sr1;
+        self.immediate = state.immediate;
+        self.recent_memory_accesses = state.recent_memory_accesses;
+        self.bus
+            .load_registers(&mut StateReader::new(&mut state.devices.as_slice()))
+            .expect("the bus saved these registers");
+    }
+    /// Called before every instruction.
+    pub(crate) fn record_history(&mut self) {
+        if self.history.is_none() {
+            return;
+        }
+        let state = self.capture_state();
+        if let Some(history) = &mut self.history {
+            history.push(state);
+        }
+    }
+    /// Called before every store to the physical `address`.
+    pub(crate) fn record_write(&mut self, address: usize) {
+        if let Some(history) = &mut self.history {
+            let address = address & ADDRESS_MASK;
+            history.log_write(address, self.bus.peek(address));
+        }
+    }
+    /// Undoes the last instruction. Returns false once the undo records
+    /// run out.
+    pub fn step_back(&mut self) -> bool {
+        let Some(record) = self.history.as_mut().and_then(History::pop) else {
+            return false;
+        };
+        for (address, old) in record.writes.iter().rev() {
+            self.bus.poke(*address, *old);
+        }
+        self.restore_state(&record.state);
+        return true;
+    }
+    /// Undoes `instructions` instructions, or as many as the history
+    /// reaches back. Returns how many were undone.
+    pub fn rewind(&mut self, instructions: u64) -> u64 {
+        let Some(history) = &self.history else {
+            return 0;
+        };
+        let current = history.instructions;
+        let target = current.saturating_sub(instructions).max(history.earliest());
+        self.rewind_to(target);
+        return current - self.history.as_ref().map_or(current, History::instructions);
+    }
+    /// Goes back to the last instruction boundary at least `cycles` cycles
+    /// ago. Returns false if the history does not reach that far, in which
+    /// case the CPU is left at the earliest point it does reach.
+    pub fn rewind_cycles(&mut self, cycles: u64) -> bool {
+        let target = self.clock.saturating_sub(cycles);
+        while self.clock > target && self.step_back() {}
+        if self.clock <= target {
+            return true;
+        }
+        let Some(history) = &self.history else {
+            return false;
+        };
+        let Some(index) = history
+            .checkpoints
+            .iter()
+            .rposition(|checkpoint| checkpoint.state.clock <= target)
+        else {
+            if !history.checkpoints.is_empty() {
+                self.restore_checkpoint(0);
+            }
+            return false;
+        };
+        self.restore_checkpoint(index);
+        while self.clock < target {
+            if !self.replay() {
+                break;
+            }
+        }
+        if self.clock > target {
+            self.step_back();
+        }
+        return true;
+    }
+    /// Steps back to just before the last recorded instruction that wrote
+    /// the physical `address`. Returns false, leaving the CPU alone, if
+    /// no undo record holds such a write.
+    pub fn run_back_to_write(&mut self, address: usize) -> bool {
+        let address = address & ADDRESS_MASK;
+        let Some(history) = &self.history else {
+            return false;
+        };
+        let Some(steps) = history
+            .records
+            .iter()
+            .rev()
+            .position(|record| record.writes.iter().any(|(a, _)| *a == address))
+        else {
+            return false;
+        };
+        for _ in 0..=steps {
+            self.step_back();
+        }
+        return true;
+    }
+    /// Moves to instruction count `target`, which must be within
+    /// [`History::earliest`] and the current count.
+    fn rewind_to(&mut self, target: u64) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        if history.instructions - target > history.records.len() as u64 {
+            if let Some(index) = history
+                .checkpoints
+                .iter()
+                .rposition(|checkpoint| checkpoint.instruction <= target)
+            {
+                self.restore_checkpoint(index);
+            }
+        }
+        while self
+            .history
+            .as_ref()
+            .is_some_and(|h| h.instructions < target)
+        {
+            if !self.replay() {
+                break;
+            }
+        }
+        while self
+            .history
+            .as_ref()
+            .is_some_and(|h| h.instructions > target)
+        {
+            if !self.step_back() {
+                break;
+            }
+        }
+    }
+    /// Puts memory and registers back to checkpoint `index` and drops all
+    /// history after it.
+    fn restore_checkpoint(&mut self, index: usize) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        let mut later = history.checkpoints.split_off(index);
+        history.records.clear();
+        // Newest first, so the oldest value of a word wins
+        for checkpoint in later.iter().rev() {
+            for (&address, &old) in checkpoint.first_writes.iter() {
+                self.bus.poke(address, old);
+            }
+        }
+        let Some(checkpoint) = later.pop_front() else {
+            return;
+        };
+        self.restore_state(&checkpoint.state);
+        if let Some(history) = &mut self.history {
+            history.instructions = checkpoint.instruction;
+            history.checkpoint_next = true;
+        }
+        if self.log {
+            info!(
+                "Rewound to checkpoint at clock {}, instruction {}",
+                self.clock, checkpoint.instruction
+            );
+        }
+    }
+    /// Executes an instruction again after a rewind. The recorded run went
+    /// on past it, so an error other than an uninitialised read means the
+    /// history was cut short and the replay has to stop.
+    fn replay(&mut self) -> bool {
+        // Breakpoints and watchpoints were already hit on the way
+        let debugger = std::mem::take(&mut self.debugger);
+        let error = self.execute_instruction(false, 0);
+        self.debugger = debugger;
+        return matches!(error, CPUError::Ok | CPUError::UninitializedRead(_));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::{CPUError, CPU};
+    use crate::display::{PixelDisplay, PIXEL_DISPLAY_DECODES};
+    use crate::{Assembler, RamInit};
+
+    /// Plots two pixels at 5 through the IO window, stores to RAM, raises
+    /// interrupt 0 and enables the timer.
+    const PROGRAM: &str = "#bankdef test {\n#bits 32\n#outp 0\n}\n\
+        LDI R1 - 1\nST R1 - 0x7fffa1\nLDI R2 - 5\nST R2 - 0x7fffa2\n\
+        LDI R3 - 0xff0000\nST R3 - 0x7fffa3\nLDI R3 - 0x00ff00\nST R3 - 0x7fffa3\n\
+        ST R1 - 0x30\nST R1 - 0x7fffc2\nST R1 - 0x7ffffa\n";
+    const INSTRUCTIONS: usize = 11;
+
+    fn recording() -> CPU {
+        let mut assembler = Assembler::new();
+        assembler.add_source("test.asm", PROGRAM);
+        let words = assembler.assemble().unwrap();
+        let mut cpu = CPU::with_ram_init(words, RamInit::Zero, false);
+        cpu.set_history(true);
+        return cpu;
+    }
+
+    fn run(cpu: &mut CPU, instructions: usize) {
+        for _ in 0..instructions {
+            assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        }
+    }
+
+    fn pixel(cpu: &CPU) -> u32 {
+        let display = cpu.port().device::<PixelDisplay>(PIXEL_DISPLAY_DECODES[0]);
+        return display.unwrap().pixels[5];
+    }
+
+    #[test]
+    fn step_back_undoes_registers_and_memory() {
+        let mut cpu = recording();
+        run(&mut cpu, 9);
+        assert_eq!(cpu.bus.peek(0x30), 1);
+        assert!(cpu.step_back());
+        assert_eq!(cpu.bus.peek(0x30), 0);
+        assert_eq!(cpu.registers.pc, 8);
+        while cpu.step_back() {}
+        assert_eq!(cpu.registers.pc, 0);
+        assert_eq!(cpu.clock, 0);
+        assert_eq!(cpu.registers[1], 0);
+        assert_eq!(cpu.history().unwrap().instructions(), 0);
+    }
+
+    #[test]
+    fn undoing_device_stores_has_no_side_effects() {
+        let mut cpu = recording();
+        run(&mut cpu, INSTRUCTIONS);
+        assert_eq!(pixel(&cpu), 0x00ff00);
+        assert_eq!(cpu.interrupts().pending, 1);
+        assert!(cpu.timer().enabled);
+
+        assert!(cpu.step_back());
+        assert!(cpu.step_back());
+        assert!(!cpu.timer().enabled);
+        assert_eq!(cpu.interrupts().pending, 0);
+        // Undoing the second plot restores the latch without plotting
+        assert!(cpu.step_back());
+        assert!(cpu.step_back());
+        assert_eq!(cpu.port().d2, 0xff0000);
+        assert_eq!(pixel(&cpu), 0x00ff00);
+
+        run(&mut cpu, 4);
+        assert_eq!(cpu.interrupts().pending, 1);
+        assert!(cpu.timer().enabled);
+    }
+
+    #[test]
+    fn rewind_past_the_undo_records_replays_from_a_checkpoint() {
+        let mut cpu = recording();
+        let history = cpu.history_mut().unwrap();
+        history.capacity = 2;
+        history.checkpoint_interval = 4;
+        run(&mut cpu, INSTRUCTIONS);
+        assert_eq!(cpu.rewind(3), 3);
+        assert_eq!(cpu.registers.pc, 8);
+        assert_eq!(cpu.bus.peek(0x30), 0);
+        assert_eq!(cpu.interrupts().pending, 0);
+        assert_eq!(cpu.rewind(100), 8);
+        assert_eq!(cpu.registers.pc, 0);
+        assert_eq!(cpu.port().d2, 0);
+        assert_eq!(pixel(&cpu), 0x00ff00);
+    }
+}
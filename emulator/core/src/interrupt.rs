@@ -0,0 +1,147 @@
+use crate::bus::BusDevice;
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+use std::any::Any;
+use std::io;
+
+/// Number of interrupt lines, line 0 has the highest priority.
+pub const INTERRUPT_LINES: usize = 8;
+/// Address of the vector table, word `INTERRUPT_VECTOR_TABLE + line` holds
+/// the handler of that line.
+pub const INTERRUPT_VECTOR_TABLE: usize = 0x48;
+/// First word of the interrupt controller registers.
+pub const INTERRUPT_CONTROLLER_START: usize = 0x7fffc0;
+/// Last word of the interrupt controller registers.
+pub const INTERRUPT_CONTROLLER_END: usize = 0x7fffc3;
+
+/// Read: lines waiting to be taken. Write: clears the lines set in the
+/// value, to acknowledge a level a handler has dealt with.
+pub const INTERRUPT_PENDING: usize = 0x00;
+/// Read/write. Lines allowed to interrupt the CPU, 1 = enabled.
+pub const INTERRUPT_MASK: usize = 0x01;
+/// Write-only. Raises the lines set in the value (software interrupts).
+pub const INTERRUPT_RAISE: usize = 0x02;
+
+const LINES_MASK: u32 = (1 << INTERRUPT_LINES) - 1;
+
+/// Pending/mask register pair of the interrupt lines, mapped at
+/// `0x7fffc0 - 0x7fffc3`.
+///
+/// Lines are raised by the host through [`crate::CPU::raise_interrupt`], by
+/// bus devices through [`BusDevice::poll_interrupts`] or by software through
+/// [`INTERRUPT_RAISE`]. A raised line stays pending until the CPU takes it,
+/// which only happens while it is unmasked and interrupts are enabled.
+pub struct InterruptController {
+    pub pending: u32,
+    pub mask: u32,
+}
+
+impl InterruptController {
+    pub fn new() -> InterruptController {
+        InterruptController {
+            pending: 0,
+            mask: 0,
+        }
+    }
+    pub fn raise(&mut self, line: u8) {
+        self.pending |= (1 << (line as usize % INTERRUPT_LINES)) & LINES_MASK;
+    }
+    /// The highest priority pending, unmasked line.
+    pub fn next(&self) -> Option<u8> {
+        let ready = self.pending & self.mask;
+        if ready == 0 {
+            return None;
+        }
+        return Some(ready.trailing_zeros() as u8);
+    }
+    /// Clears `line`, called when the CPU enters its handler.
+    pub fn acknowledge(&mut self, line: u8) {
+        self.pending &= !(1 << line);
+    }
+    pub fn reset(&mut self) {
+        self.pending = 0;
+        self.mask = 0;
+    }
+}
+
+impl Default for InterruptController {
+    fn default() -> Self {
+        InterruptController::new()
+    }
+}
+
+impl BusDevice for InterruptController {
+    fn read(&mut self, offset: usize) -> u32 {
+        return self.peek(offset);
+    }
+    fn write(&mut self, offset: usize, value: u32) {
+        match offset {
+            INTERRUPT_PENDING => self.pending &= !value,
+            INTERRUPT_MASK => self.mask = value & LINES_MASK,
+            INTERRUPT_RAISE => self.pending |= value & LINES_MASK,
+            _ => {}
+        }
+    }
+    fn peek(&self, offset: usize) -> u32 {
+        match offset {
+            INTERRUPT_PENDING => self.pending,
+            INTERRUPT_MASK => self.mask,
+            _ => 0,
+        }
+    }
+    fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        out.put_u32(self.pending)?;
+        return out.put_u32(self.mask);
+    }
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.pending = input.get_u32()?;
+        self.mask = input.get_u32()?;
+        return Ok(());
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_lowest_unmasked_pending_line_comes_next() {
+        let mut controller = InterruptController::new();
+        controller.raise(5);
+        controller.raise(3);
+        assert_eq!(controller.next(), None);
+        controller.mask = 0xFF;
+        assert_eq!(controller.next(), Some(3));
+        controller.mask = 0xF7;
+        assert_eq!(controller.next(), Some(5));
+        controller.mask = 0xFF;
+        controller.acknowledge(3);
+        assert_eq!(controller.next(), Some(5));
+        controller.acknowledge(5);
+        assert_eq!(controller.next(), None);
+        // Lines wrap around the eight there are
+        controller.raise(9);
+        assert_eq!(controller.pending, 0x02);
+    }
+
+    #[test]
+    fn registers_raise_clear_and_mask_lines() {
+        let mut controller = InterruptController::new();
+        controller.write(INTERRUPT_MASK, 0x1F0);
+        assert_eq!(controller.read(INTERRUPT_MASK), 0xF0);
+        controller.write(INTERRUPT_RAISE, 0x1A4);
+        assert_eq!(controller.read(INTERRUPT_PENDING), 0xA4);
+        assert_eq!(controller.read(INTERRUPT_RAISE), 0);
+        assert_eq!(controller.next(), Some(5));
+        controller.write(INTERRUPT_PENDING, 0x24);
+        assert_eq!(controller.read(INTERRUPT_PENDING), 0x80);
+        assert_eq!(controller.next(), Some(7));
+        controller.reset();
+        assert_eq!((controller.pending, controller.mask), (0, 0));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::bus::BusDevice;
+use crate::port::{Latch, OutputPort};
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+use std::any::Any;
+use std::io;
+
+/// First word of the IO window reserved by the memory map.
+pub const IO_WINDOW_START: usize = 0x7fffa0;
+/// Last word of the IO window.
+pub const IO_WINDOW_END: usize = 0x7fffb8;
+/// Last word of program memory.
+pub const PROGRAM_MEMORY_END: usize = 0x7fff98;
+
+/// Read-only. Bits 0-2: selected device, bit 3: a device is attached at
+/// the selected decode value, bits 8-15: mask of decode values with a
+/// device attached.
+pub const IO_PORT_STATUS: usize = 0x00;
+/// Read/write. The output port decode register (`OPW-En`).
+pub const IO_DEVICE_SELECT: usize = 0x01;
+/// Read/write. Output port data latch 1 (`OPD1W`).
+pub const IO_DATA1: usize = 0x02;
+/// Read/write. Output port data latch 2 (`OPD2W`).
+pub const IO_DATA2: usize = 0x03;
+
+/// The register block behind the IO window at `0x7fffa0 - 0x7fffb8`.
+///
+/// Offsets are relative to [`IO_WINDOW_START`]; unused offsets read as 0
+/// and ignore writes.
+pub struct IoWindow {
+    pub port: OutputPort,
+}
+
+impl IoWindow {
+    pub fn new(port: OutputPort) -> IoWindow {
+        IoWindow { port }
+    }
+    fn status(&self) -> u32 {
+        let mask = self.port.attached_mask() as u32;
+        let selected = self.port.decode as u32;
+        let present = (mask >> selected) & 0x01;
+        return selected | present << 3 | mask << 8;
+    }
+}
+
+impl BusDevice for IoWindow {
+    fn read(&mut self, offset: usize) -> u32 {
+        return self.peek(offset);
+    }
+    fn write(&mut self, offset: usize, value: u32) {
+        match offset {
+            IO_DEVICE_SELECT => self.port.set_decode(value as u8),
+            IO_DATA1 => self.port.write(Latch::D1, value),
+            IO_DATA2 => self.port.write(Latch::D2, value),
+            _ => {}
+        }
+    }
+    fn peek(&self, offset: usize) -> u32 {
+        match offset {
+            IO_PORT_STATUS => self.status(),
+            IO_DEVICE_SELECT => self.port.decode as u32,
+            IO_DATA1 => self.port.d1,
+            IO_DATA2 => self.port.d2,
+            _ => 0,
+        }
+    }
+    fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        return self.port.save_state(out);
+    }
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        return self.port.load_state(input);
+    }
+    /// Only the decode register and latches: what attached devices were
+    /// sent is output and is not taken back when stepping backwards.
+    fn save_registers(&self, out: &mut StateWriter) -> io::Result<()> {
+        out.put_u8(self.port.decode)?;
+        out.put_u32(self.port.d1)?;
+        return out.put_u32(self.port.d2);
+    }
+    fn load_registers(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.port.decode = input.get_u8()?;
+        self.port.d1 = input.get_u32()?;
+        self.port.d2 = input.get_u32()?;
+        return Ok(());
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::PixelDisplay;
+
+    fn window() -> IoWindow {
+        let mut port = OutputPort::new();
+        port.attach(1, Box::new(PixelDisplay::new(4, 4)));
+        port.attach(3, Box::new(PixelDisplay::new(4, 4)));
+        return IoWindow::new(port);
+    }
+
+    #[test]
+    fn status_reports_the_selection_and_attached_devices() {
+        let mut window = window();
+        assert_eq!(window.read(IO_PORT_STATUS), 0b1010 << 8);
+        window.write(IO_DEVICE_SELECT, 3);
+        assert_eq!(window.read(IO_PORT_STATUS), 0b1010 << 8 | 1 << 3 | 3);
+        window.write(IO_DEVICE_SELECT, 2);
+        assert_eq!(window.read(IO_PORT_STATUS), 0b1010 << 8 | 2);
+        // Writes to the status register are ignored.
+        window.write(IO_PORT_STATUS, 0xFFFF_FFFF);
+        assert_eq!(window.read(IO_PORT_STATUS), 0b1010 << 8 | 2);
+    }
+
+    #[test]
+    fn registers_drive_the_output_port() {
+        let mut window = window();
+        window.write(IO_DEVICE_SELECT, 0x0B);
+        window.write(IO_DATA1, 5);
+        window.write(IO_DATA2, 0xABCDEF);
+        assert_eq!(window.read(IO_DEVICE_SELECT), 3);
+        assert_eq!(window.read(IO_DATA1), 5);
+        assert_eq!(window.read(IO_DATA2), 0xABCDEF);
+        let display = window.port.device::<PixelDisplay>(3).unwrap();
+        assert_eq!(display.pixels[5], 0xABCDEF);
+        assert!(window
+            .port
+            .device::<PixelDisplay>(1)
+            .unwrap()
+            .pixels
+            .iter()
+            .all(|&p| p == 0));
+    }
+
+    #[test]
+    fn unused_offsets_read_zero_and_ignore_writes() {
+        let mut window = window();
+        let last = IO_WINDOW_END - IO_WINDOW_START;
+        window.write(0x04, 7);
+        window.write(last, 7);
+        assert_eq!(window.read(0x04), 0);
+        assert_eq!(window.read(last), 0);
+        assert_eq!(
+            (window.port.decode, window.port.d1, window.port.d2),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn registers_round_trip_without_the_devices() {
+        let mut window = window();
+        window.write(IO_DEVICE_SELECT, 1);
+        window.write(IO_DATA1, 2);
+        window.write(IO_DATA2, 3);
+        let mut saved = Vec::new();
+        window
+            .save_registers(&mut StateWriter::new(&mut saved))
+            .unwrap();
+
+        window.write(IO_DATA1, 4);
+        window.write(IO_DATA2, 9);
+        window
+            .load_registers(&mut StateReader::new(&mut saved.as_slice()))
+            .unwrap();
+        assert_eq!(
+            (window.port.decode, window.port.d1, window.port.d2),
+            (1, 2, 3)
+        );
+        // What the display was sent is output and stays.
+        assert_eq!(window.port.device::<PixelDisplay>(1).unwrap().pixels[4], 9);
+    }
+}
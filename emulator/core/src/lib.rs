@@ -0,0 +1,56 @@
+//! Core of the SS32 emulator: the CPU, its register file, the memory bus
+//! with RAM and devices, and the instruction decoder, free of any GUI or
+//! timing concerns.
+#![allow(clippy::needless_return, clippy::upper_case_acronyms)]
+
+pub mod assembler;
+pub mod bus;
+pub mod cpu;
+pub mod debugger;
+pub mod decoder;
+pub mod disassembler;
+pub mod display;
+pub mod history;
+pub mod interrupt;
+pub mod io;
+pub mod linker;
+pub mod loader;
+pub mod memory;
+pub mod mmu;
+pub mod mpu;
+pub mod object;
+pub mod port;
+pub mod snapshot;
+pub mod timer;
+
+pub use assembler::{assemble_file, AssembleError, Assembler};
+pub use bus::{Bus, BusDevice, ADDRESS_SPACE};
+pub use cpu::protection::{is_supervisor_address, PRIVILEGED_INSTRUCTION};
+pub use cpu::registers::Registers;
+pub use cpu::{
+    CPUError, FaultAction, InstructionFault, UninitializedRead, ALU_GROUP_FLOAT, ALU_GROUP_INTEGER,
+    ALU_GROUP_SIGNED, CPU, DIVIDE_BY_ZERO_VECTOR, ILLEGAL_INSTRUCTION_VECTOR, MEMORY_FAULT_ADDRESS,
+    MEMORY_FAULT_VECTOR, PROTECTION_FAULT_ADDRESS, PROTECTION_FAULT_VECTOR, SYSTEM_CALL_NUMBER,
+    SYSTEM_CALL_VECTOR,
+};
+pub use debugger::{Breakpoint, Debugger, WatchKind, Watchpoint, WatchpointHit};
+pub use decoder::{decode, Decoded};
+pub use disassembler::{disassemble, disassemble_line, register_name};
+pub use display::{Framebuffer, PixelDisplay, FRAMEBUFFER_ADDRESS, PIXEL_DISPLAY_DECODES};
+pub use history::History;
+pub use interrupt::{
+    InterruptController, INTERRUPT_CONTROLLER_END, INTERRUPT_CONTROLLER_START, INTERRUPT_LINES,
+    INTERRUPT_VECTOR_TABLE,
+};
+pub use io::{IoWindow, IO_WINDOW_END, IO_WINDOW_START};
+pub use linker::{link, LinkError, LinkedProgram, LinkerScript};
+pub use loader::{Format, LoadError};
+pub use memory::{Ram, RamInit};
+pub use mmu::{
+    Mmu, PageFaultCause, MMU_END, MMU_START, PAGE_SIZE, PTE_PRESENT, PTE_USER, PTE_WRITE,
+};
+pub use mpu::{Access, Mpu, MpuRegion, MPU_END, MPU_REGIONS, MPU_START};
+pub use object::{Object, ObjectError};
+pub use port::{Latch, OutputPort, PortDevice};
+pub use snapshot::{SnapshotError, SNAPSHOT_VERSION};
+pub use timer::{Timer, TIMER_END, TIMER_START};
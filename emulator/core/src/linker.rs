@@ -0,0 +1,493 @@
+use crate::assembler::{self, AssembleError, Bank};
+use crate::object::{Object, Target};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The script used when none is given: the `program` bank of
+/// `assembler/astCPU.asm`, which then takes every section.
+pub const DEFAULT_LINKER_SCRIPT: &str = "#bankdef program {\n    #bits 32\n    #outp 32\n}\n";
+
+const WORD_BITS: usize = 32;
+
+/// Where the linker puts sections, written as `#bankdef` blocks:
+///
+/// ```text
+/// #bankdef program {
+///     #bits 32
+///     #outp 32
+///     #sections program, code
+/// }
+/// #bankdef data {
+///     #addr 0x8000
+///     #size 0x1000
+///     #outp 32 * 0x8001
+/// }
+/// ```
+///
+/// Fields mean what they do in a program. A section goes in the bank
+/// whose `#sections` names it, else in the bank of its name, else in the
+/// first bank. Sections of a bank are placed in `#sections` order, then
+/// in the order of the objects.
+pub struct LinkerScript {
+    banks: Vec<Bank>,
+}
+
+impl LinkerScript {
+    /// Parses a script; `name` is the file for error messages.
+    pub fn parse(name: &str, text: &str) -> Result<LinkerScript, AssembleError> {
+        let banks = assembler::parse_linker_script(name, text)?;
+        return Ok(LinkerScript { banks });
+    }
+    pub fn read_file(path: &Path) -> Result<LinkerScript, AssembleError> {
+        let text = fs::read_to_string(path).map_err(|error| AssembleError {
+            file: path.display().to_string(),
+            line: 0,
+            column: 0,
+            message: error.to_string(),
+        })?;
+        return LinkerScript::parse(&path.display().to_string(), &text);
+    }
+    /// Index of the bank `section` goes in.
+    fn bank_of(&self, section: &str) -> usize {
+        if let Some(index) = self
+            .banks
+            .iter()
+            .position(|bank| bank.sections.iter().any(|name| name == section))
+        {
+            return index;
+        }
+        return self
+            .banks
+            .iter()
+            .position(|bank| bank.name == section)
+            .unwrap_or(0);
+    }
+}
+
+impl Default for LinkerScript {
+    fn default() -> Self {
+        LinkerScript::parse("default linker script", DEFAULT_LINKER_SCRIPT)
+            .expect("the default linker script is valid")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkError {
+    pub message: String,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+fn error(message: impl Into<String>) -> LinkError {
+    return LinkError {
+        message: message.into(),
+    };
+}
+
+/// Where a section of an object went.
+#[derive(Clone, Debug)]
+pub struct PlacedSection {
+    pub object: String,
+    pub name: String,
+    pub bank: String,
+    pub address: u32,
+    pub words: usize,
+}
+
+/// A symbol of an object at its final address.
+#[derive(Clone, Debug)]
+pub struct LinkedSymbol {
+    pub name: String,
+    pub object: String,
+    pub address: u32,
+    pub global: bool,
+}
+
+/// The result of [`link`].
+pub struct LinkedProgram {
+    /// Words from address 0, as [`crate::Assembler::assemble`] makes them.
+    pub image: Vec<u32>,
+    pub sections: Vec<PlacedSection>,
+    /// Sorted by address.
+    pub symbols: Vec<LinkedSymbol>,
+}
+
+impl LinkedProgram {
+    /// A text map of where every section and symbol went.
+    pub fn symbol_map(&self) -> String {
+        let mut map = String::from("; address  words  section (object) -> bank\n");
+        for section in &self.sections {
+            map.push_str(&format!(
+                "{:06x}     {:<6} {} ({}) -> {}\n",
+                section.address, section.words, section.name, section.object, section.bank
+            ));
+        }
+        map.push_str("\n; address  symbol (object)\n");
+        for symbol in &self.symbols {
+            let scope = if symbol.global { "" } else { ", local" };
+            map.push_str(&format!(
+                "{:06x}     {} ({}{})\n",
+                symbol.address, symbol.name, symbol.object, scope
+            ));
+        }
+        return map;
+    }
+}
+
+/// Whether `value` is representable in `width` bits, unsigned or two's
+/// complement, as slices in the assembler require.
+fn fits(value: i64, width: u32) -> bool {
+    let value = value as i128;
+    return value >= -(1 << (width - 1)) && value < 1 << width;
+}
+
+/// Links `objects`, each with the name it has in messages, into one
+/// image placed per `script`, filling in every relocation.
+pub fn link(
+    objects: &[(String, Object)],
+    script: &LinkerScript,
+) -> Result<LinkedProgram, LinkError> {
+    // Every section as (bank, rank in its #sections, object, section)
+    let mut order = Vec::new();
+    for (object_index, (_, object)) in objects.iter().enumerate() {
+        for (section_index, section) in object.sections.iter().enumerate() {
+            let bank = script.bank_of(&section.name);
+            let rank = script.banks[bank]
+                .sections
+                .iter()
+                .position(|name| *name == section.name)
+                .unwrap_or(usize::MAX);
+            order.push((bank, rank, object_index, section_index));
+        }
+    }
+    order.sort();
+
+    let mut used = vec![0; script.banks.len()];
+    let mut bases: HashMap<(usize, usize), u32> = HashMap::new();
+    let mut sections = Vec::new();
+    for &(bank_index, _, object_index, section_index) in &order {
+        let bank = &script.banks[bank_index];
+        let (object_name, object) = &objects[object_index];
+        let section = &object.sections[section_index];
+        let offset = used[bank_index];
+        used[bank_index] += section.words.len();
+        if bank.size.is_some_and(|size| used[bank_index] > size) {
+            return Err(error(format!(
+                "bank `{}` is full: section `{}` of {} does not fit",
+                bank.name, section.name, object_name
+            )));
+        }
+        let address = u32::try_from(bank.addr + offset as i128)
+            .map_err(|_| error(format!("bank `{}` has an invalid #addr", bank.name)))?;
+        bases.insert((object_index, section_index), address);
+        sections.push(PlacedSection {
+            object: object_name.clone(),
+            name: section.name.clone(),
+            bank: bank.name.clone(),
+            address,
+            words: section.words.len(),
+        });
+    }
+
+    let section_base = |object_index: usize, name: &str| -> Option<u32> {
+        let section_index = objects[object_index]
+            .1
+            .sections
+            .iter()
+            .position(|section| section.name == name)?;
+        return Some(bases[&(object_index, section_index)]);
+    };
+    let mut symbols = Vec::new();
+    let mut globals: HashMap<&str, (u32, &str)> = HashMap::new();
+    for (object_index, (object_name, object)) in objects.iter().enumerate() {
+        for symbol in &object.symbols {
+            let address = section_base(object_index, &symbol.section)
+                .ok_or_else(|| {
+                    error(format!(
+                        "symbol `{}` of {} is in unknown section `{}`",
+                        symbol.name, object_name, symbol.section
+                    ))
+                })?
+                .wrapping_add(symbol.offset as u32);
+            if symbol.global {
+                if let Some((_, other)) = globals.insert(&symbol.name, (address, object_name)) {
+                    return Err(error(format!(
+                        "`{}` is defined in both {} and {}",
+                        symbol.name, other, object_name
+                    )));
+                }
+            }
+            symbols.push(LinkedSymbol {
+                name: symbol.name.clone(),
+                object: object_name.clone(),
+                address,
+                global: symbol.global,
+            });
+        }
+    }
+    symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+
+    // Fill in the relocations and put each bank's words together
+    let mut contents: Vec<Vec<u32>> = vec![Vec::new(); script.banks.len()];
+    for &(bank_index, _, object_index, section_index) in &order {
+        let (object_name, object) = &objects[object_index];
+        let section = &object.sections[section_index];
+        let mut words = section.words.clone();
+        for relocation in &section.relocations {
+            let (base, name) = match &relocation.target {
+                Target::Section(name) => (section_base(object_index, name), name),
+                Target::Symbol(name) => (
+                    globals.get(name.as_str()).map(|(address, _)| *address),
+                    name,
+                ),
+            };
+            let Some(base) = base else {
+                return Err(error(format!(
+                    "undefined symbol `{}`, used in {}",
+                    name, object_name
+                )));
+            };
+            let value = base as i64 + relocation.addend;
+            let place = format!(
+                "{}, section `{}` word {}",
+                object_name, section.name, relocation.offset
+            );
+            if !fits(value, relocation.width) {
+                return Err(error(format!(
+                    "address 0x{:x} of `{}` does not fit in {} bits ({})",
+                    value, name, relocation.width, place
+                )));
+            }
+            let Some(word) = words.get_mut(relocation.offset) else {
+                return Err(error(format!(
+                    "relocation past the end of its section ({})",
+                    place
+                )));
+            };
+            let mask = ((1u64 << relocation.width) - 1) as u32;
+            *word &= !(mask << relocation.shift);
+            *word |= (value as u32 & mask) << relocation.shift;
+        }
+        contents[bank_index].extend(words);
+    }
+
+    let mut image = Vec::new();
+    let mut placed: Vec<(usize, usize, &str)> = Vec::new();
+    for (bank, mut words) in script.banks.iter().zip(contents) {
+        if bank.fill {
+            if let Some(size) = bank.size {
+                words.resize(size, 0);
+            }
+        }
+        if words.is_empty() {
+            continue;
+        }
+        let Some(outp) = bank.outp else {
+            return Err(error(format!(
+                "bank `{}` has no #outp, so it cannot hold sections",
+                bank.name
+            )));
+        };
+        let start = outp / WORD_BITS;
+        let end = start + words.len();
+        if let Some((_, _, other)) = placed
+            .iter()
+            .find(|(other_start, other_end, _)| start < *other_end && *other_start < end)
+        {
+            return Err(error(format!(
+                "bank `{}` overlaps bank `{}` in the output",
+                bank.name, other
+            )));
+        }
+        placed.push((start, end, &bank.name));
+        if image.len() < end {
+            image.resize(end, 0);
+        }
+        image[start..end].copy_from_slice(&words);
+    }
+    return Ok(LinkedProgram {
+        image,
+        sections,
+        symbols,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Assembler;
+
+    /// Declares a `data` section in a source.
+    const DATA: &str = "#bankdef data {\n#bits 32\n}\n#bank program\n";
+
+    fn object(name: &str, source: &str) -> (String, Object) {
+        let mut assembler = Assembler::new();
+        assembler.add_source(name, source);
+        return (name.to_string(), assembler.assemble_object().unwrap());
+    }
+
+    fn script(text: &str) -> LinkerScript {
+        return LinkerScript::parse("test.ld", text).unwrap();
+    }
+
+    fn link_error(objects: &[(String, Object)], script: &LinkerScript) -> String {
+        match link(objects, script) {
+            Ok(_) => panic!("the link should fail"),
+            Err(error) => return error.message,
+        }
+    }
+
+    #[test]
+    fn links_like_one_program() {
+        let main = "start:\nCALL func\nLDI R1 - value + 1\nJMP start\n";
+        let lib = "func:\n.done:\nRET\nvalue:\n#d32 7, func.done\n";
+        let objects = [object("main.o", main), object("lib.o", lib)];
+        let linked = link(&objects, &LinkerScript::default()).unwrap();
+        assert_eq!(
+            linked.image,
+            vec![0, 0xd0000003, 0x61000005, 0x38000000, 0xd2000000, 7, 3]
+        );
+
+        let mut assembler = Assembler::new();
+        assembler.add_source("main.asm", main);
+        assembler.add_source("lib.asm", lib);
+        assert_eq!(assembler.assemble().unwrap(), linked.image);
+
+        let map = linked.symbol_map();
+        assert!(map.contains("000003     func (lib.o)\n"), "{}", map);
+        assert!(
+            map.contains("000003     func.done (lib.o, local)\n"),
+            "{}",
+            map
+        );
+        assert!(
+            map.contains("000003     3      program (lib.o) -> program\n"),
+            "{}",
+            map
+        );
+    }
+
+    #[test]
+    fn sections_go_to_the_banks_of_the_script() {
+        let script = script(
+            "#bankdef program {\n#bits 32\n#outp 32\n}\n\
+             #bankdef ram {\n#addr 0x100\n#size 4\n#outp 32 * 0x101\n#sections data\n}\n",
+        );
+        let objects = [
+            object(
+                "a.o",
+                &format!("{}LD R1 - table + 1\n#bank data\ntable:\n#d32 9\n", DATA),
+            ),
+            object("b.o", &format!("{}NOP\n#bank data\n#d32 8\n", DATA)),
+        ];
+        let linked = link(&objects, &script).unwrap();
+        assert_eq!(linked.image.len(), 0x103);
+        assert_eq!(linked.image[1..3], [0x41000101, 0]);
+        assert_eq!(linked.image[0x101..], [9, 8]);
+        let placed: Vec<_> = linked
+            .sections
+            .iter()
+            .map(|section| {
+                (
+                    section.object.as_str(),
+                    section.bank.as_str(),
+                    section.address,
+                )
+            })
+            .collect();
+        assert_eq!(
+            placed,
+            [
+                ("a.o", "program", 0),
+                ("b.o", "program", 1),
+                ("a.o", "ram", 0x100),
+                ("b.o", "ram", 0x101),
+            ]
+        );
+    }
+
+    #[test]
+    fn addresses_must_fit_their_field() {
+        let script = script(
+            "#bankdef program {\n#bits 32\n#outp 32\n}\n\
+             #bankdef data {\n#addr 0x800000\n#outp 64\n}\n",
+        );
+        let objects = [object(
+            "a.o",
+            &format!("{}JMP far\n#bank data\nfar:\nNOP\n", DATA),
+        )];
+        let message = link_error(&objects, &script);
+        assert!(
+            message.contains("0x800000 of `data` does not fit in 23 bits"),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains("a.o, section `program` word 0"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn globals_are_defined_once() {
+        let objects = [
+            object("a.o", "start:\n.loop:\nNOP\n"),
+            object("b.o", "other:\n.loop:\nJMP start\n"),
+        ];
+        assert!(link(&objects, &LinkerScript::default()).is_ok());
+
+        let objects = [
+            object("a.o", "start:\nNOP\n"),
+            object("b.o", "start:\nNOP\n"),
+        ];
+        assert_eq!(
+            link_error(&objects, &LinkerScript::default()),
+            "`start` is defined in both a.o and b.o"
+        );
+
+        let objects = [object("a.o", "JMP missing\n")];
+        assert_eq!(
+            link_error(&objects, &LinkerScript::default()),
+            "undefined symbol `missing`, used in a.o"
+        );
+    }
+
+    #[test]
+    fn banks_must_hold_their_sections() {
+        let objects = [object("a.o", "NOP\nNOP\n"), object("b.o", "NOP\n")];
+        let full = script("#bankdef program {\n#bits 32\n#size 2\n#outp 0\n}\n");
+        assert_eq!(
+            link_error(&objects, &full),
+            "bank `program` is full: section `program` of b.o does not fit"
+        );
+
+        let objects = [object(
+            "a.o",
+            &format!("{}NOP\nNOP\n#bank data\n#d32 1\n", DATA),
+        )];
+        let overlapping = script(
+            "#bankdef program {\n#bits 32\n#outp 0\n}\n\
+             #bankdef data {\n#addr 0x100\n#outp 32\n}\n",
+        );
+        assert_eq!(
+            link_error(&objects, &overlapping),
+            "bank `data` overlaps bank `program` in the output"
+        );
+
+        let unplaced =
+            script("#bankdef program {\n#bits 32\n#outp 0\n}\n#bankdef data {\n#addr 0x100\n}\n");
+        assert_eq!(
+            link_error(&objects, &unplaced),
+            "bank `data` has no #outp, so it cannot hold sections"
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use crate::memory::RAM_SIZE;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// First line of a Logisim memory image.
+const LOGISIM_HEADER: &str = "v2.0 raw";
+
+/// File formats a program image can be loaded from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Hex words separated by whitespace, one per line as `asm` writes
+    /// them. `@address` moves to a hex word address, so sparse images like
+    /// Verilog's `$readmemh` ones load too. `//` starts a comment.
+    Hex,
+    /// Intel HEX records. Addresses count bytes and each word is made of
+    /// four bytes, most significant first.
+    IntelHex,
+    /// A Logisim `v2.0 raw` memory image, where `N*value` repeats a value
+    /// `N` times and `#` starts a comment.
+    Logisim,
+    /// Raw 32-bit words, least significant byte first.
+    BinaryLittleEndian,
+    /// Raw 32-bit words, most significant byte first.
+    BinaryBigEndian,
+}
+
+impl Format {
+    /// Guesses the format of `data` read from `path`: `.bin` files and
+    /// anything that is not text are little-endian binary, text files go by
+    /// their first line.
+    pub fn detect(path: &Path, data: &[u8]) -> Format {
+        let Ok(text) = std::str::from_utf8(data) else {
+            return Format::BinaryLittleEndian;
+        };
+        if path.extension().is_some_and(|extension| extension == "bin") {
+            return Format::BinaryLittleEndian;
+        }
+        let first = text.lines().map(str::trim).find(|line| !line.is_empty());
+        match first {
+            Some(line) if line.starts_with(LOGISIM_HEADER) => Format::Logisim,
+            Some(line) if line.starts_with(':') => Format::IntelHex,
+            _ => Format::Hex,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Hex => write!(f, "hex"),
+            Format::IntelHex => write!(f, "ihex"),
+            Format::Logisim => write!(f, "logisim"),
+            Format::BinaryLittleEndian => write!(f, "bin-le"),
+            Format::BinaryBigEndian => write!(f, "bin-be"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// A malformed image, at a 1-based line or 0 for binary files.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::Parse { line: 0, message } => write!(f, "{}", message),
+            LoadError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+fn parse_error(line: usize, message: impl Into<String>) -> LoadError {
+    return LoadError::Parse {
+        line,
+        message: message.into(),
+    };
+}
+
+/// RAM contents from address 0 being built; words no record sets are 0.
+struct Image {
+    words: Vec<u32>,
+}
+
+impl Image {
+    fn put(&mut self, address: usize, word: u32, line: usize) -> Result<(), LoadError> {
+        if address >= RAM_SIZE {
+            return Err(parse_error(
+                line,
+                format!("address 0x{:x} is past the end of RAM", address),
+            ));
+        }
+        if self.words.len() <= address {
+            self.words.resize(address + 1, 0);
+        }
+        self.words[address] = word;
+        return Ok(());
+    }
+}
+
+/// Loads an image in `format`, returning the words from address 0.
+pub fn load(data: &[u8], format: Format) -> Result<Vec<u32>, LoadError> {
+    let mut image = Image { words: Vec::new() };
+    match format {
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => {
+            if !data.len().is_multiple_of(4) {
+                return Err(parse_error(
+                    0,
+                    format!(
+                        "raw binary of {} bytes is not a whole number of 32-bit words",
+                        data.len()
+                    ),
+                ));
+            }
+            if data.len() / 4 > RAM_SIZE {
+                return Err(parse_error(0, "image is larger than RAM"));
+            }
+            image.words = data
+                .chunks_exact(4)
+                .map(|bytes| {
+                    let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                    match format {
+                        Format::BinaryBigEndian => u32::from_be_bytes(bytes),
+                        _ => u32::from_le_bytes(bytes),
+                    }
+                })
+                .collect();
+        }
+        _ => {
+            let text = std::str::from_utf8(data)
+                .map_err(|error| parse_error(0, format!("not a text file: {}", error)))?;
+            match format {
+                Format::Hex => load_hex(text, &mut image)?,
+                Format::IntelHex => load_intel_hex(text, &mut image)?,
+                _ => load_logisim(text, &mut image)?,
+            }
+        }
+    }
+    return Ok(image.words);
+}
+
+/// Loads an image file, guessing its format with [`Format::detect`]
+/// unless `format` is given.
+pub fn load_file(path: &Path, format: Option<Format>) -> Result<Vec<u32>, LoadError> {
+    let data = fs::read(path)?;
+    let format = format.unwrap_or_else(|| Format::detect(path, &data));
+    return load(&data, format);
+}
+
+fn parse_hex(token: &str, line: usize, what: &str) -> Result<u32, LoadError> {
+    let digits = token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+        .unwrap_or(token);
+    return u32::from_str_radix(digits, 16)
+        .map_err(|_| parse_error(line, format!("invalid {} `{}`", what, token)));
+}
+
+fn load_hex(text: &str, image: &mut Image) -> Result<(), LoadError> {
+    let mut address = 0;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.split("//").next().unwrap_or_default();
+        for token in line.split_whitespace() {
+            if let Some(target) = token.strip_prefix('@') {
+                address = parse_hex(target, number, "address")? as usize;
+                continue;
+            }
+            image.put(address, parse_hex(token, number, "hex word")?, number)?;
+            address += 1;
+        }
+    }
+    return Ok(());
+}
+
+fn load_logisim(text: &str, image: &mut Image) -> Result<(), LoadError> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, line)) if line.trim() == LOGISIM_HEADER => {}
+        _ => {
+            return Err(parse_error(
+                1,
+                format!("expected `{}` on the first line", LOGISIM_HEADER),
+            ))
+        }
+    }
+    let mut address: usize = 0;
+    for (index, line) in lines {
+        let number = index + 1;
+        let line = line.split('#').next().unwrap_or_default();
+        for token in line.split_whitespace() {
+            let (count, value) = match token.split_once('*') {
+                Some((count, value)) => {
+                    let count = count.parse::<usize>().map_err(|_| {
+                        parse_error(number, format!("invalid count in `{}`", token))
+                    })?;
+                    (count, value)
+                }
+                None => (1, token),
+            };
+            let value = parse_hex(value, number, "value")?;
+            if address.checked_add(count).is_none_or(|end| end > RAM_SIZE) {
+                return Err(parse_error(number, "image is larger than RAM"));
+            }
+            for _ in 0..count {
+                image.put(address, value, number)?;
+                address += 1;
+            }
+        }
+    }
+    return Ok(());
+}
+
+fn load_intel_hex(text: &str, image: &mut Image) -> Result<(), LoadError> {
+    // Added to record addresses by type 02 and 04 records
+    let mut base = 0;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some(digits) = line.strip_prefix(':') else {
+            return Err(parse_error(number, "expected a record starting with `:`"));
+        };
+        if !digits.is_ascii() {
+            return Err(parse_error(number, "record is not hex"));
+        }
+        if digits.len() % 2 != 0 || digits.len() < 10 {
+            return Err(parse_error(number, "record is too short"));
+        }
+        let bytes = (0..digits.len() / 2)
+            .map(|i| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| parse_error(number, "record is not hex"))?;
+        let length = bytes[0] as usize;
+        if bytes.len() != length + 5 {
+            return Err(parse_error(
+                number,
+                format!("record should hold {} data bytes", length),
+            ));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(parse_error(number, "checksum mismatch"));
+        }
+        let offset = (bytes[1] as usize) << 8 | bytes[2] as usize;
+        let data = &bytes[4..4 + length];
+        let extended = || -> Result<usize, LoadError> {
+            match data {
+                [high, low] => Ok((*high as usize) << 8 | *low as usize),
+                _ => Err(parse_error(number, "address record should hold 2 bytes")),
+            }
+        };
+        match bytes[3] {
+            0x00 => {
+                for (i, byte) in data.iter().enumerate() {
+                    let address = base + offset + i;
+                    let word = address / 4;
+                    let shift = 24 - 8 * (address % 4);
+                    let old = image.words.get(word).copied().unwrap_or(0);
+                    image.put(
+                        word,
+                        old & !(0xFF << shift) | (*byte as u32) << shift,
+                        number,
+                    )?;
+                }
+            }
+            0x01 => return Ok(()),
+            0x02 => base = extended()? << 4,
+            0x04 => base = extended()? << 16,
+            // Start addresses mean nothing to the emulator, which starts at 0
+            0x03 | 0x05 => {}
+            kind => {
+                return Err(parse_error(
+                    number,
+                    format!("unknown record type {:02x}", kind),
+                ))
+            }
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(result: Result<Vec<u32>, LoadError>) -> (usize, String) {
+        match result {
+            Err(LoadError::Parse { line, message }) => (line, message),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    /// An Intel HEX record with its checksum.
+    fn record(address: u16, kind: u8, data: &[u8]) -> String {
+        let mut bytes = vec![data.len() as u8, (address >> 8) as u8, address as u8, kind];
+        bytes.extend_from_slice(data);
+        let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        bytes.push(sum.wrapping_neg());
+        let hex: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        return format!(":{}\n", hex);
+    }
+
+    #[test]
+    fn hex_words_with_addresses() {
+        let text = "61000001\n// comment\n@4 6200000a 38000004\n@1\n0x00000007\n";
+        assert_eq!(
+            load(text.as_bytes(), Format::Hex).unwrap(),
+            vec![0x61000001, 7, 0, 0, 0x6200000a, 0x38000004]
+        );
+    }
+
+    #[test]
+    fn hex_errors_name_their_line() {
+        let (line, message) = parse_line(load(b"61000001\n\nzz\n", Format::Hex));
+        assert_eq!(line, 3);
+        assert!(message.contains("`zz`"), "{}", message);
+        let (line, _) = parse_line(load(b"1\n@1000000 2\n", Format::Hex));
+        assert_eq!(line, 2);
+    }
+
+    #[test]
+    fn intel_hex_records() {
+        let mut text = record(0, 0x00, &[0x61, 0x00, 0x00, 0x01]);
+        // Segment base 0x10 bytes: word 4 plus 4 bytes
+        text += &record(0, 0x02, &[0x00, 0x01]);
+        text += &record(4, 0x00, &[0x62, 0x00, 0x00, 0x0a]);
+        // Linear base 0x10000 bytes: word 0x4000
+        text += &record(0, 0x04, &[0x00, 0x01]);
+        text += &record(0, 0x00, &[0xde, 0xad]);
+        text += &record(0, 0x01, &[]);
+        text += "ignored after the end\n";
+        let words = load(text.as_bytes(), Format::IntelHex).unwrap();
+        assert_eq!(words.len(), 0x4001);
+        assert_eq!(words[0], 0x61000001);
+        assert_eq!(words[5], 0x6200000a);
+        assert_eq!(words[0x4000], 0xdead0000);
+    }
+
+    #[test]
+    fn intel_hex_checksum_and_errors() {
+        let good = record(0, 0x00, &[0x61, 0x00, 0x00, 0x01]);
+        let bad = good.replace("61", "62");
+        let text = format!("{}{}", good, bad);
+        let (line, message) = parse_line(load(text.as_bytes(), Format::IntelHex));
+        assert_eq!((line, message.as_str()), (2, "checksum mismatch"));
+        let (line, _) = parse_line(load(b"\n61000001\n", Format::IntelHex));
+        assert_eq!(line, 2);
+        let text = format!("{}{}", good, record(0, 0x07, &[]));
+        let (line, _) = parse_line(load(text.as_bytes(), Format::IntelHex));
+        assert_eq!(line, 2);
+    }
+
+    #[test]
+    fn intel_hex_non_ascii_is_an_error() {
+        let (line, message) = parse_line(load(":0000000\u{e9}1F0\n".as_bytes(), Format::IntelHex));
+        assert_eq!((line, message.as_str()), (1, "record is not hex"));
+    }
+
+    #[test]
+    fn logisim_runs() {
+        let text = "v2.0 raw\n3*0 61000001 # comment\n2*6200000a\n";
+        assert_eq!(
+            load(text.as_bytes(), Format::Logisim).unwrap(),
+            vec![0, 0, 0, 0x61000001, 0x6200000a, 0x6200000a]
+        );
+    }
+
+    #[test]
+    fn logisim_errors() {
+        let (line, _) = parse_line(load(b"v2.0\n1\n", Format::Logisim));
+        assert_eq!(line, 1);
+        let (line, message) = parse_line(load(b"v2.0 raw\n1 2\nx*3\n", Format::Logisim));
+        assert_eq!(line, 3);
+        assert!(message.contains("count"), "{}", message);
+        let text = "v2.0 raw\n0 18446744073709551615*0\n";
+        let (line, message) = parse_line(load(text.as_bytes(), Format::Logisim));
+        assert_eq!((line, message.as_str()), (2, "image is larger than RAM"));
+    }
+
+    #[test]
+    fn binary_byte_orders() {
+        let data = [0x01, 0x00, 0x00, 0x61, 0x0a, 0x00, 0x00, 0x62];
+        assert_eq!(
+            load(&data, Format::BinaryLittleEndian).unwrap(),
+            vec![0x61000001, 0x6200000a]
+        );
+        assert_eq!(
+            load(&data, Format::BinaryBigEndian).unwrap(),
+            vec![0x01000061, 0x0a000062]
+        );
+        let (line, _) = parse_line(load(&data[..5], Format::BinaryLittleEndian));
+        assert_eq!(line, 0);
+    }
+
+    #[test]
+    fn detects_formats() {
+        let path = Path::new("program.hex");
+        assert_eq!(Format::detect(path, b"61000001\n"), Format::Hex);
+        assert_eq!(Format::detect(path, b"\n:00000001FF\n"), Format::IntelHex);
+        assert_eq!(Format::detect(path, b"v2.0 raw\n0\n"), Format::Logisim);
+        assert_eq!(
+            Format::detect(path, &[0xff, 0xfe, 0, 0]),
+            Format::BinaryLittleEndian
+        );
+        assert_eq!(
+            Format::detect(Path::new("program.bin"), b"6100"),
+            Format::BinaryLittleEndian
+        );
+    }
+}
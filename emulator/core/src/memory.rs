@@ -0,0 +1,245 @@
+use crate::bus::BusDevice;
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+use log::{debug, trace};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::any::Any;
+use std::fmt;
+use std::io;
+
+/// Number of 32-bit words in RAM (64MB).
+pub const RAM_SIZE: usize = 0x1000000;
+/// Mask applied to every address put on the memory bus.
+pub const ADDRESS_MASK: usize = 0xFFFFFF;
+
+/// What RAM past the loaded image holds at power on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RamInit {
+    Zero,
+    /// Every word set to the pattern.
+    Pattern(u32),
+    /// Reproducible random values from the seed.
+    Seeded(u64),
+    /// Fresh random values on every power on, like real hardware.
+    #[default]
+    Random,
+}
+
+impl RamInit {
+    /// [`RamInit::Seeded`] with a random seed, to be printed so the run can
+    /// be reproduced.
+    pub fn random_seed() -> RamInit {
+        return RamInit::Seeded(rand::random());
+    }
+}
+
+impl fmt::Display for RamInit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RamInit::Zero => write!(f, "zero"),
+            RamInit::Pattern(pattern) => write!(f, "pattern:0x{:08x}", pattern),
+            RamInit::Seeded(seed) => write!(f, "seed:{}", seed),
+            RamInit::Random => write!(f, "random"),
+        }
+    }
+}
+
+/// Plain RAM, mapped over the whole address space.
+///
+/// With the shadow memory on, RAM remembers which words were written (by
+/// the loader or the bus) and flags the first read of any other word, to
+/// catch programs using the garbage left by [`RamInit`]. Only words RAM
+/// answers for are tracked: the framebuffer the CPU maps from
+/// [`crate::FRAMEBUFFER_ADDRESS`] up, which also holds the top of the
+/// stack, starts zeroed and is never reported.
+pub struct Ram {
+    pub words: Box<[u32]>, // Allocating on the heap
+    /// Number of words loaded from the program image.
+    loaded: usize,
+    /// One bit per word, set once the word was written.
+    shadow: Option<Box<[u64]>>,
+    uninitialized_read: Option<usize>,
+}
+
+impl Ram {
+    /// Builds the RAM image: `initial_ram_content` is loaded at address 0
+    /// and every word past it is filled according to `init`.
+    pub fn new(initial_ram_content: &[u32], init: RamInit, log: bool) -> Ram {
+        if log {
+            debug!("Initialing 64MB RAM");
+        }
+        let mut ram: Vec<u32> = Vec::with_capacity(RAM_SIZE);
+        ram.extend_from_slice(&initial_ram_content[..initial_ram_content.len().min(RAM_SIZE)]);
+        if log {
+            trace!("RAM Filled with {}", init);
+        }
+        match init {
+            RamInit::Zero => ram.resize(RAM_SIZE, 0),
+            RamInit::Pattern(pattern) => ram.resize(RAM_SIZE, pattern),
+            RamInit::Seeded(seed) => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                while ram.len() < RAM_SIZE {
+                    ram.push(rng.gen());
+                }
+            }
+            RamInit::Random => {
+                while ram.len() < RAM_SIZE {
+                    ram.push(rand::random());
+                }
+            }
+        }
+        if log {
+            for (i, value) in ram.iter().take(10).enumerate() {
+                trace!("RAM[{}] = {}", i, value);
+            }
+        }
+        return Ram {
+            words: ram.into_boxed_slice(),
+            loaded: initial_ram_content.len().min(RAM_SIZE),
+            shadow: None,
+            uninitialized_read: None,
+        };
+    }
+    /// Turns the shadow memory on or off. Turning it on marks every word
+    /// as written: once the machine ran, RAM holds what the program stored
+    /// next to the [`RamInit`] fill and the two can't be told apart. See
+    /// [`Ram::set_shadow_at_power_on`].
+    pub fn set_shadow(&mut self, enabled: bool) {
+        if !enabled {
+            self.shadow = None;
+            self.uninitialized_read = None;
+            return;
+        }
+        if self.shadow.is_some() {
+            return;
+        }
+        self.shadow = Some(vec![u64::MAX; RAM_SIZE / 64].into_boxed_slice());
+    }
+    /// Turns the shadow memory on for a machine that has not run yet,
+    /// marking only the loaded image as written.
+    pub fn set_shadow_at_power_on(&mut self) {
+        self.shadow = Some(vec![0; RAM_SIZE / 64].into_boxed_slice());
+        self.uninitialized_read = None;
+        for offset in 0..self.loaded {
+            self.mark_written(offset);
+        }
+    }
+    pub fn shadow_enabled(&self) -> bool {
+        return self.shadow.is_some();
+    }
+    pub fn is_written(&self, offset: usize) -> bool {
+        match &self.shadow {
+            Some(shadow) => shadow[offset / 64] >> (offset % 64) & 0x01 == 1,
+            None => true,
+        }
+    }
+    fn mark_written(&mut self, offset: usize) {
+        if let Some(shadow) = &mut self.shadow {
+            shadow[offset / 64] |= 1 << (offset % 64);
+        }
+    }
+    /// The first never-written word read since the last call. Each word is
+    /// reported once.
+    pub fn take_uninitialized_read(&mut self) -> Option<usize> {
+        return self.uninitialized_read.take();
+    }
+}
+
+impl BusDevice for Ram {
+    fn read(&mut self, offset: usize) -> u32 {
+        if !self.is_written(offset) {
+            self.uninitialized_read.get_or_insert(offset);
+            self.mark_written(offset);
+        }
+        return self.words[offset];
+    }
+    fn write(&mut self, offset: usize, value: u32) {
+        self.mark_written(offset);
+        self.words[offset] = value;
+    }
+    fn peek(&self, offset: usize) -> u32 {
+        return self.words[offset];
+    }
+    fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        out.put_words(&self.words)?;
+        out.put_u64(self.loaded as u64)?;
+        out.put_bool(self.shadow.is_some())?;
+        if let Some(shadow) = &self.shadow {
+            for bits in shadow.iter() {
+                out.put_u64(*bits)?;
+            }
+        }
+        return out.put_u64(
+            self.uninitialized_read
+                .map_or(u64::MAX, |offset| offset as u64),
+        );
+    }
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        input.get_words_into(&mut self.words)?;
+        self.loaded = input.get_u64()? as usize;
+        self.shadow = None;
+        if input.get_bool()? {
+            let mut shadow = vec![0; RAM_SIZE / 64];
+            for bits in shadow.iter_mut() {
+                *bits = input.get_u64()?;
+            }
+            self.shadow = Some(shadow.into_boxed_slice());
+        }
+        let offset = input.get_u64()?;
+        self.uninitialized_read = (offset != u64::MAX).then_some(offset as usize);
+        return Ok(());
+    }
+    /// Leaves the shadow memory alone, an undone store still counts as a
+    /// write.
+    fn poke(&mut self, offset: usize, value: u32) {
+        self.words[offset] = value;
+    }
+    fn save_registers(&self, _out: &mut StateWriter) -> io::Result<()> {
+        return Ok(());
+    }
+    fn load_registers(&mut self, _input: &mut StateReader) -> Result<(), SnapshotError> {
+        return Ok(());
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn at_power_on_only_the_loaded_image_is_written() {
+        let mut ram = Ram::new(&[1, 2], RamInit::Pattern(7), false);
+        ram.set_shadow_at_power_on();
+        assert_eq!((ram.read(0), ram.read(1)), (1, 2));
+        assert_eq!(ram.take_uninitialized_read(), None);
+        ram.write(3, 4);
+        assert_eq!(ram.read(3), 4);
+        assert_eq!(ram.take_uninitialized_read(), None);
+        assert_eq!(ram.read(2), 7);
+        assert_eq!(ram.take_uninitialized_read(), Some(2));
+        // Each word is reported once.
+        ram.read(2);
+        assert_eq!(ram.take_uninitialized_read(), None);
+    }
+
+    #[test]
+    fn turning_the_shadow_on_mid_run_marks_everything_written() {
+        let mut ram = Ram::new(&[1, 2], RamInit::Pattern(7), false);
+        ram.write(0x30, 5);
+        ram.set_shadow(true);
+        assert!(ram.is_written(0x30) && ram.is_written(0x31) && ram.is_written(RAM_SIZE - 1));
+        assert_eq!((ram.read(0x30), ram.read(0x31)), (5, 7));
+        assert_eq!(ram.take_uninitialized_read(), None);
+
+        ram.set_shadow(false);
+        assert!(!ram.shadow_enabled());
+        ram.set_shadow_at_power_on();
+        assert!(ram.is_written(1) && !ram.is_written(0x31));
+    }
+}
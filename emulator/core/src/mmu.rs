@@ -0,0 +1,170 @@
+use crate::bus::BusDevice;
+use crate::memory::ADDRESS_MASK;
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+use std::any::Any;
+use std::io;
+
+/// Words per page, the low `PAGE_BITS` of an address are the page offset.
+pub const PAGE_BITS: u32 = 10;
+pub const PAGE_SIZE: usize = 1 << PAGE_BITS;
+/// Number of entries in the TLB, indexed by the low bits of the page number.
+pub const TLB_ENTRIES: usize = 16;
+/// First word of the MMU registers, inside the supervisor-only IO page.
+pub const MMU_START: usize = 0x7fffc4;
+/// Last word of the MMU registers.
+pub const MMU_END: usize = 0x7fffc9;
+
+/// Read/write. Bit 0: paging enabled.
+pub const MMU_CONTROL: usize = 0x00;
+/// Read/write. Physical address of the page table, one entry per page.
+pub const MMU_PAGE_TABLE: usize = 0x01;
+/// Write-only. Any write flushes the whole TLB.
+pub const MMU_TLB_FLUSH: usize = 0x02;
+/// Write-only. Drops the TLB entry of the page holding the written address.
+pub const MMU_TLB_INVALIDATE: usize = 0x03;
+/// Read-only. Virtual address of the last page fault.
+pub const MMU_FAULT_ADDRESS: usize = 0x04;
+/// Read-only. [`PageFaultCause`] of the last page fault.
+pub const MMU_FAULT_CAUSE: usize = 0x05;
+
+/// Page table entry bits, the frame number sits in bits 10-23 so masking
+/// the offset off an entry gives the frame's physical address.
+pub const PTE_PRESENT: u32 = 0x01;
+pub const PTE_WRITE: u32 = 0x02;
+pub const PTE_USER: u32 = 0x04;
+pub const PTE_FRAME_MASK: u32 = ADDRESS_MASK as u32 & !(PAGE_SIZE as u32 - 1);
+
+/// Why a translation failed, as reported in [`MMU_FAULT_CAUSE`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageFaultCause {
+    NotPresent = 1,
+    ReadOnly = 2,
+    Supervisor = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TlbEntry {
+    pub page: u32,
+    pub pte: u32,
+}
+
+/// Paging unit: the page-table base register, a direct-mapped TLB refilled
+/// by a hardware walk of the single level page table, and the registers
+/// describing the last page fault.
+///
+/// The TLB is not kept coherent with the page table; after changing an
+/// entry software must invalidate it through [`MMU_TLB_INVALIDATE`] or
+/// [`MMU_TLB_FLUSH`].
+pub struct Mmu {
+    pub enabled: bool,
+    pub page_table: u32,
+    pub tlb: [Option<TlbEntry>; TLB_ENTRIES],
+    pub fault_address: u32,
+    pub fault_cause: u32,
+}
+
+impl Mmu {
+    pub fn new() -> Mmu {
+        Mmu {
+            enabled: false,
+            page_table: 0,
+            tlb: [None; TLB_ENTRIES],
+            fault_address: 0,
+            fault_cause: 0,
+        }
+    }
+    pub fn page(address: usize) -> u32 {
+        return ((address & ADDRESS_MASK) >> PAGE_BITS) as u32;
+    }
+    /// Physical address of the page table entry of `page`.
+    pub fn pte_address(&self, page: u32) -> usize {
+        return (self.page_table as usize + page as usize) & ADDRESS_MASK;
+    }
+    pub fn lookup(&self, page: u32) -> Option<u32> {
+        return self.tlb[page as usize % TLB_ENTRIES]
+            .filter(|entry| entry.page == page)
+            .map(|entry| entry.pte);
+    }
+    pub fn refill(&mut self, page: u32, pte: u32) {
+        self.tlb[page as usize % TLB_ENTRIES] = Some(TlbEntry { page, pte });
+    }
+    pub fn flush(&mut self) {
+        self.tlb = [None; TLB_ENTRIES];
+    }
+    pub fn invalidate(&mut self, address: usize) {
+        let page = Mmu::page(address);
+        if self.lookup(page).is_some() {
+            self.tlb[page as usize % TLB_ENTRIES] = None;
+        }
+    }
+    pub fn reset(&mut self) {
+        *self = Mmu::new();
+    }
+}
+
+impl Default for Mmu {
+    fn default() -> Self {
+        Mmu::new()
+    }
+}
+
+impl BusDevice for Mmu {
+    fn read(&mut self, offset: usize) -> u32 {
+        return self.peek(offset);
+    }
+    fn write(&mut self, offset: usize, value: u32) {
+        match offset {
+            MMU_CONTROL => {
+                self.enabled = value & 0x01 == 1;
+                self.flush();
+            }
+            MMU_PAGE_TABLE => {
+                self.page_table = value & ADDRESS_MASK as u32;
+                self.flush();
+            }
+            MMU_TLB_FLUSH => self.flush(),
+            MMU_TLB_INVALIDATE => self.invalidate(value as usize),
+            _ => {}
+        }
+    }
+    fn peek(&self, offset: usize) -> u32 {
+        match offset {
+            MMU_CONTROL => self.enabled as u32,
+            MMU_PAGE_TABLE => self.page_table,
+            MMU_FAULT_ADDRESS => self.fault_address,
+            MMU_FAULT_CAUSE => self.fault_cause,
+            _ => 0,
+        }
+    }
+    fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        out.put_bool(self.enabled)?;
+        out.put_u32(self.page_table)?;
+        for entry in self.tlb.iter() {
+            out.put_bool(entry.is_some())?;
+            let entry = entry.unwrap_or(TlbEntry { page: 0, pte: 0 });
+            out.put_u32(entry.page)?;
+            out.put_u32(entry.pte)?;
+        }
+        out.put_u32(self.fault_address)?;
+        return out.put_u32(self.fault_cause);
+    }
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.enabled = input.get_bool()?;
+        self.page_table = input.get_u32()?;
+        for entry in self.tlb.iter_mut() {
+            let valid = input.get_bool()?;
+            let page = input.get_u32()?;
+            let pte = input.get_u32()?;
+            *entry = valid.then_some(TlbEntry { page, pte });
+        }
+        self.fault_address = input.get_u32()?;
+        self.fault_cause = input.get_u32()?;
+        return Ok(());
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
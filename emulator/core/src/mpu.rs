@@ -0,0 +1,198 @@
+use crate::bus::BusDevice;
+use crate::memory::ADDRESS_MASK;
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+use std::any::Any;
+use std::io;
+
+/// Number of MPU region registers.
+pub const MPU_REGIONS: usize = 8;
+/// First word of the MPU registers, inside the supervisor-only IO page.
+pub const MPU_START: usize = 0x7fffd0;
+/// Last word of the MPU registers.
+pub const MPU_END: usize = MPU_START + MPU_REGION_BASE + MPU_REGIONS * MPU_REGION_STRIDE - 1;
+
+/// Read/write. Bit 0: the MPU checks user mode accesses.
+pub const MPU_CONTROL: usize = 0x00;
+/// Offset of the first region, region `n` starts at
+/// `MPU_REGION_BASE + n * MPU_REGION_STRIDE`.
+pub const MPU_REGION_BASE: usize = 0x08;
+pub const MPU_REGION_STRIDE: usize = 4;
+/// Region register: first word of the region.
+pub const MPU_REGION_START: usize = 0x00;
+/// Region register: last word of the region (inclusive).
+pub const MPU_REGION_LIMIT: usize = 0x01;
+/// Region register: [`MPU_READ`] | [`MPU_WRITE`] | [`MPU_EXECUTE`].
+pub const MPU_REGION_PERMISSIONS: usize = 0x02;
+
+pub const MPU_READ: u32 = 0x01;
+pub const MPU_WRITE: u32 = 0x02;
+pub const MPU_EXECUTE: u32 = 0x04;
+
+/// The kind of memory access being checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    fn permission(self) -> u32 {
+        match self {
+            Access::Read => MPU_READ,
+            Access::Write => MPU_WRITE,
+            Access::Execute => MPU_EXECUTE,
+        }
+    }
+}
+
+/// One region register. A region without permissions is disabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MpuRegion {
+    pub start: u32,
+    pub limit: u32,
+    pub permissions: u32,
+}
+
+impl MpuRegion {
+    fn contains(&self, address: usize) -> bool {
+        return (self.start as usize..=self.limit as usize).contains(&address);
+    }
+}
+
+/// Memory protection unit sandboxing user mode code.
+///
+/// While enabled, a user mode access is allowed only if one of the regions
+/// contains the address and grants the access; supervisor mode is never
+/// checked. Regions hold physical addresses: with paging on, an access is
+/// checked after it translates, and only page faults come first. The registers live in the IO page, so only the kernel can
+/// program them.
+pub struct Mpu {
+    pub enabled: bool,
+    pub regions: [MpuRegion; MPU_REGIONS],
+}
+
+impl Mpu {
+    pub fn new() -> Mpu {
+        Mpu {
+            enabled: false,
+            regions: [MpuRegion::default(); MPU_REGIONS],
+        }
+    }
+    pub fn allows(&self, address: usize, access: Access) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let address = address & ADDRESS_MASK;
+        return self.regions.iter().any(|region| {
+            region.permissions & access.permission() != 0 && region.contains(address)
+        });
+    }
+    pub fn reset(&mut self) {
+        *self = Mpu::new();
+    }
+}
+
+impl Default for Mpu {
+    fn default() -> Self {
+        Mpu::new()
+    }
+}
+
+impl BusDevice for Mpu {
+    fn read(&mut self, offset: usize) -> u32 {
+        return self.peek(offset);
+    }
+    fn write(&mut self, offset: usize, value: u32) {
+        if offset == MPU_CONTROL {
+            self.enabled = value & 0x01 == 1;
+            return;
+        }
+        let Some(index) = offset.checked_sub(MPU_REGION_BASE) else {
+            return;
+        };
+        let Some(region) = self.regions.get_mut(index / MPU_REGION_STRIDE) else {
+            return;
+        };
+        match index % MPU_REGION_STRIDE {
+            MPU_REGION_START => region.start = value & ADDRESS_MASK as u32,
+            MPU_REGION_LIMIT => region.limit = value & ADDRESS_MASK as u32,
+            MPU_REGION_PERMISSIONS => {
+                region.permissions = value & (MPU_READ | MPU_WRITE | MPU_EXECUTE)
+            }
+            _ => {}
+        }
+    }
+    fn peek(&self, offset: usize) -> u32 {
+        if offset == MPU_CONTROL {
+            return self.enabled as u32;
+        }
+        let Some(index) = offset.checked_sub(MPU_REGION_BASE) else {
+            return 0;
+        };
+        let Some(region) = self.regions.get(index / MPU_REGION_STRIDE) else {
+            return 0;
+        };
+        match index % MPU_REGION_STRIDE {
+            MPU_REGION_START => region.start,
+            MPU_REGION_LIMIT => region.limit,
+            MPU_REGION_PERMISSIONS => region.permissions,
+            _ => 0,
+        }
+    }
+    fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        out.put_bool(self.enabled)?;
+        for region in self.regions.iter() {
+            out.put_u32(region.start)?;
+            out.put_u32(region.limit)?;
+            out.put_u32(region.permissions)?;
+        }
+        return Ok(());
+    }
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.enabled = input.get_bool()?;
+        for region in self.regions.iter_mut() {
+            region.start = input.get_u32()?;
+            region.limit = input.get_u32()?;
+            region.permissions = input.get_u32()?;
+        }
+        return Ok(());
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_grant_their_accesses_inclusively() {
+        let mut mpu = Mpu::new();
+        assert!(mpu.allows(0x1234, Access::Write));
+        mpu.write(MPU_CONTROL, 1);
+        assert!(!mpu.allows(0x1234, Access::Read));
+
+        let region = MPU_REGION_BASE + 2 * MPU_REGION_STRIDE;
+        mpu.write(region + MPU_REGION_START, 0x1000);
+        mpu.write(region + MPU_REGION_LIMIT, 0x1fff);
+        mpu.write(region + MPU_REGION_PERMISSIONS, MPU_READ | 0x08);
+        assert_eq!(mpu.read(region + MPU_REGION_PERMISSIONS), MPU_READ);
+        assert!(mpu.allows(0x1000, Access::Read));
+        assert!(mpu.allows(0x1fff, Access::Read));
+        assert!(!mpu.allows(0x0fff, Access::Read));
+        assert!(!mpu.allows(0x2000, Access::Read));
+        assert!(!mpu.allows(0x1000, Access::Write));
+        assert!(!mpu.allows(0x1000, Access::Execute));
+        // Addresses wrap to 24 bits like the bus
+        assert!(mpu.allows(0x1001000, Access::Read));
+
+        // Without permissions a region is disabled
+        mpu.write(region + MPU_REGION_PERMISSIONS, 0);
+        assert!(!mpu.allows(0x1000, Access::Read));
+    }
+}
@@ -0,0 +1,346 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// First word of every object file.
+pub const OBJECT_MAGIC: &str = "SS32OBJ";
+/// Format version, bumped whenever object files change shape.
+pub const OBJECT_VERSION: u32 = 1;
+
+/// What a relocation adds to its field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// The address of a section of the same object.
+    Section(String),
+    /// The address of a global symbol of any object.
+    Symbol(String),
+}
+
+/// Bits of a section word the linker fills with an address: bits
+/// `shift..shift + width` get the target's address plus `addend`, which
+/// must fit in them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relocation {
+    /// Word within the section.
+    pub offset: usize,
+    pub shift: u32,
+    pub width: u32,
+    pub target: Target,
+    pub addend: i64,
+}
+
+/// Words assembled for one bank, to be placed by the linker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub words: Vec<u32>,
+    pub relocations: Vec<Relocation>,
+}
+
+/// A label of an object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub section: String,
+    /// Word within the section.
+    pub offset: usize,
+    /// Whether other objects can refer to it. `.local` labels and labels
+    /// inside `asm` rules are not global.
+    pub global: bool,
+}
+
+/// A relocatable object made by [`crate::Assembler::assemble_object`].
+///
+/// It is stored as text, one item per line:
+///
+/// ```text
+/// SS32OBJ 1
+/// section program 2
+/// 61000000
+/// 38000000
+/// reloc 0 0 24 section program 1
+/// reloc 1 0 23 symbol main 0
+/// symbol start program 0 global
+/// ```
+///
+/// `section NAME WORDS` is followed by that many hex words and then the
+/// relocations of the section: `reloc OFFSET SHIFT WIDTH section|symbol
+/// TARGET ADDEND`. `symbol NAME SECTION OFFSET global|local` lines may
+/// come anywhere after the header.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Object {
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+}
+
+#[derive(Debug)]
+pub enum ObjectError {
+    Io(io::Error),
+    /// A malformed object file, at a 1-based line.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjectError::Io(error) => write!(f, "{}", error),
+            ObjectError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjectError {}
+
+impl From<io::Error> for ObjectError {
+    fn from(error: io::Error) -> Self {
+        ObjectError::Io(error)
+    }
+}
+
+fn parse_error(line: usize, message: impl Into<String>) -> ObjectError {
+    return ObjectError::Parse {
+        line,
+        message: message.into(),
+    };
+}
+
+/// Parses field `index` of a line.
+fn field<T: std::str::FromStr>(
+    fields: &[&str],
+    index: usize,
+    line: usize,
+    what: &str,
+) -> Result<T, ObjectError> {
+    let text = fields
+        .get(index)
+        .ok_or_else(|| parse_error(line, format!("missing {}", what)))?;
+    return text
+        .parse()
+        .map_err(|_| parse_error(line, format!("invalid {} `{}`", what, text)));
+}
+
+impl Object {
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{} {}\n", OBJECT_MAGIC, OBJECT_VERSION);
+        for section in &self.sections {
+            text.push_str(&format!(
+                "section {} {}\n",
+                section.name,
+                section.words.len()
+            ));
+            for word in &section.words {
+                text.push_str(&format!("{:08x}\n", word));
+            }
+            for relocation in &section.relocations {
+                let (kind, target) = match &relocation.target {
+                    Target::Section(name) => ("section", name),
+                    Target::Symbol(name) => ("symbol", name),
+                };
+                text.push_str(&format!(
+                    "reloc {} {} {} {} {} {}\n",
+                    relocation.offset,
+                    relocation.shift,
+                    relocation.width,
+                    kind,
+                    target,
+                    relocation.addend
+                ));
+            }
+        }
+        for symbol in &self.symbols {
+            text.push_str(&format!(
+                "symbol {} {} {} {}\n",
+                symbol.name,
+                symbol.section,
+                symbol.offset,
+                if symbol.global { "global" } else { "local" }
+            ));
+        }
+        return text;
+    }
+    pub fn parse(text: &str) -> Result<Object, ObjectError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+        let header = format!("{} {}", OBJECT_MAGIC, OBJECT_VERSION);
+        match lines.next() {
+            Some((_, line)) if line.trim() == header => {}
+            Some((_, line)) if line.starts_with(OBJECT_MAGIC) => {
+                return Err(parse_error(
+                    1,
+                    format!("unsupported object version (expected {})", OBJECT_VERSION),
+                ))
+            }
+            _ => return Err(parse_error(1, "not an SS32 object file")),
+        }
+        let mut object = Object::default();
+        while let Some((number, line)) = lines.next() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.first() {
+                None => continue,
+                Some(&"section") => {
+                    let name: String = field(&fields, 1, number, "section name")?;
+                    let count: usize = field(&fields, 2, number, "word count")?;
+                    let mut words = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let Some((number, line)) = lines.next() else {
+                            return Err(parse_error(number, "section ends early"));
+                        };
+                        words.push(u32::from_str_radix(line.trim(), 16).map_err(|_| {
+                            parse_error(number, format!("invalid word `{}`", line.trim()))
+                        })?);
+                    }
+                    object.sections.push(Section {
+                        name,
+                        words,
+                        relocations: Vec::new(),
+                    });
+                }
+                Some(&"reloc") => {
+                    let target_name: String = field(&fields, 5, number, "target")?;
+                    let target = match fields.get(4) {
+                        Some(&"section") => Target::Section(target_name),
+                        Some(&"symbol") => Target::Symbol(target_name),
+                        _ => return Err(parse_error(number, "expected `section` or `symbol`")),
+                    };
+                    let relocation = Relocation {
+                        offset: field(&fields, 1, number, "offset")?,
+                        shift: field(&fields, 2, number, "shift")?,
+                        width: field(&fields, 3, number, "width")?,
+                        target,
+                        addend: field(&fields, 6, number, "addend")?,
+                    };
+                    if relocation.width == 0 || relocation.shift + relocation.width > 32 {
+                        return Err(parse_error(number, "relocation field is not inside a word"));
+                    }
+                    let Some(section) = object.sections.last_mut() else {
+                        return Err(parse_error(number, "relocation outside a section"));
+                    };
+                    if relocation.offset >= section.words.len() {
+                        return Err(parse_error(
+                            number,
+                            "relocation past the end of its section",
+                        ));
+                    }
+                    section.relocations.push(relocation);
+                }
+                Some(&"symbol") => {
+                    let global = match fields.get(4) {
+                        Some(&"global") => true,
+                        Some(&"local") => false,
+                        _ => return Err(parse_error(number, "expected `global` or `local`")),
+                    };
+                    object.symbols.push(Symbol {
+                        name: field(&fields, 1, number, "symbol name")?,
+                        section: field(&fields, 2, number, "section name")?,
+                        offset: field(&fields, 3, number, "offset")?,
+                        global,
+                    });
+                }
+                Some(other) => {
+                    return Err(parse_error(number, format!("unknown item `{}`", other)))
+                }
+            }
+        }
+        return Ok(object);
+    }
+    pub fn read_file(path: &Path) -> Result<Object, ObjectError> {
+        return Object::parse(&fs::read_to_string(path)?);
+    }
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        return fs::write(path, self.to_text());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Object {
+        return Object {
+            sections: vec![
+                Section {
+                    name: "program".to_string(),
+                    words: vec![0x61000000, 0x38000000],
+                    relocations: vec![
+                        Relocation {
+                            offset: 0,
+                            shift: 0,
+                            width: 24,
+                            target: Target::Section("data".to_string()),
+                            addend: 1,
+                        },
+                        Relocation {
+                            offset: 1,
+                            shift: 0,
+                            width: 23,
+                            target: Target::Symbol("main".to_string()),
+                            addend: -2,
+                        },
+                    ],
+                },
+                Section {
+                    name: "data".to_string(),
+                    words: vec![0xcafe, 0],
+                    relocations: Vec::new(),
+                },
+            ],
+            symbols: vec![
+                Symbol {
+                    name: "start".to_string(),
+                    section: "program".to_string(),
+                    offset: 0,
+                    global: true,
+                },
+                Symbol {
+                    name: "start.loop".to_string(),
+                    section: "data".to_string(),
+                    offset: 1,
+                    global: false,
+                },
+            ],
+        };
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let object = sample();
+        let text = object.to_text();
+        assert!(text.starts_with("SS32OBJ 1\nsection program 2\n61000000\n"));
+        assert!(text.contains("reloc 1 0 23 symbol main -2\n"));
+        assert!(text.contains("symbol start.loop data 1 local\n"));
+        assert_eq!(Object::parse(&text).unwrap(), object);
+        assert_eq!(Object::parse("SS32OBJ 1\n").unwrap(), Object::default());
+    }
+
+    fn parse_error_line(text: &str) -> usize {
+        match Object::parse(text) {
+            Err(ObjectError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_errors_name_their_line() {
+        assert_eq!(parse_error_line(""), 1);
+        assert_eq!(parse_error_line("SS32OBJ 2\n"), 1);
+        assert_eq!(parse_error_line("SS32OBJ 1\nsection a 2\n0\n"), 2);
+        assert_eq!(parse_error_line("SS32OBJ 1\nsection a 1\nxyz\n"), 3);
+        assert_eq!(parse_error_line("SS32OBJ 1\nreloc 0 0 24 section a 0\n"), 2);
+        assert_eq!(
+            parse_error_line("SS32OBJ 1\nsection a 1\n0\nreloc 1 0 24 section a 0\n"),
+            4
+        );
+        assert_eq!(
+            parse_error_line("SS32OBJ 1\nsection a 1\n0\nreloc 0 16 24 section a 0\n"),
+            4
+        );
+        assert_eq!(parse_error_line("SS32OBJ 1\n\nsymbol a a 0 public\n"), 3);
+        assert_eq!(parse_error_line("SS32OBJ 1\nbogus\n"), 2);
+    }
+}
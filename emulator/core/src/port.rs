@@ -0,0 +1,170 @@
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+use std::any::Any;
+use std::io;
+
+/// The two data latches of the output port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Latch {
+    D1,
+    D2,
+}
+
+/// A device driven by the output port. It only sees writes made while the
+/// decode register selects it.
+pub trait PortDevice: Any + Send {
+    /// Called after `latch` was written; `d1` and `d2` are the current
+    /// contents of both latches.
+    fn write(&mut self, latch: Latch, d1: u32, d2: u32);
+    /// See [`crate::BusDevice::save_state`].
+    fn save_state(&self, _out: &mut StateWriter) -> io::Result<()> {
+        return Ok(());
+    }
+    fn load_state(&mut self, _input: &mut StateReader) -> Result<(), SnapshotError> {
+        return Ok(());
+    }
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// The custom output port: a 3-bit decode register selecting one of up to
+/// 8 devices, and two 32-bit data latches shared by all of them.
+///
+/// `OPW-En` writes the decode register, `OPD1W`/`OPD2W` write the latches.
+/// Decode value 0 selects no device. The same registers are mapped in the
+/// IO window, see [`crate::io::IoWindow`].
+pub struct OutputPort {
+    pub decode: u8,
+    pub d1: u32,
+    pub d2: u32,
+    devices: Vec<(u8, Box<dyn PortDevice>)>,
+}
+
+impl OutputPort {
+    pub fn new() -> OutputPort {
+        OutputPort {
+            decode: 0,
+            d1: 0,
+            d2: 0,
+            devices: Vec::new(),
+        }
+    }
+    /// Attaches `device` at `decode`, replacing any device already there.
+    pub fn attach(&mut self, decode: u8, device: Box<dyn PortDevice>) {
+        let decode = decode & 0x07;
+        self.devices.retain(|(d, _)| *d != decode);
+        self.devices.push((decode, device));
+    }
+    pub fn device<T: PortDevice>(&self, decode: u8) -> Option<&T> {
+        self.devices
+            .iter()
+            .find(|(d, _)| *d == decode)
+            .and_then(|(_, device)| device.as_any().downcast_ref::<T>())
+    }
+    /// Bit `n` is set when a device is attached at decode value `n`.
+    pub fn attached_mask(&self) -> u8 {
+        self.devices.iter().fold(0, |mask, (d, _)| mask | 1 << d)
+    }
+    pub fn set_decode(&mut self, decode: u8) {
+        self.decode = decode & 0x07;
+    }
+    pub fn write(&mut self, latch: Latch, value: u32) {
+        match latch {
+            Latch::D1 => self.d1 = value,
+            Latch::D2 => self.d2 = value,
+        }
+        let (decode, d1, d2) = (self.decode, self.d1, self.d2);
+        if decode == 0 {
+            return;
+        }
+        for (_, device) in self.devices.iter_mut().filter(|(d, _)| *d == decode) {
+            device.write(latch, d1, d2);
+        }
+    }
+    /// Saves the decode register, the latches and every attached device.
+    pub fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        out.put_u8(self.decode)?;
+        out.put_u32(self.d1)?;
+        out.put_u32(self.d2)?;
+        out.put_u8(self.attached_mask())?;
+        for (_, device) in self.devices.iter() {
+            device.save_state(out)?;
+        }
+        return Ok(());
+    }
+    /// Restores a [`OutputPort::save_state`] made with devices attached
+    /// at the same decode values.
+    pub fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.decode = input.get_u8()?;
+        self.d1 = input.get_u32()?;
+        self.d2 = input.get_u32()?;
+        let attached = input.get_u8()?;
+        if attached != self.attached_mask() {
+            return Err(SnapshotError::Mismatch(format!(
+                "port devices 0b{:08b} saved, 0b{:08b} attached",
+                attached,
+                self.attached_mask()
+            )));
+        }
+        for (_, device) in self.devices.iter_mut() {
+            device.load_state(input)?;
+        }
+        return Ok(());
+    }
+    /// Clears the decode register and latches, keeping attached devices.
+    pub fn reset(&mut self) {
+        self.decode = 0;
+        self.d1 = 0;
+        self.d2 = 0;
+    }
+}
+
+impl Default for OutputPort {
+    fn default() -> Self {
+        OutputPort::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::PixelDisplay;
+
+    fn port_with_display() -> OutputPort {
+        let mut port = OutputPort::new();
+        port.attach(1, Box::new(PixelDisplay::new(4, 4)));
+        return port;
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut port = port_with_display();
+        port.set_decode(1);
+        port.write(Latch::D1, 5);
+        port.write(Latch::D2, 0x123456);
+        port.write(Latch::D1, 6);
+        let mut saved = Vec::new();
+        port.save_state(&mut StateWriter::new(&mut saved)).unwrap();
+
+        let mut loaded = port_with_display();
+        loaded
+            .load_state(&mut StateReader::new(&mut saved.as_slice()))
+            .unwrap();
+        assert_eq!((loaded.decode, loaded.d1, loaded.d2), (1, 6, 0x123456));
+        let display = loaded.device::<PixelDisplay>(1).unwrap();
+        assert_eq!(display.pixels[5], 0x123456);
+        assert_eq!(display.pixels[6], 0);
+
+        loaded.reset();
+        assert_eq!((loaded.decode, loaded.d1, loaded.d2), (0, 0, 0));
+        assert!(loaded.device::<PixelDisplay>(1).is_some());
+    }
+
+    #[test]
+    fn load_needs_the_same_devices() {
+        let mut saved = Vec::new();
+        port_with_display()
+            .save_state(&mut StateWriter::new(&mut saved))
+            .unwrap();
+        let result = OutputPort::new().load_state(&mut StateReader::new(&mut saved.as_slice()));
+        assert!(matches!(result, Err(SnapshotError::Mismatch(_))));
+    }
+}
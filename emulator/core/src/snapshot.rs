@@ -0,0 +1,362 @@
+use crate::cpu::CPU;
+use crate::memory::RamInit;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use log::info;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// First bytes of every save state file.
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"SS32SNAP";
+/// Format version, bumped whenever the saved state changes shape.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The file does not start with [`SNAPSHOT_MAGIC`].
+    NotASnapshot,
+    UnsupportedVersion(u32),
+    /// The state does not fit this machine (e.g. a device is missing).
+    Mismatch(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "{}", error),
+            SnapshotError::NotASnapshot => write!(f, "Not an SS32 save state"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported save state version {} (expected {})",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Mismatch(message) => {
+                write!(f, "Save state does not match the machine: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+/// Little-endian sink devices write their state to, see
+/// [`crate::BusDevice::save_state`].
+pub struct StateWriter<'a> {
+    out: &'a mut dyn Write,
+}
+
+impl<'a> StateWriter<'a> {
+    pub fn new(out: &'a mut dyn Write) -> StateWriter<'a> {
+        StateWriter { out }
+    }
+    pub fn put_u8(&mut self, value: u8) -> io::Result<()> {
+        return self.out.write_all(&[value]);
+    }
+    pub fn put_bool(&mut self, value: bool) -> io::Result<()> {
+        return self.put_u8(value as u8);
+    }
+    pub fn put_u32(&mut self, value: u32) -> io::Result<()> {
+        return self.out.write_all(&value.to_le_bytes());
+    }
+    pub fn put_u64(&mut self, value: u64) -> io::Result<()> {
+        return self.out.write_all(&value.to_le_bytes());
+    }
+    /// Writes the length followed by the words.
+    pub fn put_words(&mut self, words: &[u32]) -> io::Result<()> {
+        self.put_u64(words.len() as u64)?;
+        for chunk in words.chunks(4096) {
+            let bytes: Vec<u8> = chunk.iter().flat_map(|word| word.to_le_bytes()).collect();
+            self.out.write_all(&bytes)?;
+        }
+        return Ok(());
+    }
+}
+
+/// Source devices read their state back from, see
+/// [`crate::BusDevice::load_state`].
+pub struct StateReader<'a> {
+    input: &'a mut dyn Read,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(input: &'a mut dyn Read) -> StateReader<'a> {
+        StateReader { input }
+    }
+    pub fn get_u8(&mut self) -> io::Result<u8> {
+        let mut bytes = [0; 1];
+        self.input.read_exact(&mut bytes)?;
+        return Ok(bytes[0]);
+    }
+    pub fn get_bool(&mut self) -> io::Result<bool> {
+        return Ok(self.get_u8()? != 0);
+    }
+    pub fn get_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        self.input.read_exact(&mut bytes)?;
+        return Ok(u32::from_le_bytes(bytes));
+    }
+    pub fn get_u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        self.input.read_exact(&mut bytes)?;
+        return Ok(u64::from_le_bytes(bytes));
+    }
+    /// Reads words written by [`StateWriter::put_words`] into `words`,
+    /// which must have the saved length.
+    pub fn get_words_into(&mut self, words: &mut [u32]) -> Result<(), SnapshotError> {
+        let len = self.get_u64()? as usize;
+        if len != words.len() {
+            return Err(SnapshotError::Mismatch(format!(
+                "expected {} words, found {}",
+                words.len(),
+                len
+            )));
+        }
+        let mut bytes = vec![0; 4096 * 4];
+        for chunk in words.chunks_mut(4096) {
+            let bytes = &mut bytes[..chunk.len() * 4];
+            self.input.read_exact(bytes)?;
+            for (word, le) in chunk.iter_mut().zip(bytes.chunks_exact(4)) {
+                *word = u32::from_le_bytes([le[0], le[1], le[2], le[3]]);
+            }
+        }
+        return Ok(());
+    }
+}
+
+fn put_ram_init(out: &mut StateWriter, init: RamInit) -> io::Result<()> {
+    let (tag, value) = match init {
+        RamInit::Zero => (0, 0),
+        RamInit::Pattern(pattern) => (1, pattern as u64),
+        RamInit::Seeded(seed) => (2, seed),
+        RamInit::Random => (3, 0),
+    };
+    out.put_u8(tag)?;
+    return out.put_u64(value);
+}
+
+fn get_ram_init(input: &mut StateReader) -> Result<RamInit, SnapshotError> {
+    let tag = input.get_u8()?;
+    let value = input.get_u64()?;
+    return match tag {
+        0 => Ok(RamInit::Zero),
+        1 => Ok(RamInit::Pattern(value as u32)),
+        2 => Ok(RamInit::Seeded(value)),
+        3 => Ok(RamInit::Random),
+        _ => Err(SnapshotError::Mismatch(format!("RAM init mode {}", tag))),
+    };
+}
+
+/// A latched register field, which the next instruction indexes the
+/// registers with.
+fn get_register_index(input: &mut StateReader, field: &str) -> Result<usize, SnapshotError> {
+    let index = input.get_u32()?;
+    if index >= 16 {
+        return Err(SnapshotError::Mismatch(format!(
+            "{} register {} out of range",
+            field, index
+        )));
+    }
+    return Ok(index as usize);
+}
+
+/// Save states: the header ([`SNAPSHOT_MAGIC`], [`SNAPSHOT_VERSION`])
+/// followed by a zlib stream of the registers, the latched decode fields,
+/// the clock and every bus device in mapping order.
+impl CPU {
+    pub fn save_state(&self, out: impl Write) -> Result<(), SnapshotError> {
+        let mut out = out;
+        out.write_all(SNAPSHOT_MAGIC)?;
+        out.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        let mut encoder = ZlibEncoder::new(out, Compression::fast());
+        let mut state = StateWriter::new(&mut encoder);
+        for i in 0..16 {
+            state.put_u32(self.registers[i])?;
+        }
+        state.put_u64(self.registers.pc as u64)?;
+        state.put_u32(self.registers.sp)?;
+        state.put_u32(self.registers.reti)?;
+        state.put_bool(self.registers.privilege)?;
+        state.put_u32(self.registers.flags())?;
+        state.put_u64(self.clock)?;
+        state.put_u32(self.opcode)?;
+        state.put_u32(self.ir)?;
+        state.put_u32(self.dr as u32)?;
+        state.put_u32(self.sr2 as u32)?;
+        state.put_u32(self.sr1 as u32)?;
+        state.put_u32(self.immediate)?;
+        state.put_u32(self.recent_memory_accesses.0)?;
+        state.put_u32(self.recent_memory_accesses.1)?;
+        put_ram_init(&mut state, self.ram_init)?;
+        self.bus.save_state(&mut state)?;
+        encoder.finish()?.flush()?;
+        if self.log {
+            info!("Saved state at clock {}", self.clock);
+        }
+        return Ok(());
+    }
+    /// Restores a state written by [`CPU::save_state`]. On error the CPU is
+    /// left untouched.
+    pub fn load_state(&mut self, input: impl Read) -> Result<(), SnapshotError> {
+        let mut input = input;
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let mut version = [0; 4];
+        input.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let mut decoder = ZlibDecoder::new(input);
+        let mut state = StateReader::new(&mut decoder);
+        // Zero fill, the saved RAM overwrites it anyway
+        let mut cpu = CPU::with_ram_init(Vec::new(), RamInit::Zero, self.log);
+        for i in 0..16 {
+            cpu.registers[i] = state.get_u32()?;
+        }
+        cpu.registers.pc = state.get_u64()? as usize;
+        cpu.registers.sp = state.get_u32()?;
+        cpu.registers.reti = state.get_u32()?;
+        cpu.registers.privilege = state.get_bool()?;
+        cpu.registers.set_flags(state.get_u32()?);
+        cpu.clock = state.get_u64()?;
+        cpu.opcode = state.get_u32()?;
+        cpu.ir = state.get_u32()?;
+        cpu.dr = get_register_index(&mut state, "DR")?;
+        cpu.sr2 = get_register_index(&mut state, "SR2")?;
+        cpu.sr1 = get_register_index(&mut state, "SR1")?;
+        cpu.immediate = state.get_u32()?;
+        cpu.recent_memory_accesses = (state.get_u32()?, state.get_u32()?);
+        cpu.ram_init = get_ram_init(&mut state)?;
+        cpu.bus.load_state(&mut state)?;
+        // The recorded history belongs to the old run
+        cpu.set_history(self.history.is_some());
+        cpu.debugger = std::mem::take(&mut self.debugger);
+        *self = cpu;
+        if self.log {
+            info!("Loaded state at clock {}", self.clock);
+        }
+        return Ok(());
+    }
+    pub fn save_state_file(&self, path: &Path) -> Result<(), SnapshotError> {
+        return self.save_state(BufWriter::new(File::create(path)?));
+    }
+    pub fn load_state_file(&mut self, path: &Path) -> Result<(), SnapshotError> {
+        return self.load_state(BufReader::new(File::open(path)?));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::PixelDisplay;
+    use crate::{Assembler, BusDevice};
+
+    /// Counts in R2, plotting each count and storing it at 0x30, while a
+    /// periodic timer interrupt counts in R5.
+    const PROGRAM: &str = "#bankdef test {\n#bits 32\n#outp 0\n}\n\
+        LDI R1 - 20\nST R1 - 0x7ffffb\nLDI R1 - 8\nST R1 - 0x7ffffc\n\
+        LDI R1 - 3\nST R1 - 0x7ffffa\nLDI R1 - 1\nST R1 - 0x7fffc1\n\
+        OPW-En - 1\nEI\n\
+        loop:\nADD R2 - R2, R1\nOPD1W - R2\nOPD2W - R2\nST R2 - 0x30\nJMP loop\n\
+        #addr 0x48\n#d32 handler\n\
+        #addr 0x60\nhandler:\nST R1 - 0x7fffc0\nADD R5 - R5, R1\nRETI\n";
+
+    fn boot() -> CPU {
+        let mut assembler = Assembler::new();
+        assembler.add_source("test.asm", PROGRAM);
+        return CPU::with_ram_init(assembler.assemble().unwrap(), RamInit::Zero, false);
+    }
+
+    fn step(cpu: &mut CPU, steps: usize) {
+        for _ in 0..steps {
+            assert_eq!(cpu.execute_instruction(false, 0), crate::CPUError::Ok);
+        }
+    }
+
+    fn saved(cpu: &CPU) -> Vec<u8> {
+        let mut state = Vec::new();
+        cpu.save_state(&mut state).unwrap();
+        return state;
+    }
+
+    #[test]
+    fn a_loaded_state_runs_like_the_original() {
+        let mut original = boot();
+        step(&mut original, 30);
+        let mut loaded = CPU::with_ram_init(Vec::new(), RamInit::Seeded(1), false);
+        loaded.load_state(saved(&original).as_slice()).unwrap();
+        let interrupts = original.registers[5];
+        step(&mut original, 60);
+        step(&mut loaded, 60);
+        assert!(original.registers[5] > interrupts);
+
+        for i in 0..16 {
+            assert_eq!(loaded.registers[i], original.registers[i], "R{}", i);
+        }
+        let registers = |cpu: &CPU| {
+            let r = &cpu.registers;
+            (r.pc, r.sp, r.reti, r.privilege, r.flags(), cpu.clock)
+        };
+        assert_eq!(registers(&loaded), registers(&original));
+        assert!(loaded.ram().words == original.ram().words);
+        assert_eq!(loaded.ram_init, RamInit::Zero);
+        let pixels = |cpu: &CPU| cpu.port().device::<PixelDisplay>(1).unwrap().pixels.clone();
+        assert_eq!(pixels(&loaded), pixels(&original));
+        let devices = |cpu: &CPU| {
+            let mut state = Vec::new();
+            let mut out = StateWriter::new(&mut state);
+            cpu.interrupts().save_state(&mut out).unwrap();
+            cpu.timer().save_state(&mut out).unwrap();
+            cpu.mmu().save_state(&mut out).unwrap();
+            cpu.mpu().save_state(&mut out).unwrap();
+            return state;
+        };
+        assert_eq!(devices(&loaded), devices(&original));
+    }
+
+    #[test]
+    fn latched_register_fields_are_range_checked() {
+        let mut cpu = boot();
+        step(&mut cpu, 3);
+        let state = saved(&cpu);
+        // DR, SR2 and SR1 follow 16 registers, PC, SP, RETI, the
+        // privilege bit, the flags, the clock, the opcode and IR. Loading
+        // stops at the bad field, so the state can end there.
+        let header = SNAPSHOT_MAGIC.len() + 4;
+        let fields = 16 * 4 + 8 + 4 + 4 + 1 + 4 + 8 + 4 + 4;
+        for (field, name) in ["DR", "SR2", "SR1"].iter().enumerate() {
+            let mut body = Vec::new();
+            ZlibDecoder::new(&state[header..])
+                .take(fields as u64 + 12)
+                .read_to_end(&mut body)
+                .unwrap();
+            let at = fields + field * 4;
+            body[at..at + 4].copy_from_slice(&16u32.to_le_bytes());
+            let mut corrupt = state[..header].to_vec();
+            let mut encoder = ZlibEncoder::new(&mut corrupt, Compression::fast());
+            encoder.write_all(&body).unwrap();
+            encoder.finish().unwrap();
+
+            let mut target = boot();
+            let Err(SnapshotError::Mismatch(message)) = target.load_state(corrupt.as_slice())
+            else {
+                panic!("{} 16 was loaded", name);
+            };
+            assert_eq!(message, format!("{} register 16 out of range", name));
+            assert_eq!(target.registers.pc, 0);
+        }
+    }
+}
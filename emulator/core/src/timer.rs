@@ -0,0 +1,228 @@
+use crate::bus::BusDevice;
+use crate::interrupt::INTERRUPT_LINES;
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+use std::any::Any;
+use std::io;
+
+/// First word of the timer registers, inside the supervisor-only IO page.
+pub const TIMER_START: usize = 0x7ffff8;
+/// Last word of the timer registers.
+pub const TIMER_END: usize = 0x7ffffd;
+/// Interrupt line the timer raises after reset.
+pub const TIMER_DEFAULT_LINE: u8 = 0;
+
+/// Read-only. Low word of the free-running cycle counter.
+pub const TIMER_COUNTER_LO: usize = 0x00;
+/// Read-only. High word of the free-running cycle counter.
+pub const TIMER_COUNTER_HI: usize = 0x01;
+/// Read/write. Bit 0: enabled, bit 1: periodic (else one-shot), bit 2
+/// (read-only): fired since the last write to this register.
+pub const TIMER_CONTROL: usize = 0x02;
+/// Read/write. The timer fires when the low word of the counter next
+/// reaches this value; write it before enabling the timer.
+pub const TIMER_COMPARE: usize = 0x03;
+/// Read/write. Cycles added to the compare value each time a periodic
+/// timer fires. A periodic timer with reload 0 fires once and disables
+/// itself like a one-shot timer.
+pub const TIMER_RELOAD: usize = 0x04;
+/// Read/write. Interrupt line raised when the timer fires.
+pub const TIMER_LINE: usize = 0x05;
+
+pub const TIMER_ENABLE: u32 = 0x01;
+pub const TIMER_PERIODIC: u32 = 0x02;
+pub const TIMER_FIRED: u32 = 0x04;
+
+/// Timer/counter counting CPU cycles, so runs stay deterministic whatever
+/// the host speed.
+///
+/// The bus ticks it with [`crate::CPU::clock`] before every instruction.
+/// Once the counter passes the compare value the timer fires: it raises
+/// its interrupt line and either disables itself (one-shot, or periodic
+/// with reload 0) or moves the compare value on by the reload value
+/// (periodic).
+pub struct Timer {
+    pub counter: u64,
+    /// The counter value the timer fires at.
+    pub deadline: u64,
+    pub reload: u32,
+    pub line: u8,
+    pub enabled: bool,
+    pub periodic: bool,
+    pub fired: bool,
+    interrupt: bool,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            counter: 0,
+            deadline: 0,
+            reload: 0,
+            line: TIMER_DEFAULT_LINE,
+            enabled: false,
+            periodic: false,
+            fired: false,
+            interrupt: false,
+        }
+    }
+    fn control(&self) -> u32 {
+        let mut control = 0;
+        if self.enabled {
+            control |= TIMER_ENABLE;
+        }
+        if self.periodic {
+            control |= TIMER_PERIODIC;
+        }
+        if self.fired {
+            control |= TIMER_FIRED;
+        }
+        return control;
+    }
+    /// Points the deadline at the next counter value whose low word is
+    /// `compare`.
+    fn set_compare(&mut self, compare: u32) {
+        let deadline = (self.counter & !0xFFFFFFFF) | compare as u64;
+        self.deadline = if deadline <= self.counter {
+            deadline + (1 << 32)
+        } else {
+            deadline
+        };
+    }
+    pub fn reset(&mut self) {
+        let counter = self.counter;
+        *self = Timer::new();
+        self.counter = counter;
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
+}
+
+impl BusDevice for Timer {
+    fn read(&mut self, offset: usize) -> u32 {
+        return self.peek(offset);
+    }
+    fn write(&mut self, offset: usize, value: u32) {
+        match offset {
+            TIMER_CONTROL => {
+                self.enabled = value & TIMER_ENABLE != 0;
+                self.periodic = value & TIMER_PERIODIC != 0;
+                self.fired = false;
+            }
+            TIMER_COMPARE => self.set_compare(value),
+            TIMER_RELOAD => self.reload = value,
+            TIMER_LINE => self.line = (value as usize % INTERRUPT_LINES) as u8,
+            _ => {}
+        }
+    }
+    fn peek(&self, offset: usize) -> u32 {
+        match offset {
+            TIMER_COUNTER_LO => self.counter as u32,
+            TIMER_COUNTER_HI => (self.counter >> 32) as u32,
+            TIMER_CONTROL => self.control(),
+            TIMER_COMPARE => self.deadline as u32,
+            TIMER_RELOAD => self.reload,
+            TIMER_LINE => self.line as u32,
+            _ => 0,
+        }
+    }
+    fn tick(&mut self, clock: u64) {
+        self.counter = clock;
+        if !self.enabled || self.counter < self.deadline {
+            return;
+        }
+        self.fired = true;
+        self.interrupt = true;
+        if self.periodic && self.reload != 0 {
+            // Skip the periods a long instruction stepped over
+            while self.deadline <= self.counter {
+                self.deadline += self.reload as u64;
+            }
+        } else {
+            self.enabled = false;
+        }
+    }
+    fn poll_interrupts(&mut self) -> u32 {
+        if !self.interrupt {
+            return 0;
+        }
+        self.interrupt = false;
+        return 1 << self.line;
+    }
+    fn save_state(&self, out: &mut StateWriter) -> io::Result<()> {
+        out.put_u64(self.counter)?;
+        out.put_u64(self.deadline)?;
+        out.put_u32(self.reload)?;
+        out.put_u8(self.line)?;
+        out.put_bool(self.enabled)?;
+        out.put_bool(self.periodic)?;
+        out.put_bool(self.fired)?;
+        return out.put_bool(self.interrupt);
+    }
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.counter = input.get_u64()?;
+        self.deadline = input.get_u64()?;
+        self.reload = input.get_u32()?;
+        self.line = input.get_u8()?;
+        self.enabled = input.get_bool()?;
+        self.periodic = input.get_bool()?;
+        self.fired = input.get_bool()?;
+        self.interrupt = input.get_bool()?;
+        return Ok(());
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A timer due at cycle 10, enabled with `control`.
+    fn timer(control: u32, reload: u32) -> Timer {
+        let mut timer = Timer::new();
+        timer.write(TIMER_COMPARE, 10);
+        timer.write(TIMER_RELOAD, reload);
+        timer.write(TIMER_CONTROL, control);
+        return timer;
+    }
+
+    /// The cycles in `0..cycles` the timer fires at.
+    fn fires(timer: &mut Timer, cycles: u64) -> Vec<u64> {
+        return (0..cycles)
+            .filter(|clock| {
+                timer.tick(*clock);
+                timer.poll_interrupts() != 0
+            })
+            .collect();
+    }
+
+    #[test]
+    fn one_shot_and_periodic() {
+        let mut one_shot = timer(TIMER_ENABLE, 5);
+        assert_eq!(fires(&mut one_shot, 40), [10]);
+        assert_eq!(one_shot.read(TIMER_CONTROL), TIMER_FIRED);
+
+        let mut periodic = timer(TIMER_ENABLE | TIMER_PERIODIC, 8);
+        assert_eq!(fires(&mut periodic, 40), [10, 18, 26, 34]);
+        assert_eq!(
+            periodic.read(TIMER_CONTROL),
+            TIMER_ENABLE | TIMER_PERIODIC | TIMER_FIRED
+        );
+    }
+
+    #[test]
+    fn periodic_with_reload_zero_fires_once() {
+        let mut timer = timer(TIMER_ENABLE | TIMER_PERIODIC, 0);
+        assert_eq!(fires(&mut timer, 40), [10]);
+        assert!(!timer.enabled);
+        assert_eq!(timer.read(TIMER_CONTROL), TIMER_PERIODIC | TIMER_FIRED);
+    }
+}
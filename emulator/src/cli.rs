@@ -0,0 +1,279 @@
+use ss32_core::{FaultAction, Format, RamInit};
+
+/// Command-line options accepted by the emulator.
+///
+/// `SS32-Emulator [program.hex] [--format hex|ihex|logisim|bin-le|bin-be]
+/// [-L log_file] [--headless]
+/// [--max-cycles N] [--max-instructions N] [--on-fault stop|skip|trap]
+/// [--ram-init zero|pattern:N|seed[:N]|random] [--check-uninit]
+/// [--load-state file] [--save-state file] [--gdb PORT|HOST:PORT|unix:PATH]`
+///
+/// `SS32-Emulator asm program.asm [-c] [-o program.hex]` assembles a program,
+/// or with `-c` makes an object of it.
+/// `SS32-Emulator link a.o b.asm... [-T script.ld] [-o program.hex]
+/// [--map program.map]` links objects, and `.asm` files assembled as
+/// objects, into one program.
+/// `SS32-Emulator disasm program.hex` prints its disassembly.
+pub struct Options {
+    pub command: Command,
+    pub program: Option<String>,
+    /// Objects and sources for `link`.
+    pub inputs: Vec<String>,
+    pub log_file: Option<String>,
+    pub headless: bool,
+    pub max_cycles: Option<u64>,
+    pub max_instructions: Option<u64>,
+    pub on_fault: FaultAction,
+    pub ram_init: RamInit,
+    /// Stop on reads of RAM nothing wrote.
+    pub check_uninitialized: bool,
+    /// Save state to restore instead of powering on with `program`.
+    pub load_state: Option<String>,
+    /// Where a headless run saves the machine when it stops.
+    pub save_state: Option<String>,
+    /// Where to wait for a GDB connection instead of opening the window.
+    pub gdb: Option<String>,
+    /// Where `asm` and `link` write their output, standard output if unset.
+    pub output: Option<String>,
+    /// `asm` makes a relocatable object instead of hex.
+    pub object: bool,
+    /// Linker script for `link`, the default one if unset.
+    pub script: Option<String>,
+    /// Where `link` writes the symbol map.
+    pub map: Option<String>,
+    /// Format of `program`, guessed from the file if unset.
+    pub format: Option<Format>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    /// Assemble `program` to hex.
+    Assemble,
+    /// Print the disassembly of `program`.
+    Disassemble,
+    /// Link `inputs` to hex.
+    Link,
+}
+
+fn parse_fault_action(value: Option<String>) -> Result<FaultAction, String> {
+    match value.as_deref() {
+        Some("stop") => Ok(FaultAction::Stop),
+        Some("skip") => Ok(FaultAction::Skip),
+        Some("trap") => Ok(FaultAction::Trap),
+        Some(other) => Err(format!("Invalid value for --on-fault: {}", other)),
+        None => Err("--on-fault requires a value".to_string()),
+    }
+}
+
+fn parse_format(value: Option<String>) -> Result<Format, String> {
+    match value.as_deref() {
+        Some("hex") => Ok(Format::Hex),
+        Some("ihex") => Ok(Format::IntelHex),
+        Some("logisim") => Ok(Format::Logisim),
+        Some("bin" | "bin-le") => Ok(Format::BinaryLittleEndian),
+        Some("bin-be") => Ok(Format::BinaryBigEndian),
+        Some(other) => Err(format!("Invalid value for --format: {}", other)),
+        None => Err("--format requires a value".to_string()),
+    }
+}
+
+fn parse_ram_init(value: Option<String>) -> Result<RamInit, String> {
+    let value = value.ok_or("--ram-init requires a value")?;
+    let (mode, argument) = match value.split_once(':') {
+        Some((mode, argument)) => (mode, Some(argument.to_string())),
+        None => (value.as_str(), None),
+    };
+    match (mode, argument) {
+        ("zero", None) => Ok(RamInit::Zero),
+        ("random", None) => Ok(RamInit::Random),
+        ("seed", None) => Ok(RamInit::random_seed()),
+        ("seed", seed) => Ok(RamInit::Seeded(parse_number("--ram-init seed", seed)?)),
+        ("pattern", pattern) => {
+            let pattern = parse_number("--ram-init pattern", pattern)?;
+            let pattern = u32::try_from(pattern)
+                .map_err(|_| format!("RAM pattern does not fit in a word: 0x{:x}", pattern))?;
+            Ok(RamInit::Pattern(pattern))
+        }
+        _ => Err(format!("Invalid value for --ram-init: {}", value)),
+    }
+}
+
+fn parse_number(flag: &str, value: Option<String>) -> Result<u64, String> {
+    let value = value.ok_or_else(|| format!("{} requires a value", flag))?;
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        command: Command::Run,
+        program: None,
+        log_file: None,
+        headless: false,
+        max_cycles: None,
+        max_instructions: None,
+        on_fault: FaultAction::Stop,
+        ram_init: RamInit::default(),
+        check_uninitialized: false,
+        load_state: None,
+        save_state: None,
+        gdb: None,
+        output: None,
+        inputs: Vec::new(),
+        object: false,
+        script: None,
+        map: None,
+        format: None,
+    };
+    let mut args = args.into_iter().skip(1).peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("asm") => Command::Assemble,
+        Some("disasm") => Command::Disassemble,
+        Some("link") => Command::Link,
+        _ => Command::Run,
+    };
+    if command != Command::Run {
+        args.next();
+        options.command = command;
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-L" => {
+                options.log_file = Some(args.next().ok_or("Log file path not provided")?);
+            }
+            "--headless" => options.headless = true,
+            "--max-cycles" => options.max_cycles = Some(parse_number(&arg, args.next())?),
+            "--max-instructions" => {
+                options.max_instructions = Some(parse_number(&arg, args.next())?)
+            }
+            "--on-fault" => options.on_fault = parse_fault_action(args.next())?,
+            "--load-state" => {
+                options.load_state = Some(args.next().ok_or("--load-state requires a file")?);
+            }
+            "--save-state" => {
+                options.save_state = Some(args.next().ok_or("--save-state requires a file")?);
+            }
+            "-o" => options.output = Some(args.next().ok_or("Output file path not provided")?),
+            "-c" => options.object = true,
+            "-T" => options.script = Some(args.next().ok_or("-T requires a linker script")?),
+            "--map" => options.map = Some(args.next().ok_or("--map requires a file")?),
+            "--gdb" => options.gdb = Some(args.next().ok_or("--gdb requires an address")?),
+            "--check-uninit" => options.check_uninitialized = true,
+            "--ram-init" => options.ram_init = parse_ram_init(args.next())?,
+            "--format" => options.format = Some(parse_format(args.next())?),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if options.command == Command::Link => options.inputs.push(arg),
+            _ if options.program.is_none() => options.program = Some(arg),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    match options.command {
+        Command::Assemble if options.program.is_none() => {
+            return Err("asm requires a program".to_string())
+        }
+        Command::Disassemble if options.program.is_none() => {
+            return Err("disasm requires a program".to_string())
+        }
+        Command::Link if options.inputs.is_empty() => {
+            return Err("link requires at least one object".to_string())
+        }
+        Command::Run | Command::Disassemble if options.output.is_some() => {
+            return Err("-o is only used by asm and link".to_string())
+        }
+        _ if options.object && options.command != Command::Assemble => {
+            return Err("-c is only used by asm".to_string())
+        }
+        _ if (options.script.is_some() || options.map.is_some())
+            && options.command != Command::Link =>
+        {
+            return Err("-T and --map are only used by link".to_string())
+        }
+        Command::Assemble | Command::Link if options.format.is_some() => {
+            return Err("--format is only used when loading a program".to_string())
+        }
+        _ => {}
+    }
+    return Ok(options);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ss32_core::{Assembler, CPUError, CPU};
+
+    fn ram_init(value: &str) -> Result<RamInit, String> {
+        let args = ["SS32-Emulator", "program.hex", "--ram-init", value];
+        return parse(args.map(String::from)).map(|options| options.ram_init);
+    }
+
+    /// Powers on with `--ram-init value` and loads the two words past the
+    /// program into R1 and R2.
+    fn boot(value: &str) -> CPU {
+        let mut assembler = Assembler::new();
+        assembler.add_source(
+            "test.asm",
+            "#bankdef test {\n#bits 32\n#outp 0\n}\nLD R1 - 0x1000\nLD R2 - 0x1001\n",
+        );
+        let words = assembler.assemble().unwrap();
+        let mut cpu = CPU::with_ram_init(words.clone(), ram_init(value).unwrap(), false);
+        for _ in 0..2 {
+            assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        }
+        assert_eq!(cpu.ram().words[..words.len()], words[..]);
+        return cpu;
+    }
+
+    fn loaded(cpu: &CPU) -> (u32, u32) {
+        return (cpu.registers[1], cpu.registers[2]);
+    }
+
+    #[test]
+    fn ram_init_fills_memory_past_the_program() {
+        assert_eq!(loaded(&boot("zero")), (0, 0));
+        assert_eq!(
+            loaded(&boot("pattern:0xdeadbeef")),
+            (0xdeadbeef, 0xdeadbeef)
+        );
+        assert_eq!(loaded(&boot("pattern:7")), (7, 7));
+
+        let seeded = loaded(&boot("seed:42"));
+        assert_eq!(loaded(&boot("seed:0x2a")), seeded);
+        assert_ne!(loaded(&boot("seed:43")), seeded);
+        assert!(matches!(ram_init("seed"), Ok(RamInit::Seeded(_))));
+
+        assert_ne!(loaded(&boot("random")), loaded(&boot("random")));
+    }
+
+    #[test]
+    fn reload_keeps_the_ram_init() {
+        let mut cpu = boot("seed:42");
+        let seeded = loaded(&cpu);
+        let program = cpu.ram().words[..2].to_vec();
+        cpu.reload(program);
+        for _ in 0..2 {
+            assert_eq!(cpu.execute_instruction(false, 0), CPUError::Ok);
+        }
+        assert_eq!(loaded(&cpu), seeded);
+    }
+
+    #[test]
+    fn invalid_ram_init_values_are_rejected() {
+        for value in [
+            "",
+            "ones",
+            "zero:1",
+            "random:1",
+            "seed:x",
+            "pattern",
+            "pattern:0x100000000",
+        ] {
+            assert!(ram_init(value).is_err(), "{:?}", value);
+        }
+        let args = ["SS32-Emulator", "--ram-init"].map(String::from);
+        assert_eq!(parse(args).err().unwrap(), "--ram-init requires a value");
+    }
+}
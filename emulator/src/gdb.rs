@@ -0,0 +1,669 @@
+use crate::headless::{EXIT_HALTED, EXIT_USAGE};
+use log::trace;
+use ss32_core::{CPUError, WatchKind, CPU};
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Register numbers as GDB sees them: R0-R14 and tmp are 0-15.
+const REGISTER_PC: usize = 16;
+const REGISTER_SP: usize = 17;
+const REGISTER_RETI: usize = 18;
+const REGISTER_FLAGS: usize = 19;
+const REGISTER_COUNT: usize = 20;
+
+/// Instructions run between checks for a Ctrl-C from the debugger.
+const INTERRUPT_POLL_INTERVAL: u32 = 4096;
+
+/// GDB addresses bytes, the SS32 words: byte address `4 * n` is word `n`,
+/// words are little-endian in memory dumps and the PC is reported the same
+/// way so it matches breakpoint addresses.
+const BYTES_PER_WORD: usize = 4;
+
+/// Packet size advertised in `qSupported`. A memory read replies with two
+/// hex digits per byte, so it returns at most half as many bytes.
+const PACKET_SIZE: usize = 0x4000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.ss32.core">
+    <flags id="ss32_flags" size="4">
+      <field name="C" start="0" end="0"/>
+      <field name="Z" start="1" end="1"/>
+      <field name="CMP" start="2" end="2"/>
+      <field name="V" start="3" end="3"/>
+      <field name="N" start="4" end="4"/>
+      <field name="IE" start="5" end="5"/>
+    </flags>
+    <reg name="r0" bitsize="32" regnum="0" type="uint32"/>
+    <reg name="r1" bitsize="32" type="uint32"/>
+    <reg name="r2" bitsize="32" type="uint32"/>
+    <reg name="r3" bitsize="32" type="uint32"/>
+    <reg name="r4" bitsize="32" type="uint32"/>
+    <reg name="r5" bitsize="32" type="uint32"/>
+    <reg name="r6" bitsize="32" type="uint32"/>
+    <reg name="r7" bitsize="32" type="uint32"/>
+    <reg name="r8" bitsize="32" type="uint32"/>
+    <reg name="r9" bitsize="32" type="uint32"/>
+    <reg name="r10" bitsize="32" type="uint32"/>
+    <reg name="r11" bitsize="32" type="uint32"/>
+    <reg name="r12" bitsize="32" type="uint32"/>
+    <reg name="r13" bitsize="32" type="uint32"/>
+    <reg name="r14" bitsize="32" type="uint32"/>
+    <reg name="tmp" bitsize="32" type="uint32"/>
+    <reg name="pc" bitsize="32" type="code_ptr"/>
+    <reg name="sp" bitsize="32" type="uint32"/>
+    <reg name="reti" bitsize="32" type="uint32"/>
+    <reg name="flags" bitsize="32" type="ss32_flags"/>
+  </feature>
+</target>
+"#;
+
+/// A debugger connection, TCP or Unix socket.
+trait Connection: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        return TcpStream::set_nonblocking(self, nonblocking);
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        return UnixStream::set_nonblocking(self, nonblocking);
+    }
+}
+
+/// Waits for one debugger on `address` (`PORT`, `HOST:PORT` or
+/// `unix:PATH`) and serves it until it detaches or kills the target.
+/// Returns the process exit code.
+pub fn serve(cpu: &mut CPU, address: &str) -> i32 {
+    let connection = match accept(address) {
+        Ok(connection) => connection,
+        Err(error) => {
+            eprintln!("Error: GDB server on {}: {}", address, error);
+            return EXIT_USAGE;
+        }
+    };
+    let mut session = Session {
+        cpu,
+        connection,
+        no_ack: false,
+        hardware_breakpoints: HashSet::new(),
+    };
+    return match session.run() {
+        Ok(()) => EXIT_HALTED,
+        Err(error) => {
+            eprintln!("Error: GDB connection: {}", error);
+            EXIT_USAGE
+        }
+    };
+}
+
+fn accept(address: &str) -> io::Result<Box<dyn Connection>> {
+    if let Some(path) = address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        {
+            let listener = UnixListener::bind(path)?;
+            println!("Waiting for GDB on {}", path);
+            let (stream, _) = listener.accept()?;
+            return Ok(Box::new(stream));
+        }
+        #[cfg(not(unix))]
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Unix sockets are not available here: {}", path),
+            ));
+        }
+    }
+    let address = if address.chars().all(|c| c.is_ascii_digit()) {
+        format!("127.0.0.1:{}", address)
+    } else {
+        address.to_string()
+    };
+    let listener = TcpListener::bind(&address)?;
+    println!("Waiting for GDB on {}", address);
+    let (stream, peer) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    println!("GDB connected from {}", peer);
+    return Ok(Box::new(stream));
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn hex_word(value: u32) -> String {
+    return value
+        .to_le_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    return usize::from_str_radix(text, 16).ok();
+}
+
+fn parse_hex_word(text: &str) -> Option<u32> {
+    if text.len() != 8 || !text.is_ascii() {
+        return None;
+    }
+    let mut bytes = [0; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    return Some(u32::from_le_bytes(bytes));
+}
+
+/// Splits `addr,len` into byte address and length.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, len) = text.split_once(',')?;
+    return Some((parse_hex(address)?, parse_hex(len)?));
+}
+
+enum Packet {
+    Command(String),
+    /// Ctrl-C outside a packet.
+    Interrupt,
+    Closed,
+}
+
+struct Session<'a> {
+    cpu: &'a mut CPU,
+    connection: Box<dyn Connection>,
+    /// Set by `QStartNoAckMode`.
+    no_ack: bool,
+    /// Word addresses inserted with `Z1`, reported as `hwbreak`.
+    hardware_breakpoints: HashSet<usize>,
+}
+
+impl Session<'_> {
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            let packet = match self.receive()? {
+                Packet::Command(packet) => packet,
+                Packet::Interrupt => continue,
+                Packet::Closed => return Ok(()),
+            };
+            if self.cpu.log {
+                trace!("GDB <- {}", packet);
+            }
+            match packet.as_str() {
+                "k" => return Ok(()),
+                "D" => {
+                    self.send("OK")?;
+                    return Ok(());
+                }
+                _ => {
+                    let reply = self.handle(&packet)?;
+                    self.send(&reply)?;
+                }
+            }
+        }
+    }
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0; 1];
+        return match self.connection.read(&mut byte) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(error) => Err(error),
+        };
+    }
+    fn receive(&mut self) -> io::Result<Packet> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(Packet::Closed);
+            };
+            match byte {
+                0x03 => return Ok(Packet::Interrupt),
+                b'$' => {}
+                // Acks of our replies and line noise
+                _ => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(Packet::Closed),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut sum = [0; 2];
+            self.connection.read_exact(&mut sum)?;
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                == Some(checksum(&data));
+            if !self.no_ack {
+                self.connection.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Packet::Command(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        if self.cpu.log {
+            trace!("GDB -> {}", data);
+        }
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+        loop {
+            self.connection.write_all(packet.as_bytes())?;
+            self.connection.flush()?;
+            if self.no_ack {
+                return Ok(());
+            }
+            // Resend on a nack, anything else counts as received
+            match self.read_byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+    /// The reply to `packet`, empty for unsupported packets.
+    fn handle(&mut self, packet: &str) -> io::Result<String> {
+        // Empty packets and a non-ASCII first byte are unsupported
+        let Some((command, arguments)) = packet.split_at_checked(1) else {
+            return Ok(String::new());
+        };
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => (0..REGISTER_COUNT)
+                .map(|register| hex_word(self.register(register)))
+                .collect(),
+            "G" => self.write_registers(arguments),
+            "p" => match parse_hex(arguments) {
+                Some(register) if register < REGISTER_COUNT => hex_word(self.register(register)),
+                _ => "E01".to_string(),
+            },
+            "P" => self.write_register(arguments),
+            "m" => self.read_memory(arguments),
+            "M" => self.write_memory(arguments),
+            "s" => {
+                self.jump(arguments);
+                self.step()
+            }
+            "c" => {
+                self.jump(arguments);
+                self.resume()?
+            }
+            "v" => self.handle_v(packet)?,
+            "Z" | "z" => self.breakpoint(command == "Z", arguments),
+            "H" | "T" => "OK".to_string(),
+            "q" | "Q" => self.handle_query(packet),
+            _ => String::new(),
+        };
+        return Ok(reply);
+    }
+    fn handle_v(&mut self, packet: &str) -> io::Result<String> {
+        if packet == "vCont?" {
+            return Ok("vCont;c;C;s;S".to_string());
+        }
+        let Some(actions) = packet.strip_prefix("vCont;") else {
+            return Ok(String::new());
+        };
+        // Single threaded, the first action applies
+        let action = actions.split(';').next().unwrap_or("");
+        return match action.chars().next() {
+            Some('s') | Some('S') => Ok(self.step()),
+            Some('c') | Some('C') => self.resume(),
+            _ => Ok("E01".to_string()),
+        };
+    }
+    fn handle_query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+;QStartNoAckMode+",
+                PACKET_SIZE
+            );
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_range(range) else {
+                return "E01".to_string();
+            };
+            let start = offset.min(TARGET_XML.len());
+            let end = offset.saturating_add(len).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { "m" } else { "l" };
+            return format!("{}{}", more, &TARGET_XML[start..end]);
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+    fn register(&self, register: usize) -> u32 {
+        let registers = &self.cpu.registers;
+        match register {
+            REGISTER_PC => (registers.pc * BYTES_PER_WORD) as u32,
+            REGISTER_SP => registers.sp,
+            REGISTER_RETI => registers.reti,
+            REGISTER_FLAGS => registers.flags(),
+            _ => registers[register],
+        }
+    }
+    fn set_register(&mut self, register: usize, value: u32) {
+        let registers = &mut self.cpu.registers;
+        match register {
+            REGISTER_PC => registers.pc = (value as usize / BYTES_PER_WORD) & 0xFFFFFF,
+            REGISTER_SP => registers.sp = value,
+            REGISTER_RETI => registers.reti = value,
+            REGISTER_FLAGS => registers.set_flags(value),
+            _ => registers[register] = value,
+        }
+    }
+    fn write_registers(&mut self, data: &str) -> String {
+        if data.len() != REGISTER_COUNT * 8 || !data.is_ascii() {
+            return "E01".to_string();
+        }
+        for register in 0..REGISTER_COUNT {
+            let Some(value) = parse_hex_word(&data[register * 8..register * 8 + 8]) else {
+                return "E01".to_string();
+            };
+            self.set_register(register, value);
+        }
+        return "OK".to_string();
+    }
+    fn write_register(&mut self, arguments: &str) -> String {
+        let Some((register, value)) = arguments.split_once('=') else {
+            return "E01".to_string();
+        };
+        match (parse_hex(register), parse_hex_word(value)) {
+            (Some(register), Some(value)) if register < REGISTER_COUNT => {
+                self.set_register(register, value);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+    /// Memory packets address physical memory through the bus, reads
+    /// without side effects. Reads longer than a packet holds return
+    /// their first part, which GDB continues from.
+    fn read_memory(&self, arguments: &str) -> String {
+        let Some((address, len)) = parse_range(arguments) else {
+            return "E01".to_string();
+        };
+        let Some(end) = address.checked_add(len.min(PACKET_SIZE / 2)) else {
+            return "E01".to_string();
+        };
+        return (address..end)
+            .map(|byte| {
+                let word = self.cpu.bus.peek(byte / BYTES_PER_WORD);
+                format!("{:02x}", word.to_le_bytes()[byte % BYTES_PER_WORD])
+            })
+            .collect();
+    }
+    fn write_memory(&mut self, arguments: &str) -> String {
+        let Some((range, data)) = arguments.split_once(':') else {
+            return "E01".to_string();
+        };
+        let Some((address, len)) = parse_range(range) else {
+            return "E01".to_string();
+        };
+        if len.checked_mul(2) != Some(data.len()) || !data.is_ascii() {
+            return "E01".to_string();
+        }
+        let Some(end) = address.checked_add(len) else {
+            return "E01".to_string();
+        };
+        for (i, byte) in (address..end).enumerate() {
+            let Ok(value) = u8::from_str_radix(&data[i * 2..i * 2 + 2], 16) else {
+                return "E01".to_string();
+            };
+            let word_address = byte / BYTES_PER_WORD;
+            let mut word = self.cpu.bus.peek(word_address).to_le_bytes();
+            word[byte % BYTES_PER_WORD] = value;
+            self.cpu.bus.write(word_address, u32::from_le_bytes(word));
+        }
+        return "OK".to_string();
+    }
+    /// `s addr` / `c addr` resume at `addr`.
+    fn jump(&mut self, arguments: &str) {
+        if let Some(address) = parse_hex(arguments) {
+            self.set_register(REGISTER_PC, address as u32);
+        }
+    }
+    fn step(&mut self) -> String {
+        self.cpu.debugger.resume(self.cpu.registers.pc);
+        let error = self.cpu.execute_instruction(false, 0);
+        return self.stop_reply(error);
+    }
+    /// Runs until a breakpoint, watchpoint, fault or a Ctrl-C.
+    fn resume(&mut self) -> io::Result<String> {
+        self.cpu.debugger.resume(self.cpu.registers.pc);
+        loop {
+            for _ in 0..INTERRUPT_POLL_INTERVAL {
+                let error = self.cpu.execute_instruction(false, 0);
+                if error != CPUError::Ok {
+                    return Ok(self.stop_reply(error));
+                }
+            }
+            if self.interrupted()? {
+                return Ok("S02".to_string());
+            }
+        }
+    }
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.connection.set_nonblocking(true)?;
+        let mut byte = [0; 1];
+        let read = self.connection.read(&mut byte);
+        self.connection.set_nonblocking(false)?;
+        return match read {
+            Ok(1) => Ok(byte[0] == 0x03),
+            Ok(_) => Ok(false),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        };
+    }
+    fn stop_reply(&self, error: CPUError) -> String {
+        let reason = match error {
+            CPUError::Ok | CPUError::UninitializedRead(_) => return "S05".to_string(),
+            CPUError::Halt => return "W00".to_string(),
+            CPUError::Breakpoint(pc) => {
+                if self.hardware_breakpoints.contains(&pc) {
+                    "hwbreak:".to_string()
+                } else {
+                    "swbreak:".to_string()
+                }
+            }
+            CPUError::Watchpoint(hit) => {
+                let kind = match hit.kind {
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                    WatchKind::Write | WatchKind::Change => "watch",
+                };
+                format!("{}:{:x}", kind, hit.address * BYTES_PER_WORD)
+            }
+            CPUError::DivideByZero(_) => return "S08".to_string(),
+            CPUError::PcOutOfBounds | CPUError::MemoryFault(_) | CPUError::PageFault(_) => {
+                return "S0b".to_string()
+            }
+            _ => return "S04".to_string(),
+        };
+        return format!("T05{};", reason);
+    }
+    /// `Z type,addr,kind` inserts and `z type,addr,kind` removes a
+    /// breakpoint (types 0 and 1) or watchpoint (2 write, 3 read,
+    /// 4 access).
+    fn breakpoint(&mut self, insert: bool, arguments: &str) -> String {
+        let mut fields = arguments.split(',');
+        let (Some(kind), Some(address), Some(len)) = (
+            fields.next(),
+            fields.next().and_then(parse_hex),
+            fields.next().and_then(parse_hex),
+        ) else {
+            return "E01".to_string();
+        };
+        let start = address / BYTES_PER_WORD;
+        let debugger = &mut self.cpu.debugger;
+        let watch_kind = match kind {
+            "0" | "1" => {
+                if insert {
+                    debugger.add_breakpoint(start);
+                } else {
+                    debugger.remove_breakpoint(start);
+                }
+                if kind == "1" && insert {
+                    self.hardware_breakpoints.insert(start);
+                } else {
+                    self.hardware_breakpoints.remove(&start);
+                }
+                return "OK".to_string();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return String::new(),
+        };
+        let end = address.saturating_add(len.max(1) - 1) / BYTES_PER_WORD;
+        if insert {
+            debugger.add_watchpoint(start, end, watch_kind);
+        } else {
+            debugger
+                .watchpoints
+                .retain(|w| (w.start, w.end, w.kind) != (start, end, watch_kind));
+        }
+        return "OK".to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ss32_core::{Assembler, RamInit};
+    use std::thread;
+
+    /// Sends `packet` and returns the reply, skipping acks.
+    fn exchange(stream: &mut TcpStream, packet: impl AsRef<[u8]>) -> String {
+        let packet = packet.as_ref();
+        stream.write_all(b"$").unwrap();
+        stream.write_all(packet).unwrap();
+        stream
+            .write_all(format!("#{:02x}", checksum(packet)).as_bytes())
+            .unwrap();
+        let mut byte = [0; 1];
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+        let mut reply = Vec::new();
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            reply.push(byte[0]);
+        }
+        let mut sum = [0; 2];
+        stream.read_exact(&mut sum).unwrap();
+        assert_eq!(
+            u8::from_str_radix(std::str::from_utf8(&sum).unwrap(), 16).unwrap(),
+            checksum(&reply)
+        );
+        return String::from_utf8(reply).unwrap();
+    }
+
+    /// Serves a program from a local socket to `client`, which gets the
+    /// connected stream.
+    fn serve_to(source: &str, client: impl FnOnce(TcpStream) + Send + 'static) -> CPU {
+        let mut assembler = Assembler::new();
+        assembler.add_source("test.asm", source);
+        let mut cpu = CPU::with_ram_init(assembler.assemble().unwrap(), RamInit::Zero, false);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || client(TcpStream::connect(address).unwrap()));
+        let (stream, _) = listener.accept().unwrap();
+        let mut session = Session {
+            cpu: &mut cpu,
+            connection: Box::new(stream),
+            no_ack: false,
+            hardware_breakpoints: HashSet::new(),
+        };
+        session.run().unwrap();
+        client.join().unwrap();
+        return cpu;
+    }
+
+    #[test]
+    fn scripted_session() {
+        // Word 0x20 is byte address 0x80, word 3 is byte address 0xc
+        let source = "#bankdef test {\n#bits 32\n#outp 0\n}\n\
+            LDI R1 - 5\nST R1 - 0x20\nLDI R2 - 7\n#d32 0xf0000000\n";
+        let cpu = serve_to(source, |mut gdb| {
+            assert_eq!(exchange(&mut gdb, "QStartNoAckMode"), "OK");
+            assert_eq!(exchange(&mut gdb, ""), "");
+            assert_eq!(exchange(&mut gdb, "\u{e9}"), "");
+            assert_eq!(exchange(&mut gdb, "\u{e9}1"), "");
+            assert_eq!(exchange(&mut gdb, [0xff, b'1']), "");
+            assert!(exchange(&mut gdb, "qSupported:swbreak+").starts_with("PacketSize=4000;"));
+
+            let xml = exchange(&mut gdb, "qXfer:features:read:target.xml:0,ffff");
+            assert_eq!(&xml[1..], TARGET_XML);
+            assert!(xml.starts_with('l'));
+            assert!(exchange(&mut gdb, "qXfer:features:read:target.xml:0,10").starts_with('m'));
+            assert_eq!(
+                exchange(
+                    &mut gdb,
+                    "qXfer:features:read:target.xml:10,ffffffffffffffff"
+                ),
+                format!("l{}", &TARGET_XML[0x10..])
+            );
+
+            // Registers
+            let registers = exchange(&mut gdb, "g");
+            assert_eq!(registers.len(), REGISTER_COUNT * 8);
+            let mut written = registers.clone();
+            written.replace_range(3 * 8..4 * 8, "44332211");
+            assert_eq!(exchange(&mut gdb, format!("G{}", written)), "OK");
+            assert_eq!(exchange(&mut gdb, "p3"), "44332211");
+            assert_eq!(exchange(&mut gdb, "P4=78563412"), "OK");
+            assert_eq!(exchange(&mut gdb, "p4"), "78563412");
+            assert_eq!(exchange(&mut gdb, "P4=a\u{e9}bcdef"), "E01");
+            assert_eq!(exchange(&mut gdb, "P99=00000000"), "E01");
+
+            // Memory
+            assert_eq!(exchange(&mut gdb, "m0,8"), "0500006120000071");
+            assert_eq!(exchange(&mut gdb, "M100,4:efbeadde"), "OK");
+            assert_eq!(exchange(&mut gdb, "m100,4"), "efbeadde");
+            assert_eq!(exchange(&mut gdb, "M101,2:0102"), "OK");
+            assert_eq!(exchange(&mut gdb, "m100,4"), "ef0102de");
+            assert_eq!(exchange(&mut gdb, "M100,4:ef"), "E01");
+            assert_eq!(exchange(&mut gdb, "mffffffffffffffff,10"), "E01");
+            assert_eq!(exchange(&mut gdb, "Mffffffffffffffff,1:00"), "E01");
+            assert_eq!(exchange(&mut gdb, "m0,100000").len(), PACKET_SIZE);
+
+            // Step, then continue to a watchpoint, a breakpoint and the halt
+            assert_eq!(exchange(&mut gdb, "s"), "S05");
+            assert_eq!(exchange(&mut gdb, "p10"), "04000000");
+            assert_eq!(exchange(&mut gdb, "Z2,80,4"), "OK");
+            assert_eq!(exchange(&mut gdb, "Z0,c,4"), "OK");
+            assert_eq!(exchange(&mut gdb, "c"), "T05watch:80;");
+            assert_eq!(exchange(&mut gdb, "m80,4"), "05000000");
+            assert_eq!(exchange(&mut gdb, "c"), "T05swbreak:;");
+            assert_eq!(exchange(&mut gdb, "p10"), "0c000000");
+            assert_eq!(exchange(&mut gdb, "z0,c,4"), "OK");
+            assert_eq!(exchange(&mut gdb, "z2,80,4"), "OK");
+            assert_eq!(exchange(&mut gdb, "c"), "W00");
+            gdb.write_all(b"$k#6b").unwrap();
+        });
+        assert_eq!(cpu.registers[1], 5);
+        assert_eq!(cpu.registers[2], 7);
+        assert_eq!(cpu.registers[3], 0x11223344);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::cli::Options;
+use ss32_core::{CPUError, FaultAction, CPU};
+use std::path::Path;
+
+/// The program executed `HLT`.
+pub const EXIT_HALTED: i32 = 0;
+/// The CPU faulted (e.g. the PC left RAM or an instruction faulted).
+pub const EXIT_FAULT: i32 = 1;
+/// The cycle or instruction budget ran out before the program halted.
+pub const EXIT_TIMEOUT: i32 = 2;
+/// The command line could not be parsed or the program could not be loaded.
+pub const EXIT_USAGE: i32 = 3;
+
+enum Outcome {
+    Halted,
+    Fault(CPUError),
+    CycleLimit,
+    InstructionLimit,
+}
+
+/// Runs `cpu` without a window until it halts, faults or exhausts the
+/// budget in `options`, prints the final machine state and returns the
+/// process exit code.
+pub fn run(cpu: &mut CPU, options: &Options) -> i32 {
+    let (outcome, instructions) = execute(cpu, options);
+    let (status, code) = match outcome {
+        Outcome::Halted => ("Halted".to_string(), EXIT_HALTED),
+        Outcome::Fault(error) => (format!("Fault: {}", error), EXIT_FAULT),
+        Outcome::CycleLimit => ("Timeout: cycle limit reached".to_string(), EXIT_TIMEOUT),
+        Outcome::InstructionLimit => (
+            "Timeout: instruction limit reached".to_string(),
+            EXIT_TIMEOUT,
+        ),
+    };
+    println!("Status: {}", status);
+    println!("Instructions: {}", instructions);
+    println!("Clock: {}", cpu.clock);
+    print!("{}", cpu.registers);
+    if let Some(path) = &options.save_state {
+        // A failed save is reported without hiding how the run ended
+        match cpu.save_state_file(Path::new(path)) {
+            Ok(()) => println!("Saved state to {}", path),
+            Err(error) => eprintln!("Error: Could not save state {}: {}", path, error),
+        }
+    }
+    return code;
+}
+
+/// Executes instructions until the run ends, returning why and how many
+/// instructions ran.
+fn execute(cpu: &mut CPU, options: &Options) -> (Outcome, u64) {
+    let mut instructions: u64 = 0;
+    let outcome = loop {
+        if options.max_cycles.is_some_and(|max| cpu.clock >= max) {
+            break Outcome::CycleLimit;
+        }
+        if options
+            .max_instructions
+            .is_some_and(|max| instructions >= max)
+        {
+            break Outcome::InstructionLimit;
+        }
+        let error = cpu.execute_instruction(false, 0);
+        // A breakpoint stops before the instruction runs
+        if !matches!(error, CPUError::Breakpoint(_)) {
+            instructions += 1;
+        }
+        match error {
+            CPUError::Ok => {}
+            CPUError::Halt => break Outcome::Halted,
+            CPUError::PcOutOfBounds => break Outcome::Fault(error),
+            _ => {
+                eprintln!("{}", error);
+                if options.on_fault == FaultAction::Stop {
+                    break Outcome::Fault(error);
+                }
+                cpu.recover(&error, options.on_fault);
+            }
+        }
+    };
+    return (outcome, instructions);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+    use ss32_core::{Assembler, RamInit};
+
+    fn boot(source: &str) -> CPU {
+        let mut assembler = Assembler::new();
+        assembler.add_source(
+            "test.asm",
+            format!("#bankdef test {{\n#bits 32\n#outp 0\n}}\n{}", source),
+        );
+        return CPU::with_ram_init(assembler.assemble().unwrap(), RamInit::Zero, false);
+    }
+
+    fn options(args: &[&str]) -> Options {
+        let args = ["SS32-Emulator", "program.hex", "--headless"]
+            .iter()
+            .chain(args);
+        return cli::parse(args.map(|arg| arg.to_string())).unwrap();
+    }
+
+    #[test]
+    fn breakpoints_are_not_counted_as_instructions() {
+        let mut cpu = boot("NOP\nNOP\nHLT\n");
+        cpu.debugger.add_breakpoint(1);
+        let (outcome, instructions) = execute(&mut cpu, &options(&["--on-fault", "skip"]));
+        assert!(matches!(outcome, Outcome::Halted));
+        assert_eq!(instructions, 3);
+
+        let mut cpu = boot("NOP\nNOP\nHLT\n");
+        cpu.debugger.add_breakpoint(1);
+        let (outcome, instructions) = execute(&mut cpu, &options(&[]));
+        assert!(matches!(outcome, Outcome::Fault(CPUError::Breakpoint(1))));
+        assert_eq!(instructions, 1);
+    }
+
+    #[test]
+    fn a_failed_save_keeps_the_exit_code() {
+        let path = "/nonexistent/directory/state.ss32";
+        let save = ["--save-state", path];
+        assert_eq!(run(&mut boot("HLT\n"), &options(&save)), EXIT_HALTED);
+        let timeout = ["--save-state", path, "--max-instructions", "2"];
+        assert_eq!(
+            run(&mut boot("loop:\nJMP loop\n"), &options(&timeout)),
+            EXIT_TIMEOUT
+        );
+        let fault = ["--save-state", path];
+        assert_eq!(
+            run(&mut boot("#d32 0x11233400\n"), &options(&fault)),
+            EXIT_FAULT
+        );
+    }
+}
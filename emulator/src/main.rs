@@ -1,36 +1,109 @@
-use cpu::CPUError;
-use eframe;
-use egui;
+#![allow(clippy::needless_return, clippy::upper_case_acronyms)]
+use cli::Command;
 use rfd::FileDialog;
+use ss32_core::{
+    assemble_file, assembler, disassemble_line, link, loader, Assembler, CPUError, FaultAction,
+    Format, Framebuffer, LinkerScript, Object, PixelDisplay, RamInit, WatchKind, ADDRESS_SPACE,
+    CPU, PIXEL_DISPLAY_DECODES,
+};
 use std::env;
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::fs;
 use std::path::Path;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc, Mutex,
 };
 use std::thread;
-mod cpu;
+use std::time::Instant;
+mod cli;
+mod gdb;
+mod headless;
 
 static mut HZ: f64 = 0.0;
 
+/// Wall-clock pacing of the emulated CPU; lives in the front end so the
+/// core stays deterministic.
+struct Timing {
+    clock_speed: f32,
+    l_executed_t: Instant,
+    hz: f64,
+    run_fast: bool,
+    last_update_time: Instant,
+}
+
+impl Timing {
+    fn new(clock_speed: f32) -> Timing {
+        Timing {
+            clock_speed,
+            l_executed_t: Instant::now(),
+            hz: 0.0,
+            run_fast: false,
+            last_update_time: Instant::now(),
+        }
+    }
+}
+
+struct Emulator {
+    cpu: CPU,
+    timing: Timing,
+}
+
 struct GUI {
-    cpu: Arc<Mutex<cpu::CPU>>,
+    cpu: Arc<Mutex<Emulator>>,
     running: Arc<AtomicBool>,
     search_ram: String,
+    screen: Option<egui::TextureHandle>,
     error_pc_out_of_bounds: Arc<AtomicBool>,
     error_halt: Arc<AtomicBool>,
+    error_fault: Arc<Mutex<Option<CPUError>>>,
+    /// Result of the last Save State / Load State.
+    state_message: String,
+    /// Cycles the Rewind button goes back.
+    rewind_cycles: u64,
+    /// Physical address for Back To Write, in hex.
+    back_to_write: String,
+    breakpoint_form: BreakpointForm,
+    /// RAM row the view scrolls to on the next frame.
+    scroll_to: Option<usize>,
+}
+
+/// Inputs of the Breakpoints panel, addresses in hex.
+struct BreakpointForm {
+    breakpoint: String,
+    watch_start: String,
+    watch_end: String,
+    watch_kind: WatchKind,
+}
+
+impl BreakpointForm {
+    fn new() -> BreakpointForm {
+        BreakpointForm {
+            breakpoint: String::new(),
+            watch_start: String::new(),
+            watch_end: String::new(),
+            watch_kind: WatchKind::Write,
+        }
+    }
 }
 
 impl GUI {
-    fn new(_cc: &eframe::CreationContext<'_>, cpu: cpu::CPU) -> Self {
+    fn new(_cc: &eframe::CreationContext<'_>, cpu: CPU) -> Self {
         Self {
-            cpu: Arc::new(Mutex::new(cpu)),
+            cpu: Arc::new(Mutex::new(Emulator {
+                cpu,
+                timing: Timing::new(1.0),
+            })),
             running: Arc::new(AtomicBool::new(false)),
             search_ram: String::new(),
+            screen: None,
             error_pc_out_of_bounds: Arc::new(AtomicBool::new(false)),
             error_halt: Arc::new(AtomicBool::new(false)),
+            error_fault: Arc::new(Mutex::new(None)),
+            state_message: String::new(),
+            rewind_cycles: 100,
+            back_to_write: String::new(),
+            breakpoint_form: BreakpointForm::new(),
+            scroll_to: None,
         }
     }
 
@@ -38,16 +111,20 @@ impl GUI {
         let cpu_ref = Arc::clone(&self.cpu);
         let running = Arc::clone(&self.running);
         let error_pc_out_of_bounds = Arc::clone(&self.error_pc_out_of_bounds);
+        // Only used by the disabled stop on HLT below
+        #[allow(unused_variables)]
         let error_halt = Arc::clone(&self.error_halt);
+        let error_fault = Arc::clone(&self.error_fault);
         running.store(true, Ordering::SeqCst);
         thread::spawn(move || {
             while running.load(Ordering::SeqCst) {
                 let time: f32;
                 let l_clock: u64;
-                if let Ok(mut cpu) = cpu_ref.lock() {
+                if let Ok(mut emulator) = cpu_ref.lock() {
+                    let Emulator { cpu, timing } = &mut *emulator;
                     l_clock = cpu.clock;
-                    // Assuming the cpu struct has a field `last_update_time` of type `std::time::Instant`
-                    let x = 1e9 / (std::time::Instant::now() - cpu.l_executed_t).as_nanos() as f64;
+                    let x = 1e9 / (Instant::now() - timing.l_executed_t).as_nanos() as f64;
+                    timing.l_executed_t = Instant::now();
                     let error = cpu.execute_instruction(false, 0);
                     match error {
                         CPUError::Ok => {}
@@ -59,18 +136,22 @@ impl GUI {
                             // running.store(false, Ordering::SeqCst);
                             // error_halt.store(true, Ordering::SeqCst);
                         }
+                        _ => {
+                            running.store(false, Ordering::SeqCst);
+                            *error_fault.lock().unwrap() = Some(error);
+                        }
                     }
                     let current_hz = x * (cpu.clock - l_clock) as f64;
-                    let now = std::time::Instant::now();
-                    if now.duration_since(cpu.last_update_time).as_secs() >= 1 {
-                        cpu.last_update_time = now;
-                        cpu.hz = unsafe { HZ };
+                    let now = Instant::now();
+                    if now.duration_since(timing.last_update_time).as_secs() >= 1 {
+                        timing.last_update_time = now;
+                        timing.hz = unsafe { HZ };
                         unsafe { HZ = current_hz };
                     } else {
-                        unsafe { HZ = (cpu.hz + current_hz) / 2.0 };
+                        unsafe { HZ = (timing.hz + current_hz) / 2.0 };
                     } // Sealing the clock speed based on the clock speed/second
-                    time = (cpu.clock - l_clock) as f32 / cpu.clock_speed;
-                    if cpu.run_fast {
+                    time = (cpu.clock - l_clock) as f32 / timing.clock_speed;
+                    if timing.run_fast {
                         continue; // Skip the sleep if running at max speed
                     }
                 } else {
@@ -89,9 +170,10 @@ impl GUI {
                             remaining_time -= sleep_interval;
                         }
                         // Check if cpu.clock_speed has changed
-                        if let Ok(cpu) = cpu_ref.lock() {
-                            let new_time = (cpu.clock - l_clock) as f32 / cpu.clock_speed;
-                            if new_time != time || cpu.run_fast {
+                        if let Ok(emulator) = cpu_ref.lock() {
+                            let new_time =
+                                (emulator.cpu.clock - l_clock) as f32 / emulator.timing.clock_speed;
+                            if new_time != time || emulator.timing.run_fast {
                                 break;
                             }
                         }
@@ -106,14 +188,13 @@ impl GUI {
     }
 }
 
-fn display_image_from_u32_array(
-    ui: &mut egui::Ui,
+fn texture_from_u32_array(
     ctx: &egui::Context,
-    ram: Box<[u32]>,
+    ram: &[u32],
     st_adr: u32,
     width: u32,
     height: u32,
-) {
+) -> egui::TextureHandle {
     let mut pixels = vec![Default::default(); (width * height * 3) as usize];
     for i in 0..(width * height) {
         let pixel = ram[(st_adr + i) as usize];
@@ -125,16 +206,19 @@ fn display_image_from_u32_array(
         pixels[(i * 3 + 2) as usize] = b;
     }
     let color_image = egui::ColorImage::from_rgb([width as usize, height as usize], &pixels);
-    let texture: egui::TextureHandle =
-        ctx.load_texture("image", color_image, egui::TextureOptions::default());
-    ui.image(&texture, [width as f32, height as f32]);
+    return ctx.load_texture("image", color_image, egui::TextureOptions::default());
 }
 
 impl eframe::App for GUI {
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
-        let mut cpu = self.cpu.lock().unwrap();
+        let mut emulator = self.cpu.lock().unwrap();
+        let Emulator { cpu, timing } = &mut *emulator;
         let error_pcob = Arc::clone(&self.error_pc_out_of_bounds);
         let error_hlt = Arc::clone(&self.error_halt);
+        let mut error_fault = self.error_fault.lock().unwrap();
+        let mut state_message = std::mem::take(&mut self.state_message);
+        let mut rewind_cycles = self.rewind_cycles;
+        let mut back_to_write = std::mem::take(&mut self.back_to_write);
         // Top panel for general information and control buttons
         egui::TopBottomPanel::top("TopPanel").show(ctx, |ui| {
             ui.vertical_centered(|ui| {
@@ -152,58 +236,226 @@ impl eframe::App for GUI {
                     } else {
                         ui.label("Status: Stopped");
                         if ui.button("Start").clicked() {
+                            // Run off a breakpoint on the current instruction
+                            cpu.debugger.resume(cpu.registers.pc);
                             self.start_execution();
                         }
                         if ui.button("Step").clicked() {
-                            cpu.execute_instruction(false, 0);
+                            cpu.debugger.resume(cpu.registers.pc);
+                            let error = cpu.execute_instruction(false, 0);
+                            if error.instruction_fault().is_some()
+                                || matches!(
+                                    error,
+                                    CPUError::UninitializedRead(_) | CPUError::Watchpoint(_)
+                                )
+                            {
+                                *error_fault = Some(error);
+                            }
+                        }
+                        let mut history = cpu.history().is_some();
+                        if ui.checkbox(&mut history, "Record History").changed() {
+                            cpu.set_history(history);
+                        }
+                        if history {
+                            if ui.button("Step Back").clicked() {
+                                *error_fault = None;
+                                cpu.step_back();
+                            }
+                            if ui.button("Rewind").clicked() {
+                                *error_fault = None;
+                                cpu.rewind_cycles(rewind_cycles);
+                            }
+                            ui.add(egui::DragValue::new(&mut rewind_cycles).suffix(" cycles"));
+                            if ui.button("Back To Write").clicked() {
+                                if let Some(address) = parse_address(&back_to_write) {
+                                    *error_fault = None;
+                                    cpu.run_back_to_write(address);
+                                }
+                            }
+                            ui.add(
+                                egui::TextEdit::singleline(&mut back_to_write)
+                                    .hint_text("address")
+                                    .desired_width(60.0),
+                            );
                         }
                     }
                     if ui.button("Reset").clicked() {
                         self.error_pc_out_of_bounds.store(false, Ordering::SeqCst);
                         self.error_halt.store(false, Ordering::SeqCst);
+                        *error_fault = None;
                         cpu.reset();
                     }
                     if ui.button("Restart").clicked() {
                         self.error_pc_out_of_bounds.store(false, Ordering::SeqCst);
                         self.error_halt.store(false, Ordering::SeqCst);
+                        *error_fault = None;
                         cpu.restart();
                     }
                     if ui.button("⬇ Load Ram").clicked() {
                         let path = FileDialog::new()
-                            .add_filter("hex", &["hex", "hex"])
+                            .add_filter("program", &["hex", "ihex", "mem", "bin"])
+                            .add_filter("asm", &["asm"])
+                            .add_filter("any", &["*"])
+                            .pick_file();
+                        if let Some(path) = path.as_ref().filter(|path| {
+                            path.extension().is_some_and(|extension| extension == "asm")
+                        }) {
+                            match assemble_file(path) {
+                                Ok(words) => cpu.reload(words),
+                                Err(error) => state_message = error.to_string(),
+                            }
+                        } else if let Some(path) = path {
+                            match loader::load_file(&path, None) {
+                                Ok(words) => cpu.reload(words),
+                                Err(error) => state_message = format!("Load failed: {}", error),
+                            }
+                        }
+                    }
+                    if ui.button("Save State").clicked() {
+                        let path = FileDialog::new()
+                            .add_filter("SS32 state", &["ss32state"])
+                            .save_file();
+                        if let Some(path) = path {
+                            state_message = match cpu.save_state_file(&path) {
+                                Ok(()) => format!("Saved {}", path.display()),
+                                Err(error) => format!("Save failed: {}", error),
+                            };
+                        }
+                    }
+                    if ui.button("Load State").clicked() {
+                        let path = FileDialog::new()
+                            .add_filter("SS32 state", &["ss32state"])
                             .pick_file();
                         if let Some(path) = path {
-                            let mut initial_ram_content: Vec<u32> = Vec::new();
-                            let file = File::open(&path).unwrap();
-                            let reader = io::BufReader::new(file);
-                            for line in reader.lines() {
-                                let line = line.unwrap();
-                                let number = u32::from_str_radix(&line, 16)
-                                    .expect("Failed to parse hex string");
-                                initial_ram_content.push(number);
+                            state_message = match cpu.load_state_file(&path) {
+                                Ok(()) => {
+                                    self.error_pc_out_of_bounds.store(false, Ordering::SeqCst);
+                                    self.error_halt.store(false, Ordering::SeqCst);
+                                    *error_fault = None;
+                                    format!("Loaded {}", path.display())
+                                }
+                                Err(error) => format!("Load failed: {}", error),
+                            };
+                        }
+                    }
+                    if !state_message.is_empty() {
+                        ui.label(&state_message);
+                    }
+                    // Used by the next Restart / Load Ram
+                    egui::ComboBox::from_label("RAM Init")
+                        .selected_text(match cpu.ram_init {
+                            RamInit::Zero => "Zero",
+                            RamInit::Pattern(_) => "Pattern",
+                            RamInit::Seeded(_) => "Seeded",
+                            RamInit::Random => "Random",
+                        })
+                        .show_ui(ui, |ui| {
+                            let ram_init = cpu.ram_init;
+                            if ui
+                                .selectable_label(ram_init == RamInit::Zero, "Zero")
+                                .clicked()
+                            {
+                                cpu.ram_init = RamInit::Zero;
                             }
-                            *cpu = cpu::CPU::new(initial_ram_content, cpu.log, cpu.clock_speed);
+                            if ui
+                                .selectable_label(
+                                    matches!(ram_init, RamInit::Pattern(_)),
+                                    "Pattern",
+                                )
+                                .clicked()
+                            {
+                                cpu.ram_init = RamInit::Pattern(0xDEADBEEF);
+                            }
+                            if ui
+                                .selectable_label(matches!(ram_init, RamInit::Seeded(_)), "Seeded")
+                                .clicked()
+                            {
+                                cpu.ram_init = RamInit::random_seed();
+                            }
+                            if ui
+                                .selectable_label(ram_init == RamInit::Random, "Random")
+                                .clicked()
+                            {
+                                cpu.ram_init = RamInit::Random;
+                            }
+                        });
+                    match &mut cpu.ram_init {
+                        RamInit::Pattern(pattern) => {
+                            ui.add(egui::DragValue::new(pattern).hexadecimal(8, false, true));
+                        }
+                        RamInit::Seeded(seed) => {
+                            ui.label("Seed:");
+                            ui.add(egui::DragValue::new(seed));
                         }
+                        _ => {}
                     }
-                    ui.checkbox(&mut cpu.run_fast, "Run At Max Speed");
+                    let mut shadow = cpu.ram().shadow_enabled();
+                    if ui
+                        .checkbox(&mut shadow, "Detect Uninitialised Reads")
+                        .changed()
+                    {
+                        cpu.ram_mut().set_shadow(shadow);
+                    }
+                    ui.checkbox(&mut timing.run_fast, "Run At Max Speed");
                     ui.add(
-                        egui::Slider::new(&mut cpu.clock_speed, 0.1..=100.0).text("Clock Speed"),
+                        egui::Slider::new(&mut timing.clock_speed, 0.1..=100.0).text("Clock Speed"),
                     );
-                    ui.label(format!("Hz: {:.5}", cpu.hz));
+                    ui.label(format!("Hz: {:.5}", timing.hz));
                 });
             });
         });
 
+        self.state_message = state_message;
+        self.rewind_cycles = rewind_cycles;
+        self.back_to_write = back_to_write;
+
         // Left panel for the small screen
         egui::SidePanel::left("left_panel")
             .resizable(false)
             .exact_width(640.0)
             .show(ctx, |ui| {
-                ui.vertical_centered(|ui| {
-                    ui.heading("Screen");
-                    ui.separator();
-                    display_image_from_u32_array(ui, ctx, cpu.ram.clone(), 0xFB5000, 640, 480);
-                    ui.separator();
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.vertical_centered(|ui| {
+                        ui.heading("Screen");
+                        ui.separator();
+                        if let Some(framebuffer) = cpu.bus.device_mut::<Framebuffer>() {
+                            // Only re-upload the screen when a store touched it
+                            if framebuffer.dirty || self.screen.is_none() {
+                                self.screen = Some(texture_from_u32_array(
+                                    ctx,
+                                    &framebuffer.pixels,
+                                    0,
+                                    640,
+                                    480,
+                                ));
+                                framebuffer.dirty = false;
+                            }
+                        }
+                        if let Some(screen) = &self.screen {
+                            ui.image(screen, [640.0, 480.0]);
+                        }
+                        ui.separator();
+                        ui.heading("Port Displays");
+                        // Laid out like the circuit, three displays per row
+                        for row in PIXEL_DISPLAY_DECODES.chunks(3) {
+                            ui.horizontal(|ui| {
+                                for &decode in row {
+                                    if let Some(display) = cpu.port().device::<PixelDisplay>(decode)
+                                    {
+                                        let texture = texture_from_u32_array(
+                                            ctx,
+                                            &display.pixels,
+                                            0,
+                                            display.width as u32,
+                                            display.height as u32,
+                                        );
+                                        ui.image(&texture, [200.0, 200.0]);
+                                    }
+                                }
+                            });
+                        }
+                        ui.separator();
+                    });
                 });
             });
 
@@ -239,13 +491,42 @@ impl eframe::App for GUI {
                     });
                 }
                 ui.separator();
-                if error_pcob.load(Ordering::SeqCst) {
+                if let Some(error) = *error_fault {
+                    ui.label(format!("Error: {}", error));
+                    ui.horizontal(|ui| {
+                        if let Some(pc) = stopped_at(&error) {
+                            if ui.button("Show").clicked() {
+                                self.scroll_to = Some(pc);
+                            }
+                        }
+                        if error.instruction_fault().is_none() {
+                            // Uninitialised reads are reported after the
+                            // instruction ran, there is nothing to recover
+                            if ui.button("Continue").clicked() {
+                                *error_fault = None;
+                            }
+                            return;
+                        }
+                        if ui.button("Skip").clicked() {
+                            cpu.recover(&error, FaultAction::Skip);
+                            *error_fault = None;
+                        }
+                        if ui.button("Trap").clicked() {
+                            cpu.recover(&error, FaultAction::Trap);
+                            *error_fault = None;
+                        }
+                    });
+                } else if error_pcob.load(Ordering::SeqCst) {
                     ui.label("Error: Program Count Out of Bound");
                 } else if error_hlt.load(Ordering::SeqCst) {
                     ui.label("Error: CPU Halted");
                 } else {
                     ui.label("Error: None");
                 }
+                ui.separator();
+                ui.collapsing("Breakpoints", |ui| {
+                    breakpoint_panel(ui, cpu, &mut self.breakpoint_form);
+                });
             });
 
         // Central panel for additional information or logs if needed
@@ -255,6 +536,7 @@ impl eframe::App for GUI {
 
             // Instruction Execution
             ui.label(format!("Current Instruction: 0x{:08x}", cpu.ir));
+            ui.label(format!("Disassembly: {}", disassemble_line(cpu.ir)));
             ui.label(format!("Opcode: 0x{:02x}", cpu.opcode));
             ui.label(format!("DR: {}", cpu.dr));
             ui.label(format!("SR2: {}", cpu.sr2));
@@ -263,6 +545,13 @@ impl eframe::App for GUI {
 
             ui.separator();
 
+            // Output Port
+            ui.label(format!("Port Decode: {}", cpu.port().decode));
+            ui.label(format!("Port D1: 0x{:08x}", cpu.port().d1));
+            ui.label(format!("Port D2: 0x{:08x}", cpu.port().d2));
+
+            ui.separator();
+
             // CPU State
             ui.label(format!("Cycle Count: {}", cpu.clock));
             ui.label(format!(
@@ -274,6 +563,33 @@ impl eframe::App for GUI {
             ui.label(format!("Zero Flag: {}", cpu.registers.zero_f));
             ui.label(format!("Carry Flag: {}", cpu.registers.carry_f));
             ui.label(format!("Compare Flag: {}", cpu.registers.comp_f));
+            ui.label(format!("Overflow Flag: {}", cpu.registers.overflow_f));
+            ui.label(format!("Negative Flag: {}", cpu.registers.negative_f));
+
+            ui.separator();
+
+            // Interrupts
+            ui.label(format!(
+                "Interrupts Enabled: {}",
+                cpu.registers.interrupt_enable
+            ));
+            ui.label(format!(
+                "Interrupt Pending: 0b{:08b}",
+                cpu.interrupts().pending
+            ));
+            ui.label(format!("Interrupt Mask: 0b{:08b}", cpu.interrupts().mask));
+            ui.label(format!("MPU Enabled: {}", cpu.mpu().enabled));
+            ui.label(format!(
+                "Timer: {} (Enabled: {}, Periodic: {})",
+                cpu.timer().counter,
+                cpu.timer().enabled,
+                cpu.timer().periodic
+            ));
+            ui.label(format!(
+                "Paging Enabled: {} (Page Table: 0x{:06x})",
+                cpu.mmu().enabled,
+                cpu.mmu().page_table
+            ));
 
             ui.separator();
 
@@ -292,29 +608,23 @@ impl eframe::App for GUI {
                     egui::ScrollArea::vertical()
                         .id_source("stack")
                         .min_scrolled_height(a_h)
-                        .show_rows(
-                            ui,
-                            height,
-                            cpu.ram[0xFFF400..=0xFFFFFF].len(),
-                            |ui, row_range| {
-                                for i in row_range {
-                                    let Some(value) = cpu.ram.get(0xFFF400 + i) else {
-                                        continue;
-                                    };
-                                    ui.label(format!("SP-{:03x}: 0x{:08x}", i, value));
-                                }
-                            },
-                        );
+                        .show_rows(ui, height, ADDRESS_SPACE - 0xFFF400, |ui, row_range| {
+                            for i in row_range {
+                                let value = cpu.bus.peek(0xFFF400 + i);
+                                ui.label(format!("SP-{:03x}: 0x{:08x}", i, value));
+                            }
+                        });
                 });
                 ui.separator();
                 // RAM
                 ui.vertical(|ui| {
                     ui.label("RAM Contents:");
-                    let mut search: Option<u32> = None;
+                    let mut search: Option<u32> = self.scroll_to.take().map(|pc| pc as u32);
                     if ui.text_edit_singleline(&mut self.search_ram).changed() {
                         search = u32::from_str_radix(&self.search_ram, 16).ok();
                         println!("Search: {:?}", self.search_ram);
                     }
+                    let highlight = error_fault.as_ref().and_then(stopped_at);
                     let mut scroll_area = egui::ScrollArea::vertical();
                     let height = egui::TextStyle::Body.resolve(ui.style()).size;
                     if let Some(index) = search {
@@ -322,13 +632,19 @@ impl eframe::App for GUI {
                         println!("Offset: {}", offset);
                         scroll_area = scroll_area.vertical_scroll_offset(offset);
                     }
-                    scroll_area.show_rows(ui, height, cpu.ram.len(), |ui, row_range| {
+                    scroll_area.show_rows(ui, height, ADDRESS_SPACE, |ui, row_range| {
                         ui.allocate_space([ui.available_width(), 0.0].into());
                         for i in row_range {
-                            let Some(value) = cpu.ram.get(i) else {
-                                continue;
-                            };
-                            ui.label(format!("{i:06x}: {value:08x}"));
+                            let value = cpu.bus.peek(i);
+                            let row = egui::RichText::new(format!(
+                                "{i:06x}: {value:08x}  {}",
+                                disassemble_line(value)
+                            ));
+                            if highlight == Some(i) {
+                                ui.label(row.background_color(egui::Color32::DARK_RED));
+                            } else {
+                                ui.label(row);
+                            }
                         }
                     })
                 });
@@ -342,46 +658,271 @@ impl eframe::App for GUI {
     }
 }
 
-fn main() -> Result<(), eframe::Error> {
-    let args: Vec<String> = env::args().collect();
-    println!("Args: {:?}", args);
+/// The instruction a fault, breakpoint or watchpoint stopped on.
+fn stopped_at(error: &CPUError) -> Option<usize> {
+    match error {
+        CPUError::Breakpoint(pc) => Some(*pc),
+        CPUError::Watchpoint(hit) => Some(hit.pc),
+        CPUError::UninitializedRead(read) => Some(read.pc),
+        _ => error.instruction_fault().map(|fault| fault.pc),
+    }
+}
+
+fn parse_address(text: &str) -> Option<usize> {
+    let text = text.trim();
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    return usize::from_str_radix(digits, 16).ok();
+}
+
+/// Lists the breakpoints and watchpoints with their enable toggles and
+/// lets new ones be added.
+fn breakpoint_panel(ui: &mut egui::Ui, cpu: &mut CPU, form: &mut BreakpointForm) {
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut form.breakpoint)
+                .hint_text("PC")
+                .desired_width(60.0),
+        );
+        if ui.button("Add Breakpoint").clicked() {
+            if let Some(address) = parse_address(&form.breakpoint) {
+                cpu.debugger.add_breakpoint(address);
+                form.breakpoint.clear();
+            }
+        }
+    });
+    let mut remove = None;
+    for breakpoint in cpu.debugger.breakpoints.iter_mut() {
+        ui.horizontal(|ui| {
+            ui.checkbox(
+                &mut breakpoint.enabled,
+                format!("PC 0x{:06x}", breakpoint.address),
+            );
+            if ui.button("Remove").clicked() {
+                remove = Some(breakpoint.address);
+            }
+        });
+    }
+    if let Some(address) = remove {
+        cpu.debugger.remove_breakpoint(address);
+    }
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.add(
+            egui::TextEdit::singleline(&mut form.watch_start)
+                .hint_text("start")
+                .desired_width(60.0),
+        );
+        ui.add(
+            egui::TextEdit::singleline(&mut form.watch_end)
+                .hint_text("end")
+                .desired_width(60.0),
+        );
+    });
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("watch_kind")
+            .selected_text(form.watch_kind.to_string())
+            .show_ui(ui, |ui| {
+                for kind in [
+                    WatchKind::Read,
+                    WatchKind::Write,
+                    WatchKind::Access,
+                    WatchKind::Change,
+                ] {
+                    ui.selectable_value(&mut form.watch_kind, kind, kind.to_string());
+                }
+            });
+        if ui.button("Add Watchpoint").clicked() {
+            if let Some(start) = parse_address(&form.watch_start) {
+                // A single word unless an end is given
+                let end = parse_address(&form.watch_end).unwrap_or(start);
+                cpu.debugger
+                    .add_watchpoint(start, end.max(start), form.watch_kind);
+                form.watch_start.clear();
+                form.watch_end.clear();
+            }
+        }
+    });
+    let mut remove = None;
+    for (index, watchpoint) in cpu.debugger.watchpoints.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.checkbox(
+                &mut watchpoint.enabled,
+                format!(
+                    "{} 0x{:06x}-0x{:06x}",
+                    watchpoint.kind, watchpoint.start, watchpoint.end
+                ),
+            );
+            if ui.button("Remove").clicked() {
+                remove = Some(index);
+            }
+        });
+    }
+    if let Some(index) = remove {
+        cpu.debugger.remove_watchpoint(index);
+    }
+}
+
+fn assemble_or_exit(path: &Path) -> Vec<u32> {
+    match assemble_file(path) {
+        Ok(words) => return words,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(headless::EXIT_USAGE);
+        }
+    }
+}
+
+fn assemble_object_or_exit(path: &Path) -> Object {
+    let mut assembler = Assembler::new();
+    let object = assembler
+        .add_file(path)
+        .and_then(|()| assembler.assemble_object());
+    match object {
+        Ok(object) => return object,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            std::process::exit(headless::EXIT_USAGE);
+        }
+    }
+}
+
+/// Reads an input of `link`: `.asm` sources are assembled as objects.
+fn read_object(path: &Path) -> Object {
+    if path.extension().is_some_and(|extension| extension == "asm") {
+        return assemble_object_or_exit(path);
+    }
+    match Object::read_file(path) {
+        Ok(object) => return object,
+        Err(error) => {
+            eprintln!("Error: {}: {}", path.display(), error);
+            std::process::exit(headless::EXIT_USAGE);
+        }
+    }
+}
 
-    let mut initial_ram_content: Vec<u32> = Vec::new();
+/// Writes `text` to `path`, or to standard output without one.
+fn write_output(path: Option<&str>, text: &str) {
+    let Some(path) = path else {
+        print!("{}", text);
+        return;
+    };
+    if let Err(error) = fs::write(path, text) {
+        eprintln!("Error: Could not write {}: {}", path, error);
+        std::process::exit(headless::EXIT_USAGE);
+    }
+}
 
-    if args.len() > 1 {
-        let input_path = Path::new(&args[1]); // Replace with your file path
-        let file = File::open(&input_path).unwrap();
-        let reader = io::BufReader::new(file);
+fn link_or_exit(options: &cli::Options) {
+    let objects: Vec<(String, Object)> = options
+        .inputs
+        .iter()
+        .map(|input| (input.clone(), read_object(Path::new(input))))
+        .collect();
+    let script = match &options.script {
+        Some(path) => LinkerScript::read_file(Path::new(path)),
+        None => Ok(LinkerScript::default()),
+    };
+    let script = script.unwrap_or_else(|error| {
+        eprintln!("Error: {}", error);
+        std::process::exit(headless::EXIT_USAGE);
+    });
+    let program = link(&objects, &script).unwrap_or_else(|error| {
+        eprintln!("Error: {}", error);
+        std::process::exit(headless::EXIT_USAGE);
+    });
+    write_output(
+        options.output.as_deref(),
+        &assembler::to_hex(&program.image),
+    );
+    if let Some(map) = &options.map {
+        write_output(Some(map), &program.symbol_map());
+    }
+}
 
-        for line in reader.lines() {
-            let line = line.unwrap();
-            let number = u32::from_str_radix(&line, 16).expect("Failed to parse hex string");
-            initial_ram_content.push(number);
+/// Assembles `.asm` sources, anything else is loaded as an image in
+/// `format`, or the one it looks like.
+fn load_program(path: &Path, format: Option<Format>) -> Vec<u32> {
+    if format.is_none() && path.extension().is_some_and(|extension| extension == "asm") {
+        return assemble_or_exit(path);
+    }
+    match loader::load_file(path, format) {
+        Ok(words) => return words,
+        Err(error) => {
+            eprintln!("Error: {}: {}", path.display(), error);
+            std::process::exit(headless::EXIT_USAGE);
         }
     }
+}
 
-    let mut log: bool = false;
-    let log_file_path = if args.len() > 2 && args[2] == "-L" {
-        if args.len() < 4 {
-            eprintln!("Error: Log file path not provided");
-            std::process::exit(1);
+fn main() -> Result<(), eframe::Error> {
+    let args: Vec<String> = env::args().collect();
+
+    let options = match cli::parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            std::process::exit(headless::EXIT_USAGE);
         }
-        log = true;
-        Some(&args[3])
-    } else {
-        None
     };
 
-    if log {
+    if options.command == Command::Assemble {
+        let path = Path::new(options.program.as_deref().expect("asm requires a program"));
+        let text = if options.object {
+            assemble_object_or_exit(path).to_text()
+        } else {
+            assembler::to_hex(&assemble_or_exit(path))
+        };
+        write_output(options.output.as_deref(), &text);
+        return Ok(());
+    }
+    if options.command == Command::Link {
+        link_or_exit(&options);
+        return Ok(());
+    }
+    let initial_ram_content = match &options.program {
+        Some(path) => load_program(Path::new(path), options.format),
+        None => Vec::new(),
+    };
+    if options.command == Command::Disassemble {
+        for (address, word) in initial_ram_content.iter().enumerate() {
+            println!("{:06x}: {:08x}  {}", address, word, disassemble_line(*word));
+        }
+        return Ok(());
+    }
+
+    let log = options.log_file.is_some();
+    if let Some(log_file_path) = &options.log_file {
         fast_log::init(
             fast_log::Config::new()
-                .file(log_file_path.unwrap())
+                .file(log_file_path)
                 .chan_len(Some(10)),
         )
         .unwrap();
     }
 
-    let cpu = cpu::CPU::new(initial_ram_content, log, 1.0);
+    if let RamInit::Seeded(seed) = options.ram_init {
+        println!("RAM seed: {}", seed);
+    }
+    let mut cpu = CPU::with_ram_init(initial_ram_content, options.ram_init, log);
+    if options.check_uninitialized {
+        cpu.ram_mut().set_shadow_at_power_on();
+    }
+    if let Some(path) = &options.load_state {
+        if let Err(error) = cpu.load_state_file(Path::new(path)) {
+            eprintln!("Error: Could not load state {}: {}", path, error);
+            std::process::exit(headless::EXIT_USAGE);
+        }
+    }
+    if let Some(address) = &options.gdb {
+        std::process::exit(gdb::serve(&mut cpu, address));
+    }
+    if options.headless {
+        if options.program.is_none() && options.load_state.is_none() {
+            eprintln!("Error: --headless requires a program or --load-state");
+            std::process::exit(headless::EXIT_USAGE);
+        }
+        std::process::exit(headless::run(&mut cpu, &options));
+    }
     // self.cpu.execute_instruction(false, 0);
     return eframe::run_native(
         "SS32",
@@ -28,25 +28,3 @@ loop:
     JMP loop
 offset:
 #d 0x00000000
-
-00000000
-62000013
-64000001
-65000018
-a0000280
-50200000
-11410000
-12420000
-50230000
-1353b000
-a1000000
-a0000100
-50200000
-11410000
-12420000
-a1000000
-a0000100
-13431000
-3d00000b
-38000004
-00000000
\ No newline at end of file
@@ -4,9 +4,11 @@
 ; R0: pix POS
 ; R4: pix data
 
+#const FRAMEBUFFER = 0xFB5000
+
 LDI R1 - offset
 LDI R2 - 1
-LDI R3 - 0x00fb4fff
+LDI R3 - FRAMEBUFFER - 1 ; R0 is incremented before the first pixel
 OPW-En - 1
 loop:
     ADD R0 - R0, R2
@@ -18,16 +20,3 @@ loop:
 HLT
 offset:
 ; Writing an Image data in the format of 0x00RRGGBB
-
-00000000
-6100000b
-62000001
-63fb4fff
-a0000280
-10200000
-10350000
-11210000
-50140000
-84500000
-38000004
-ffffffff
\ No newline at end of file
@@ -9,14 +9,3 @@ loop:
     OPD2W - R1
     JMP R3
 HLT
-
-00000000
-62000001
-a0000280
-63000003
-10010000
-10200000
-a0000000
-a1000100
-30300000
-ffffffff
\ No newline at end of file
@@ -12,16 +12,3 @@ loop:
     CMP-EQ R0, R2
     JP-NCMP loop
 HLT
-
-00000000
-6100000b
-62000001
-63fb4fff
-a0000280
-10200000
-10350000
-11210000
-50140000
-84500000
-38000004
-ffffffff
\ No newline at end of file